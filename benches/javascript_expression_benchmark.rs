@@ -23,5 +23,27 @@ pub fn simple_expression_benchmark(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, simple_expression_benchmark);
+//the same expression in the native language, for comparison
+pub fn simple_language_benchmark(c: &mut Criterion) {
+    let expr = LanguageParser::default()
+        .parse(
+            &NodeId::new("bench"),
+            &Expression {
+                language: String::from("simple"),
+                expression: String::from("input + 5"),
+            },
+            &CompilationVarContext::default(),
+        )
+        .unwrap();
+
+    c.bench_function("simple input + 5", |b| {
+        b.iter(|| expr.execute(&VarContext::default_context_for_value(black_box(json!(10)))))
+    });
+}
+
+criterion_group!(
+    benches,
+    simple_expression_benchmark,
+    simple_language_benchmark
+);
 criterion_main!(benches);
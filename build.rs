@@ -1,9 +1,17 @@
 use std::io::prelude::*;
-use std::{error::Error, fs::File};
+use std::path::{Path, PathBuf};
+use std::{env, error::Error, fs, fs::File};
 
 use deno_core::{JsRuntime, RuntimeOptions};
 
+//helpers shipped with the project
+const HELPERS_DIR: &str = "js/helpers";
+//directory with additional, user-provided helpers
+const CUSTOM_HELPERS_ENV: &str = "JS_HELPERS_DIR";
+
 fn main() {
+    println!("cargo:rerun-if-changed={}", HELPERS_DIR);
+    println!("cargo:rerun-if-env-changed={}", CUSTOM_HELPERS_ENV);
     create_snapshot().unwrap();
 }
 
@@ -14,8 +22,28 @@ fn create_snapshot() -> Result<(), Box<dyn Error>> {
     };
     let mut runtime = JsRuntime::new(options);
 
+    let mut helpers = helper_files(Path::new(HELPERS_DIR))?;
+    if let Ok(custom_dir) = env::var(CUSTOM_HELPERS_ENV) {
+        println!("cargo:rerun-if-changed={}", custom_dir);
+        helpers.extend(helper_files(Path::new(&custom_dir))?);
+    }
+    for helper in helpers {
+        let source = fs::read_to_string(&helper)?;
+        runtime.execute_script(&helper.to_string_lossy(), &source)?;
+    }
+
     let mut snap = File::create("snapshots/query_runtime.snap")?;
     snap.write_all(&runtime.snapshot())?;
 
     Ok(())
 }
+
+//files are loaded in alphabetical order
+fn helper_files(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()?;
+    files.retain(|file| matches!(file.extension().and_then(|ext| ext.to_str()), Some("js")));
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,3 @@
+// All helpers are available in expressions in `helpers` namespace, e.g. helpers.padLeft(input.id, 10).
+// Additional helper files (see JS_HELPERS_DIR) should add their functions to the same object.
+globalThis.helpers = {};
@@ -0,0 +1,25 @@
+// Dates are passed as epoch millis, as there is no date type in JSON.
+
+// Parses ISO-8601 date (e.g. '2022-12-01' or '2022-12-01T10:15:00Z'), returns epoch millis, or null if text is not a valid date
+helpers.parseDate = (text) => {
+    const millis = Date.parse(text);
+    return Number.isNaN(millis) ? null : millis;
+};
+
+// Formats epoch millis (in UTC) using yyyy, MM, dd, HH, mm, ss patterns, e.g. helpers.formatDate(input.time, 'dd.MM.yyyy')
+helpers.formatDate = (millis, pattern) => {
+    const date = new Date(millis);
+    const pad = (value, length) => String(value).padStart(length, '0');
+    const parts = {
+        yyyy: pad(date.getUTCFullYear(), 4),
+        MM: pad(date.getUTCMonth() + 1, 2),
+        dd: pad(date.getUTCDate(), 2),
+        HH: pad(date.getUTCHours(), 2),
+        mm: pad(date.getUTCMinutes(), 2),
+        ss: pad(date.getUTCSeconds(), 2),
+    };
+    return pattern.replace(/yyyy|MM|dd|HH|mm|ss/g, (part) => parts[part]);
+};
+
+// Number of full days between two epoch millis
+helpers.daysBetween = (from, to) => Math.floor((to - from) / (24 * 60 * 60 * 1000));
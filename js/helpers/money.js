@@ -0,0 +1,5 @@
+// Rounds half away from zero, avoiding binary representation problems (e.g. 1.005 is rounded to 1.01)
+helpers.roundMoney = (amount, decimals = 2) => {
+    const factor = Math.pow(10, decimals);
+    return Math.sign(amount) * Math.round(Math.abs(amount) * factor + Number.EPSILON * factor) / factor;
+};
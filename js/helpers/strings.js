@@ -0,0 +1,10 @@
+helpers.padLeft = (value, length, fill = '0') => String(value).padStart(length, fill);
+
+helpers.padRight = (value, length, fill = ' ') => String(value).padEnd(length, fill);
+
+helpers.isBlank = (value) => value === null || value === undefined || String(value).trim() === '';
+
+helpers.capitalize = (value) => {
+    const text = String(value);
+    return text.charAt(0).toUpperCase() + text.slice(1);
+};
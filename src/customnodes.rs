@@ -1,45 +1,115 @@
-use crate::interpreter::{
-    data::{ScenarioOutput, ScenarioRuntimeError, VarContext, VarValue},
-    CustomNode, Interpreter,
+use crate::{
+    expression::ResultType,
+    interpreter::{
+        data::{ScenarioOutput, ScenarioRuntimeError, VarContext, VarValue},
+        workers::WorkerPool,
+        CustomNode, Interpreter, LazyParameters, ParameterDefinition,
+    },
+    scenariomodel::NodeId,
 };
 use async_trait::async_trait;
-use futures::future::join_all;
+use futures::{stream, FutureExt, StreamExt};
 use serde_json::Value::{self, Array};
-use std::{collections::HashMap, error::Error, fmt::Display};
-pub struct ForEach;
+use std::{collections::HashMap, error::Error, fmt::Display, sync::Arc};
+
+pub use self::aggregate::{AggregateError, Window, WindowAggregate};
+pub use self::collect::{Aggregator, Collect, CollectError};
+pub use self::deduplicate::Deduplicate;
+pub use self::lookup::{Lookup, LookupError};
+pub use self::ratelimit::{LimitAction, RateLimit};
+
+mod aggregate;
+mod collect;
+mod deduplicate;
+mod lookup;
+mod ratelimit;
+
+#[derive(Default)]
+pub struct ForEach {
+    workers: Option<Arc<WorkerPool>>,
+}
+
+impl ForEach {
+    ///Elements are processed by the workers, instead of the invoking thread
+    pub fn with_workers(workers: Arc<WorkerPool>) -> ForEach {
+        ForEach {
+            workers: Some(workers),
+        }
+    }
+}
 
 const VALUE_PARAM: &str = "value";
+const PARALLELISM_PARAM: &str = "parallelism";
+const ORDERED_PARAM: &str = "ordered";
+const MAX_ELEMENTS_PARAM: &str = "maxElements";
+
+const DEFAULT_PARALLELISM: u64 = 16;
 
 ///The components requires "value" parameter of array type.
 ///For each element of array, the subsequent part of the scenario is invoked, with the element passed as an output variable.
 ///This is the implementation of: https://nussknacker.io/documentation/docs/scenarios_authoring/BasicNodes#foreach
+///Optional parameters:
+///- parallelism - how many elements are processed concurrently, 16 by default
+///- ordered - if outputs are in the order of elements (default), or in order of completion
+///- maxElements - if the list is longer, the node fails
 #[async_trait]
 impl CustomNode for ForEach {
     async fn run(
         &self,
-        output_var: &str,
+        _node_id: &NodeId,
+        output_var: Option<&str>,
         parameters: &HashMap<String, VarValue>,
+        _lazy_parameters: &LazyParameters,
         data: &VarContext,
-        next_part: &dyn Interpreter,
+        next_part: &Arc<dyn Interpreter>,
     ) -> Result<ScenarioOutput, ScenarioRuntimeError> {
-        match parameters.get(VALUE_PARAM) {
-            Some(Array(values)) => {
-                let new_values: Vec<VarContext> = values
-                    .iter()
-                    .map(|v| data.with_new_var(output_var, v.clone()))
-                    .collect();
-                let output_result: Result<Vec<ScenarioOutput>, ScenarioRuntimeError> =
-                    join_all(new_values.iter().map(|k| next_part.run(k)))
-                        .await
-                        .into_iter()
-                        .collect();
-                output_result.map(ScenarioOutput::flatten)
-            }
-            Some(other) => Err(ScenarioRuntimeError::from(ForEachError::WrongValueType(
-                other.clone(),
-            ))),
-            None => Err(ScenarioRuntimeError::from(ForEachError::NoValueParam)),
-        }
+        let parameters = Parameters(parameters);
+        let values = parameters.elements()?;
+        let parallelism = parameters
+            .optional_positive(PARALLELISM_PARAM)?
+            .unwrap_or(DEFAULT_PARALLELISM) as usize;
+        let ordered = match parameters.get(ORDERED_PARAM) {
+            Value::Null => true,
+            Value::Bool(ordered) => ordered,
+            other => return Err(WrongParameter(ORDERED_PARAM, other).into()),
+        };
+
+        let new_values: Vec<VarContext> = values
+            .iter()
+            .map(|v| data.with_output_var(output_var, v.clone()))
+            .collect();
+        //collected first, lazy map adapter inside the stream confuses Send checks of async_trait
+        let invocations: Vec<_> = match &self.workers {
+            Some(workers) => new_values
+                .into_iter()
+                .map(|k| workers.run(next_part.clone(), k).boxed())
+                .collect(),
+            None => new_values.iter().map(|k| next_part.run(k)).collect(),
+        };
+        let invocations = stream::iter(invocations);
+        let outputs: Vec<Result<ScenarioOutput, ScenarioRuntimeError>> = if ordered {
+            invocations.buffered(parallelism).collect().await
+        } else {
+            invocations.buffer_unordered(parallelism).collect().await
+        };
+        let output_result: Result<Vec<ScenarioOutput>, ScenarioRuntimeError> =
+            outputs.into_iter().collect();
+        output_result.map(ScenarioOutput::flatten)
+    }
+
+    fn parameters(&self) -> Option<Vec<ParameterDefinition>> {
+        Some(vec![
+            ParameterDefinition::required(VALUE_PARAM).with_type(ResultType::List),
+            ParameterDefinition::optional(PARALLELISM_PARAM, None)
+                .with_type(ResultType::Number)
+                .literal(),
+            ParameterDefinition::optional(ORDERED_PARAM, None)
+                .with_type(ResultType::Boolean)
+                .literal(),
+            ParameterDefinition::optional(MAX_ELEMENTS_PARAM, None)
+                .with_type(ResultType::Number)
+                .literal(),
+        ])
     }
 }
 
@@ -47,6 +117,7 @@ impl CustomNode for ForEach {
 pub enum ForEachError {
     WrongValueType(Value),
     NoValueParam,
+    TooManyElements { count: usize, max: u64 },
 }
 
 impl From<ForEachError> for ScenarioRuntimeError {
@@ -62,15 +133,101 @@ impl Display for ForEachError {
             Self::WrongValueType(other) => {
                 write!(f, "Parameter 'value' is of wrong type {}", other)
             }
+            Self::TooManyElements { count, max } => {
+                write!(
+                    f,
+                    "List has {} elements, at most {} are allowed",
+                    count, max
+                )
+            }
         }
     }
 }
 impl Error for ForEachError {}
 
+///Parameter has a value which the node cannot use, e.g. zero length of a window
+#[derive(Debug, PartialEq, Eq)]
+pub struct WrongParameter(pub &'static str, pub Value);
+
+impl From<WrongParameter> for ScenarioRuntimeError {
+    fn from(error: WrongParameter) -> Self {
+        ScenarioRuntimeError::CustomNodeError(Box::new(error))
+    }
+}
+
+impl Display for WrongParameter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Parameter '{}' has invalid value {}", self.0, self.1)
+    }
+}
+impl Error for WrongParameter {}
+
+//values of evaluated parameters, the missing ones are null
+struct Parameters<'a>(&'a HashMap<String, VarValue>);
+
+impl<'a> Parameters<'a> {
+    fn get(&self, name: &str) -> Value {
+        self.0.get(name).cloned().unwrap_or_default()
+    }
+
+    fn positive(&self, name: &'static str) -> Result<u64, WrongParameter> {
+        match self.get(name).as_u64() {
+            Some(value) if value > 0 => Ok(value),
+            _ => Err(WrongParameter(name, self.get(name))),
+        }
+    }
+
+    fn optional_positive(&self, name: &'static str) -> Result<Option<u64>, WrongParameter> {
+        match self.get(name) {
+            Value::Null => Ok(None),
+            _ => self.positive(name).map(Some),
+        }
+    }
+
+    //e.g. timestamps
+    fn optional_u64(&self, name: &'static str) -> Result<Option<u64>, WrongParameter> {
+        match self.get(name) {
+            Value::Null => Ok(None),
+            other => other.as_u64().map(Some).ok_or(WrongParameter(name, other)),
+        }
+    }
+
+    fn key(&self, name: &str) -> String {
+        key_string(&self.get(name))
+    }
+
+    //list in value parameter of for-each like nodes, at most maxElements long
+    fn elements(&self) -> Result<&'a Vec<Value>, ScenarioRuntimeError> {
+        let values = match self.0.get(VALUE_PARAM) {
+            Some(Array(values)) => values,
+            Some(other) => return Err(ForEachError::WrongValueType(other.clone()).into()),
+            None => return Err(ForEachError::NoValueParam.into()),
+        };
+        if let Some(max) = self.optional_positive(MAX_ELEMENTS_PARAM)? {
+            if values.len() as u64 > max {
+                return Err(ForEachError::TooManyElements {
+                    count: values.len(),
+                    max,
+                }
+                .into());
+            }
+        }
+        Ok(values)
+    }
+}
+
+//keys of state and tables are strings, other values are taken as JSON
+fn key_string(value: &Value) -> String {
+    match value {
+        Value::String(key) => key.clone(),
+        other => other.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
-        customnodes::ForEachError,
+        customnodes::{ForEachError, WrongParameter},
         interpreter::{
             data::{
                 ScenarioOutput, ScenarioRuntimeError, SingleScenarioOutput, VarContext, VarValue,
@@ -84,7 +241,16 @@ mod tests {
     use std::collections::HashMap;
     use tokio_test::block_on;
 
-    use super::{ForEach, VALUE_PARAM};
+    use super::{ForEach, MAX_ELEMENTS_PARAM, ORDERED_PARAM, PARALLELISM_PARAM, VALUE_PARAM};
+    use std::{
+        future::Future,
+        pin::Pin,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll},
+    };
 
     const TEST_OUTPUT: &str = "test_output";
 
@@ -102,14 +268,20 @@ mod tests {
 
     #[test]
     fn test_arrays() -> Result<(), ScenarioRuntimeError> {
-        let foreach = ForEach {};
+        let foreach = ForEach::default();
         let output_var = "output";
-        let next_part = MockInterpreter {};
+        let next_part: Arc<dyn Interpreter> = Arc::new(MockInterpreter {});
 
         let check_for_value = |v: &[&VarValue]| -> Result<(), ScenarioRuntimeError> {
             let parameters = HashMap::from([(VALUE_PARAM.to_owned(), json!(v))]);
-            let result =
-                block_on(foreach.run(output_var, &parameters, &VarContext::empty(), &next_part))?;
+            let result = block_on(foreach.run(
+                &NodeId::new("forEach"),
+                Some(output_var),
+                &parameters,
+                &HashMap::new(),
+                &VarContext::empty(),
+                &next_part,
+            ))?;
             let values: Vec<&VarValue> = result
                 .var_in_sink(&NodeId::new(TEST_OUTPUT), output_var)
                 .iter()
@@ -127,20 +299,27 @@ mod tests {
 
     #[test]
     fn test_wrong_parameters() {
-        let foreach = ForEach {};
-        let next_part = MockInterpreter {};
+        let foreach = ForEach::default();
+        let next_part: Arc<dyn Interpreter> = Arc::new(MockInterpreter {});
         let output_var = "output";
 
-        let test_parameter = |params: &HashMap<String, VarValue>, expected_error: ForEachError| {
-            let result =
-                block_on(foreach.run(output_var, params, &VarContext::empty(), &next_part))
-                    .unwrap_err();
-            let error = match result {
-                ScenarioRuntimeError::CustomNodeError(error) => {
-                    error.downcast::<ForEachError>().unwrap()
-                }
+        let error_for = |params: &HashMap<String, VarValue>| {
+            let result = block_on(foreach.run(
+                &NodeId::new("forEach"),
+                Some(output_var),
+                params,
+                &HashMap::new(),
+                &VarContext::empty(),
+                &next_part,
+            ))
+            .unwrap_err();
+            match result {
+                ScenarioRuntimeError::CustomNodeError(error) => error,
                 other => panic!("Unexpected error {:?}", other),
-            };
+            }
+        };
+        let test_parameter = |params: &HashMap<String, VarValue>, expected_error: ForEachError| {
+            let error = error_for(params).downcast::<ForEachError>().unwrap();
             assert_eq!(*error, expected_error);
         };
         test_parameter(&HashMap::from([]), ForEachError::NoValueParam);
@@ -153,5 +332,90 @@ mod tests {
             &HashMap::from([(String::from(VALUE_PARAM), json!(""))]),
             ForEachError::WrongValueType(json!("")),
         );
+        test_parameter(
+            &HashMap::from([
+                (String::from(VALUE_PARAM), json!([1, 2, 3])),
+                (String::from(MAX_ELEMENTS_PARAM), json!(2)),
+            ]),
+            ForEachError::TooManyElements { count: 3, max: 2 },
+        );
+        let error = error_for(&HashMap::from([
+            (String::from(VALUE_PARAM), json!([])),
+            (String::from(PARALLELISM_PARAM), json!(0)),
+        ]));
+        assert_eq!(
+            *error.downcast::<WrongParameter>().unwrap(),
+            WrongParameter(PARALLELISM_PARAM, json!(0))
+        );
+    }
+
+    //returns Pending a given number of times, so that other invocations can start in the meantime
+    struct Yield(usize);
+
+    impl Future for Yield {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 == 0 {
+                return Poll::Ready(());
+            }
+            self.0 -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    //elements with greater value take longer
+    #[derive(Default)]
+    struct ConcurrencyTracking {
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Interpreter for ConcurrencyTracking {
+        async fn run(&self, data: &VarContext) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            let element = data.get("output").and_then(Value::as_u64).unwrap_or(0);
+            Yield(element as usize).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            MockInterpreter {}.run(data).await
+        }
+    }
+
+    #[test]
+    fn test_parallelism_and_ordering() -> Result<(), ScenarioRuntimeError> {
+        let run = |ordered: bool| -> Result<(Vec<Value>, usize), ScenarioRuntimeError> {
+            let tracking = Arc::new(ConcurrencyTracking::default());
+            let next_part: Arc<dyn Interpreter> = tracking.clone();
+            let parameters = HashMap::from([
+                (String::from(VALUE_PARAM), json!([5, 1, 4, 2, 3, 1])),
+                (String::from(PARALLELISM_PARAM), json!(2)),
+                (String::from(ORDERED_PARAM), json!(ordered)),
+            ]);
+            let result = block_on(ForEach::default().run(
+                &NodeId::new("forEach"),
+                Some("output"),
+                &parameters,
+                &HashMap::new(),
+                &VarContext::empty(),
+                &next_part,
+            ))?;
+            let values = result
+                .var_in_sink(&NodeId::new(TEST_OUTPUT), "output")
+                .into_iter()
+                .map(|value| value.cloned().unwrap_or_default())
+                .collect();
+            Ok((values, tracking.max_running.load(Ordering::SeqCst)))
+        };
+        let (values, max_running) = run(true)?;
+        assert_eq!(values, [5, 1, 4, 2, 3, 1]);
+        assert_eq!(max_running, 2);
+
+        let (values, max_running) = run(false)?;
+        assert_ne!(values, [5, 1, 4, 2, 3, 1]);
+        assert_eq!(max_running, 2);
+        Ok(())
     }
 }
@@ -0,0 +1,401 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::{
+    expression::ResultType,
+    interpreter::{
+        clock::Clock,
+        data::{ScenarioOutput, ScenarioRuntimeError, VarContext, VarValue},
+        state::StateStore,
+        CustomNode, Interpreter, LazyParameters, ParameterDefinition,
+    },
+    scenariomodel::NodeId,
+};
+
+use super::{collect::Aggregator, Parameters};
+
+const GROUP_BY_PARAM: &str = "groupBy";
+const AGGREGATOR_PARAM: &str = "aggregator";
+const AGGREGATE_BY_PARAM: &str = "aggregateBy";
+const WINDOW_LENGTH_PARAM: &str = "windowLength";
+const SESSION_TIMEOUT_PARAM: &str = "sessionTimeout";
+const TIMESTAMP_PARAM: &str = "timestamp";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    ///Consecutive windows of given length, e.g. [0, 60000), [60000, 120000)
+    Tumbling,
+    ///Window of given length, ending with the current event
+    Sliding,
+    ///Events closer to each other than session timeout
+    Session,
+}
+
+///Aggregates "aggregateBy" values of events with the same "groupBy" key in time windows. The rest of the scenario
+///is invoked for each event, with the aggregate of its window (including the event) in the output variable.
+///Lengths are in milliseconds, "timestamp" (milliseconds since epoch) is the time of processing if not given.
+///Aggregators: list, sum, min, max, count (of non-null values). Events are expected to arrive in order of timestamps,
+///they are kept in the state store, with node id as namespace. Keys without events in the current window are removed
+///once per window length
+pub struct WindowAggregate {
+    window: Window,
+    store: Arc<dyn StateStore>,
+    clock: Arc<dyn Clock>,
+    //time of the last eviction of idle keys, by namespace
+    evicted: Mutex<HashMap<String, u64>>,
+}
+
+impl WindowAggregate {
+    pub fn new(
+        window: Window,
+        store: Arc<dyn StateStore>,
+        clock: Arc<dyn Clock>,
+    ) -> WindowAggregate {
+        WindowAggregate {
+            window,
+            store,
+            clock,
+            evicted: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn length_param(&self) -> &'static str {
+        match self.window {
+            Window::Tumbling | Window::Sliding => WINDOW_LENGTH_PARAM,
+            Window::Session => SESSION_TIMEOUT_PARAM,
+        }
+    }
+
+    //tells if the event time is in the window of the event with given timestamp, sums are computed on u128 so that
+    //they don't overflow for large timestamps or lengths
+    fn in_window(&self, events: &[Value], timestamp: u64, length: u64) -> Box<dyn Fn(u64) -> bool> {
+        match self.window {
+            Window::Tumbling => {
+                let start = timestamp - timestamp % length;
+                Box::new(move |time| time >= start)
+            }
+            Window::Sliding => {
+                Box::new(move |time| time as u128 + length as u128 > timestamp as u128)
+            }
+            Window::Session => {
+                let last = events.iter().map(event_time).max();
+                let expired =
+                    matches!(last, Some(last) if timestamp as u128 > last as u128 + length as u128);
+                Box::new(move |_| !expired)
+            }
+        }
+    }
+
+    //events are [timestamp, value] pairs, the ones outside the window of the new event are removed
+    fn add_event(
+        &self,
+        events: Vec<Value>,
+        timestamp: u64,
+        value: Value,
+        length: u64,
+    ) -> Vec<Value> {
+        let in_window = self.in_window(&events, timestamp, length);
+        let mut events: Vec<Value> = events
+            .into_iter()
+            .filter(|event| in_window(event_time(event)))
+            .collect();
+        events.push(Value::from(vec![Value::from(timestamp), value]));
+        events
+    }
+
+    //keys which have no events in the window of the current one are removed, at most once per window length
+    fn evict_idle(&self, namespace: &str, timestamp: u64, length: u64) {
+        let mut evicted = self
+            .evicted
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let last = evicted.entry(String::from(namespace)).or_insert(timestamp);
+        if timestamp < last.saturating_add(length) {
+            return;
+        }
+        *last = timestamp;
+        drop(evicted);
+        self.store.retain(namespace, &mut |_, events| {
+            let events = events.as_array().map(Vec::as_slice).unwrap_or_default();
+            let in_window = self.in_window(events, timestamp, length);
+            events.iter().any(|event| in_window(event_time(event)))
+        });
+    }
+}
+
+fn event_time(event: &Value) -> u64 {
+    event.get(0).and_then(Value::as_u64).unwrap_or_default()
+}
+
+#[async_trait]
+impl CustomNode for WindowAggregate {
+    async fn run(
+        &self,
+        node_id: &NodeId,
+        output_var: Option<&str>,
+        parameters: &HashMap<String, VarValue>,
+        _lazy_parameters: &LazyParameters,
+        data: &VarContext,
+        next_part: &Arc<dyn Interpreter>,
+    ) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+        let parameters = Parameters(parameters);
+        let aggregator = aggregator(&parameters)?;
+        let length = parameters.positive(self.length_param())?;
+        let timestamp = match parameters.optional_u64(TIMESTAMP_PARAM)? {
+            Some(timestamp) => timestamp,
+            None => self.clock.now(),
+        };
+        let key = parameters.key(GROUP_BY_PARAM);
+        let value = parameters.get(AGGREGATE_BY_PARAM);
+
+        let namespace = node_id.to_string();
+        let events = self.store.update(&namespace, &key, &mut |events| {
+            let events = match events {
+                Some(Value::Array(events)) => events,
+                _ => vec![],
+            };
+            Some(Value::Array(self.add_event(
+                events,
+                timestamp,
+                value.clone(),
+                length,
+            )))
+        });
+        self.evict_idle(&namespace, timestamp, length);
+        let values = match events {
+            Some(Value::Array(events)) => events
+                .into_iter()
+                .map(|mut event| event.get_mut(1).map(Value::take).unwrap_or_default())
+                .collect(),
+            _ => vec![],
+        };
+        let result = aggregator.aggregate(values, vec![])?;
+        next_part
+            .run(&data.with_output_var(output_var, result))
+            .await
+    }
+
+    fn parameters(&self) -> Option<Vec<ParameterDefinition>> {
+        Some(vec![
+            ParameterDefinition::required(GROUP_BY_PARAM),
+            ParameterDefinition::required(AGGREGATOR_PARAM)
+                .with_type(ResultType::String)
+                .literal(),
+            ParameterDefinition::required(AGGREGATE_BY_PARAM),
+            ParameterDefinition::required(self.length_param())
+                .with_type(ResultType::Number)
+                .literal(),
+            ParameterDefinition::optional(TIMESTAMP_PARAM, None).with_type(ResultType::Number),
+        ])
+    }
+
+    //aggregator is literal, so it's known during compilation
+    fn validate(
+        &self,
+        _node_id: &NodeId,
+        parameters: &HashMap<String, VarValue>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        aggregator(&Parameters(parameters))?;
+        Ok(())
+    }
+}
+
+//map needs keys, which events don't have
+fn aggregator(parameters: &Parameters) -> Result<Aggregator, AggregateError> {
+    match parameters
+        .get(AGGREGATOR_PARAM)
+        .as_str()
+        .and_then(Aggregator::parse)
+    {
+        Some(Aggregator::Map) | None => Err(AggregateError::UnknownAggregator(
+            parameters.get(AGGREGATOR_PARAM),
+        )),
+        Some(aggregator) => Ok(aggregator),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AggregateError {
+    UnknownAggregator(Value),
+}
+
+impl From<AggregateError> for ScenarioRuntimeError {
+    fn from(error: AggregateError) -> Self {
+        ScenarioRuntimeError::CustomNodeError(Box::new(error))
+    }
+}
+
+impl Display for AggregateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownAggregator(other) => write!(f, "Unknown aggregator {}", other),
+        }
+    }
+}
+impl Error for AggregateError {}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::{json, Value};
+
+    use crate::{
+        customnodes::AggregateError,
+        interpreter::{
+            compiler::{
+                tests::{custom_node_scenario, parameter, single_output},
+                Compiler,
+            },
+            data::ScenarioCompilationError,
+            state::{InMemoryStateStore, StateStore},
+            Interpreter,
+        },
+        scenariomodel::NodeId,
+    };
+
+    fn aggregate(node_type: &str, length_param: &str, aggregator: &str) -> Box<dyn Interpreter> {
+        aggregate_in(
+            Arc::new(InMemoryStateStore::default()),
+            node_type,
+            length_param,
+            aggregator,
+        )
+    }
+
+    fn aggregate_in(
+        store: Arc<dyn StateStore>,
+        node_type: &str,
+        length_param: &str,
+        aggregator: &str,
+    ) -> Box<dyn Interpreter> {
+        let scenario = custom_node_scenario(
+            node_type,
+            Some("aggregated"),
+            vec![
+                parameter("groupBy", "input.user"),
+                parameter("aggregator", &format!("'{}'", aggregator)),
+                parameter("aggregateBy", "input.amount"),
+                parameter(length_param, "10"),
+                parameter("timestamp", "input.time"),
+            ],
+        );
+        Compiler::default()
+            .with_state_store(store)
+            .compile(&scenario)
+            .unwrap()
+    }
+
+    //events are (user, time, amount)
+    fn run(interpreter: &dyn Interpreter, events: &[(&str, u64, u64)]) -> Vec<Value> {
+        events
+            .iter()
+            .map(|(user, time, amount)| {
+                let input = json!({"user": user, "time": time, "amount": amount});
+                single_output(interpreter, input, "aggregated")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_windows() {
+        let events = [
+            ("a", 1, 1),
+            ("a", 5, 2),
+            ("b", 6, 10),
+            ("a", 12, 4),
+            ("a", 18, 8),
+            ("a", 30, 16),
+        ];
+        assert_eq!(
+            run(
+                aggregate("aggregateTumbling", "windowLength", "sum").as_ref(),
+                &events
+            ),
+            [1, 3, 10, 4, 12, 16]
+        );
+        assert_eq!(
+            run(
+                aggregate("aggregateSliding", "windowLength", "sum").as_ref(),
+                &events
+            ),
+            [1, 3, 10, 6, 12, 16]
+        );
+        assert_eq!(
+            run(
+                aggregate("aggregateSession", "sessionTimeout", "list").as_ref(),
+                &events
+            ),
+            [
+                json!([1]),
+                json!([1, 2]),
+                json!([10]),
+                json!([1, 2, 4]),
+                json!([1, 2, 4, 8]),
+                json!([16])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_idle_keys_evicted() {
+        let store = Arc::new(InMemoryStateStore::default());
+        let interpreter = aggregate_in(store.clone(), "aggregateSliding", "windowLength", "sum");
+        run(
+            interpreter.as_ref(),
+            &[("a", 1, 1), ("b", 2, 1), ("c", 5, 1)],
+        );
+        assert_eq!(store.count("aggregateSliding"), 3);
+        //"b" and "c" have no events in the window of the last event
+        run(interpreter.as_ref(), &[("a", 11, 1), ("a", 21, 1)]);
+        assert_eq!(store.count("aggregateSliding"), 1);
+    }
+
+    #[test]
+    fn test_large_timestamps() {
+        let events = [("a", u64::MAX - 1, 1), ("a", u64::MAX, 2)];
+        for (node_type, length_param) in [
+            ("aggregateSliding", "windowLength"),
+            ("aggregateSession", "sessionTimeout"),
+        ] {
+            assert_eq!(
+                run(aggregate(node_type, length_param, "sum").as_ref(), &events),
+                [1, 3]
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_aggregator() {
+        for aggregator in ["map", "median"] {
+            let scenario = custom_node_scenario(
+                "aggregateTumbling",
+                Some("aggregated"),
+                vec![
+                    parameter("groupBy", "input.user"),
+                    parameter("aggregator", &format!("'{}'", aggregator)),
+                    parameter("aggregateBy", "input.amount"),
+                    parameter("windowLength", "10"),
+                ],
+            );
+            let error = match Compiler::default().compile(&scenario) {
+                Err(ScenarioCompilationError::InvalidNode { node_id, error }) => {
+                    assert_eq!(node_id, NodeId::new("aggregateTumbling"));
+                    error
+                }
+                other => panic!("Unexpected result {:?}", other.err()),
+            };
+            assert_eq!(
+                *error.downcast::<AggregateError>().unwrap(),
+                AggregateError::UnknownAggregator(json!(aggregator))
+            );
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use std::{collections::HashMap, error::Error, fmt::Display, sync::Arc};
+
+use async_trait::async_trait;
+use serde_json::{Map, Number, Value};
+
+use crate::{
+    expression::ResultType,
+    interpreter::{
+        data::{ScenarioOutput, ScenarioRuntimeError, VarContext, VarValue},
+        CustomNode, Interpreter, LazyParameters, ParameterDefinition,
+    },
+    scenariomodel::NodeId,
+};
+
+use super::{Parameters, MAX_ELEMENTS_PARAM, VALUE_PARAM};
+
+pub struct Collect;
+
+const AGGREGATOR_PARAM: &str = "aggregator";
+const AGGREGATE_BY_PARAM: &str = "aggregateBy";
+const KEY_PARAM: &str = "key";
+const ELEMENT_VAR: &str = "element";
+
+///Aggregates elements of "value" list into single value, stored in output variable - the rest of the scenario
+///is invoked once. "aggregateBy" (and "key" for map aggregator) are evaluated for each element, which is available
+///as `element` variable - without aggregateBy, elements are aggregated as they are. As in forEach, "maxElements"
+///limits the length of the list.
+#[async_trait]
+impl CustomNode for Collect {
+    async fn run(
+        &self,
+        _node_id: &NodeId,
+        output_var: Option<&str>,
+        parameters: &HashMap<String, VarValue>,
+        lazy_parameters: &LazyParameters,
+        data: &VarContext,
+        next_part: &Arc<dyn Interpreter>,
+    ) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+        let parameters = Parameters(parameters);
+        let elements = parameters.elements()?;
+        //checked in validate
+        let aggregator = parameters
+            .get(AGGREGATOR_PARAM)
+            .as_str()
+            .and_then(Aggregator::parse)
+            .ok_or_else(|| CollectError::UnknownAggregator(parameters.get(AGGREGATOR_PARAM)))?;
+        let key = match (aggregator, lazy_parameters.get(KEY_PARAM)) {
+            (Aggregator::Map, None) => return Err(CollectError::NoKeyParam.into()),
+            (Aggregator::Map, key) => key,
+            _ => None,
+        };
+        let mut values = vec![];
+        let mut keys = vec![];
+        for element in elements {
+            let element_ctx = data.with_new_var(ELEMENT_VAR, element.clone());
+            values.push(match lazy_parameters.get(AGGREGATE_BY_PARAM) {
+                Some(aggregate_by) => aggregate_by.execute(&element_ctx)?,
+                None => element.clone(),
+            });
+            if let Some(key) = key {
+                keys.push(key.execute(&element_ctx)?);
+            }
+        }
+        let result = aggregator.aggregate(values, keys)?;
+        next_part
+            .run(&data.with_output_var(output_var, result))
+            .await
+    }
+
+    fn parameters(&self) -> Option<Vec<ParameterDefinition>> {
+        Some(vec![
+            ParameterDefinition::required(VALUE_PARAM).with_type(ResultType::List),
+            ParameterDefinition::required(AGGREGATOR_PARAM)
+                .with_type(ResultType::String)
+                .literal(),
+            ParameterDefinition::optional(AGGREGATE_BY_PARAM, None).lazy(&[ELEMENT_VAR]),
+            ParameterDefinition::optional(KEY_PARAM, None).lazy(&[ELEMENT_VAR]),
+            ParameterDefinition::optional(MAX_ELEMENTS_PARAM, None)
+                .with_type(ResultType::Number)
+                .literal(),
+        ])
+    }
+
+    fn validate(
+        &self,
+        _node_id: &NodeId,
+        parameters: &HashMap<String, VarValue>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let aggregator = parameters
+            .get(AGGREGATOR_PARAM)
+            .cloned()
+            .unwrap_or_default();
+        match aggregator.as_str().and_then(Aggregator::parse) {
+            Some(_) => Ok(()),
+            None => Err(Box::new(CollectError::UnknownAggregator(aggregator))),
+        }
+    }
+}
+
+///Aggregators used by collect node and windowed aggregates: list, map (by key, last value wins), sum, min, max,
+///count (of non-null values)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregator {
+    List,
+    Map,
+    Sum,
+    Min,
+    Max,
+    Count,
+}
+
+impl Aggregator {
+    pub fn parse(name: &str) -> Option<Aggregator> {
+        Some(match name {
+            "list" => Aggregator::List,
+            "map" => Aggregator::Map,
+            "sum" => Aggregator::Sum,
+            "min" => Aggregator::Min,
+            "max" => Aggregator::Max,
+            "count" => Aggregator::Count,
+            _ => return None,
+        })
+    }
+
+    ///min and max are null for no values, so their type is not known
+    pub fn output_type(&self) -> ResultType {
+        match self {
+            Aggregator::List => ResultType::List,
+            Aggregator::Map => ResultType::Map,
+            Aggregator::Sum | Aggregator::Count => ResultType::Number,
+            Aggregator::Min | Aggregator::Max => ResultType::Unknown,
+        }
+    }
+
+    //keys are given only for map
+    pub fn aggregate(&self, values: Vec<Value>, keys: Vec<Value>) -> Result<Value, CollectError> {
+        let numbers = || -> Result<Vec<&Number>, CollectError> {
+            values
+                .iter()
+                .filter(|value| !value.is_null())
+                .map(|value| match value {
+                    Value::Number(number) => Ok(number),
+                    other => Err(CollectError::NotNumber(other.clone())),
+                })
+                .collect()
+        };
+        Ok(match self {
+            Aggregator::List => Value::Array(values),
+            Aggregator::Map => {
+                let mut result = Map::new();
+                for (key, value) in keys.into_iter().zip(values) {
+                    let key = match key {
+                        Value::String(key) => key,
+                        Value::Number(key) => key.to_string(),
+                        other => return Err(CollectError::InvalidKey(other)),
+                    };
+                    result.insert(key, value);
+                }
+                Value::Object(result)
+            }
+            Aggregator::Sum => sum(&numbers()?),
+            Aggregator::Min | Aggregator::Max => {
+                let mut numbers = numbers()?.into_iter();
+                let first = numbers.next();
+                let chosen = numbers.fold(first, |current, number| {
+                    let current = current?;
+                    let (a, b) = (as_f64(number), as_f64(current));
+                    let replace = if *self == Aggregator::Min {
+                        a < b
+                    } else {
+                        a > b
+                    };
+                    Some(if replace { number } else { current })
+                });
+                chosen.cloned().map(Value::Number).unwrap_or_default()
+            }
+            Aggregator::Count => Value::from(values.iter().filter(|v| !v.is_null()).count()),
+        })
+    }
+}
+
+fn as_f64(number: &Number) -> f64 {
+    number.as_f64().unwrap_or_default()
+}
+
+//integers stay integers, unless they overflow
+fn sum(numbers: &[&Number]) -> Value {
+    let integers: Option<i64> = numbers
+        .iter()
+        .try_fold(0i64, |sum, number| sum.checked_add(number.as_i64()?));
+    match integers {
+        Some(sum) => Value::from(sum),
+        None => Value::from(numbers.iter().map(|number| as_f64(number)).sum::<f64>()),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CollectError {
+    UnknownAggregator(Value),
+    NoKeyParam,
+    InvalidKey(Value),
+    NotNumber(Value),
+}
+
+impl From<CollectError> for ScenarioRuntimeError {
+    fn from(error: CollectError) -> Self {
+        ScenarioRuntimeError::CustomNodeError(Box::new(error))
+    }
+}
+
+impl Display for CollectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownAggregator(other) => write!(f, "Unknown aggregator {}", other),
+            Self::NoKeyParam => write!(f, "Parameter 'key' is required by map aggregator"),
+            Self::InvalidKey(other) => write!(f, "Invalid key {}", other),
+            Self::NotNumber(other) => write!(f, "Cannot aggregate {}, number expected", other),
+        }
+    }
+}
+impl Error for CollectError {}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use tokio_test::block_on;
+
+    use crate::{
+        customnodes::{CollectError, ForEachError},
+        interpreter::{
+            compiler::{
+                tests::{custom_node_scenario, parameter, single_output},
+                Compiler,
+            },
+            data::{ScenarioCompilationError, ScenarioRuntimeError, VarContext},
+            Interpreter,
+        },
+        scenariomodel::Parameter,
+    };
+
+    fn collect_node(
+        parameters: Vec<Parameter>,
+    ) -> Result<Box<dyn Interpreter>, ScenarioCompilationError> {
+        let scenario = custom_node_scenario("collect", Some("collected"), parameters);
+        Compiler::default().compile(&scenario)
+    }
+
+    fn collect(aggregator: &str) -> Value {
+        let interpreter = collect_node(vec![
+            parameter("value", "input.items"),
+            parameter("aggregator", &format!("'{}'", aggregator)),
+            parameter("aggregateBy", "element.amount * 2"),
+            parameter("key", "upper(element.name)"),
+        ])
+        .unwrap();
+        let input = json!({"items": [
+            {"name": "a", "amount": 10},
+            {"name": "b", "amount": 3},
+            {"name": "c", "amount": 0}
+        ]});
+        single_output(interpreter.as_ref(), input, "collected")
+    }
+
+    #[test]
+    fn test_aggregators() {
+        assert_eq!(collect("list"), json!([20, 6, 0]));
+        assert_eq!(collect("map"), json!({"A": 20, "B": 6, "C": 0}));
+        assert_eq!(collect("sum"), json!(26));
+        assert_eq!(collect("min"), json!(0));
+        assert_eq!(collect("max"), json!(20));
+        assert_eq!(collect("count"), json!(3));
+
+        //without aggregateBy elements are taken as they are
+        let interpreter = collect_node(vec![
+            parameter("value", "input"),
+            parameter("aggregator", "'sum'"),
+        ])
+        .unwrap();
+        assert_eq!(
+            single_output(interpreter.as_ref(), json!([1, 2, 3]), "collected"),
+            json!(6)
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            collect_node(vec![
+                parameter("value", "input"),
+                parameter("aggregator", "'median'"),
+            ]),
+            Err(ScenarioCompilationError::InvalidNode { error, .. }) if error.to_string() == "Unknown aggregator \"median\""
+        ));
+
+        let error = |parameters: Vec<Parameter>, input: Value| {
+            let interpreter = collect_node(parameters).unwrap();
+            match block_on(interpreter.run(&VarContext::default_context_for_value(input))) {
+                Err(ScenarioRuntimeError::CustomNodeError(error)) => error,
+                other => panic!("Unexpected: {:?}", other),
+            }
+        };
+        let no_key = error(
+            vec![
+                parameter("value", "input"),
+                parameter("aggregator", "'map'"),
+            ],
+            json!([1]),
+        );
+        assert_eq!(
+            *no_key.downcast::<CollectError>().unwrap(),
+            CollectError::NoKeyParam
+        );
+        let too_many = error(
+            vec![
+                parameter("value", "input"),
+                parameter("aggregator", "'list'"),
+                parameter("maxElements", "2"),
+            ],
+            json!([1, 2, 3]),
+        );
+        assert_eq!(
+            *too_many.downcast::<ForEachError>().unwrap(),
+            ForEachError::TooManyElements { count: 3, max: 2 }
+        );
+    }
+}
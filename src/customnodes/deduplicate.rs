@@ -0,0 +1,246 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::{
+    expression::ResultType,
+    interpreter::{
+        clock::Clock,
+        data::{ScenarioOutput, ScenarioRuntimeError, VarContext, VarValue},
+        state::StateStore,
+        CustomNode, Interpreter, LazyParameters, ParameterDefinition,
+    },
+    scenariomodel::NodeId,
+};
+
+use super::Parameters;
+
+const KEY_PARAM: &str = "key";
+const TTL_PARAM: &str = "ttl";
+const MAX_ENTRIES_PARAM: &str = "maxEntries";
+const TIMESTAMP_PARAM: &str = "timestamp";
+
+const DEFAULT_MAX_ENTRIES: u64 = 100_000;
+
+///Invokes the rest of the scenario only for the first occurrence of "key" in "ttl" milliseconds, duplicates
+///produce no output. Keys are kept in the state store (with time of first occurrence), so with FileStateStore
+///deduplication survives restarts. If there are more than "maxEntries" keys, expired and then the oldest ones are removed.
+///"timestamp" (milliseconds since epoch) is the time of processing if not given
+pub struct Deduplicate {
+    store: Arc<dyn StateStore>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Deduplicate {
+    pub fn new(store: Arc<dyn StateStore>, clock: Arc<dyn Clock>) -> Deduplicate {
+        Deduplicate { store, clock }
+    }
+
+    //removes 10% more than needed, so that it doesn't happen on each invocation. Entries with the same time
+    //(e.g. in bursts) are removed in order of keys
+    fn evict(&self, namespace: &str, now: u64, ttl: u64, max_entries: usize) {
+        let first_seen = |value: &Value| value.as_u64().unwrap_or_default();
+        self.store.retain(namespace, &mut |_, value| {
+            seen_recently(first_seen(value), ttl, now)
+        });
+        let count = self.store.count(namespace);
+        if count <= max_entries {
+            return;
+        }
+        let mut entries: Vec<(u64, String)> = self
+            .store
+            .entries(namespace)
+            .into_iter()
+            .map(|(key, value)| (first_seen(&value), key))
+            .collect();
+        entries.sort_unstable();
+        let to_remove: HashSet<String> = entries
+            .into_iter()
+            .take(count - max_entries + max_entries / 10)
+            .map(|(_, key)| key)
+            .collect();
+        self.store
+            .retain(namespace, &mut |key, _| !to_remove.contains(key));
+    }
+}
+
+//computed on u128, so that it doesn't overflow for large ttl or timestamps
+fn seen_recently(first_seen: u64, ttl: u64, now: u64) -> bool {
+    first_seen as u128 + ttl as u128 > now as u128
+}
+
+#[async_trait]
+impl CustomNode for Deduplicate {
+    async fn run(
+        &self,
+        node_id: &NodeId,
+        _output_var: Option<&str>,
+        parameters: &HashMap<String, VarValue>,
+        _lazy_parameters: &LazyParameters,
+        data: &VarContext,
+        next_part: &Arc<dyn Interpreter>,
+    ) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+        let parameters = Parameters(parameters);
+        let ttl = parameters.positive(TTL_PARAM)?;
+        let max_entries = parameters.positive(MAX_ENTRIES_PARAM)? as usize;
+        let now = match parameters.optional_u64(TIMESTAMP_PARAM)? {
+            Some(timestamp) => timestamp,
+            None => self.clock.now(),
+        };
+        let key = parameters.key(KEY_PARAM);
+
+        let namespace = node_id.to_string();
+        let mut first = false;
+        self.store.update(&namespace, &key, &mut |first_seen| {
+            first = !matches!(first_seen.as_ref().and_then(Value::as_u64),
+                Some(time) if seen_recently(time, ttl, now));
+            if first {
+                Some(Value::from(now))
+            } else {
+                first_seen
+            }
+        });
+        if !first {
+            return Ok(ScenarioOutput(vec![]));
+        }
+        if self.store.count(&namespace) > max_entries {
+            self.evict(&namespace, now, ttl, max_entries);
+        }
+        next_part.run(data).await
+    }
+
+    fn parameters(&self) -> Option<Vec<ParameterDefinition>> {
+        Some(vec![
+            ParameterDefinition::required(KEY_PARAM),
+            ParameterDefinition::required(TTL_PARAM)
+                .with_type(ResultType::Number)
+                .literal(),
+            ParameterDefinition::optional(
+                MAX_ENTRIES_PARAM,
+                Some(Value::from(DEFAULT_MAX_ENTRIES)),
+            )
+            .with_type(ResultType::Number)
+            .literal(),
+            ParameterDefinition::optional(TIMESTAMP_PARAM, None).with_type(ResultType::Number),
+        ])
+    }
+
+    fn output_type(&self, _parameter_types: &HashMap<String, ResultType>) -> Option<ResultType> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, sync::Arc};
+
+    use serde_json::json;
+
+    use crate::interpreter::{
+        compiler::{
+            tests::{self, custom_node_scenario, parameter},
+            Compiler,
+        },
+        state::{FileStateStore, InMemoryStateStore, StateStore},
+        Interpreter,
+    };
+
+    fn deduplicate(store: Arc<dyn StateStore>, max_entries: u64) -> Box<dyn Interpreter> {
+        let scenario = custom_node_scenario(
+            "deduplicate",
+            None,
+            vec![
+                parameter("key", "input.id"),
+                parameter("ttl", "10"),
+                parameter("maxEntries", &max_entries.to_string()),
+                parameter("timestamp", "input.time"),
+            ],
+        );
+        Compiler::default()
+            .with_state_store(store)
+            .compile(&scenario)
+            .unwrap()
+    }
+
+    //events are (id, time), returns if the event passed
+    fn run(interpreter: &dyn Interpreter, events: &[(&str, u64)]) -> Vec<bool> {
+        events
+            .iter()
+            .map(|(id, time)| {
+                !tests::run(interpreter, json!({"id": id, "time": time}))
+                    .0
+                    .is_empty()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_deduplication_survives_restart() {
+        let path = env::temp_dir().join(format!("deduplicate-test-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let store = Arc::new(FileStateStore::open(&path).unwrap());
+        let interpreter = deduplicate(store.clone(), 100);
+        assert_eq!(
+            run(
+                interpreter.as_ref(),
+                &[("a", 1), ("b", 2), ("a", 5), ("a", 11), ("b", 12)]
+            ),
+            [true, true, false, true, true]
+        );
+        store.flush().unwrap();
+
+        let interpreter = deduplicate(Arc::new(FileStateStore::open(&path).unwrap()), 100);
+        assert_eq!(
+            run(interpreter.as_ref(), &[("a", 15), ("b", 30)]),
+            [false, true]
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_oldest_keys_evicted() {
+        let store = Arc::new(InMemoryStateStore::default());
+        let interpreter = deduplicate(store.clone(), 2);
+        assert_eq!(
+            run(interpreter.as_ref(), &[("a", 1), ("b", 2), ("c", 3)]),
+            [true, true, true]
+        );
+        assert_eq!(store.count("deduplicate"), 2);
+        //"a" was forgotten
+        assert_eq!(
+            run(interpreter.as_ref(), &[("c", 4), ("a", 5)]),
+            [false, true]
+        );
+    }
+
+    #[test]
+    fn test_burst_evicted() {
+        let store = Arc::new(InMemoryStateStore::default());
+        let interpreter = deduplicate(store.clone(), 10);
+        let keys: Vec<String> = (0..30).map(|i| format!("key{:02}", i)).collect();
+        let events: Vec<(&str, u64)> = keys.iter().map(|key| (key.as_str(), 1)).collect();
+        assert!(run(interpreter.as_ref(), &events)
+            .into_iter()
+            .all(|passed| passed));
+        assert!(store.count("deduplicate") <= 10);
+        //the last key is still remembered
+        assert_eq!(run(interpreter.as_ref(), &[("key29", 2)]), [false]);
+    }
+
+    #[test]
+    fn test_large_timestamps() {
+        let interpreter = deduplicate(Arc::new(InMemoryStateStore::default()), 10);
+        assert_eq!(
+            run(
+                interpreter.as_ref(),
+                &[("a", u64::MAX - 1), ("a", u64::MAX)]
+            ),
+            [true, false]
+        );
+    }
+}
@@ -0,0 +1,547 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    error::Error,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+
+use crate::{
+    expression::ResultType,
+    interpreter::{
+        data::{ScenarioOutput, ScenarioRuntimeError, VarContext, VarValue},
+        CustomNode, Interpreter, LazyParameters, NodeContext, ParameterDefinition,
+    },
+    scenariomodel::NodeId,
+};
+
+use super::{key_string, Parameters, WrongParameter};
+
+const FILE_PARAM: &str = "file";
+const FORMAT_PARAM: &str = "format";
+const KEY_COLUMN_PARAM: &str = "keyColumn";
+const COLUMN_TYPES_PARAM: &str = "columnTypes";
+const KEY_PARAM: &str = "key";
+
+///Enriches the context with the row of the table from "file" (csv with header, json array of objects or ndjson),
+///whose "keyColumn" is equal to "key". Output variable is null if there is no such row.
+///The table is loaded when the scenario is compiled, so missing or invalid file (e.g. with duplicate keys) fails
+///the compilation. The file is checked for modifications at most once per second and reloaded if it changed
+///(if the new version cannot be read, the previous one is used). CSV values are strings, unless "columnTypes" map
+///gives other type (string, number or boolean) for the column. Format is taken from file extension if not given
+pub struct Lookup {
+    //by node id
+    tables: Mutex<HashMap<String, Arc<Table>>>,
+    reload_interval: Duration,
+}
+
+struct Table {
+    settings: TableSettings,
+    version: Option<FileVersion>,
+    //when the version of the file was last compared
+    checked: Mutex<Instant>,
+    rows: HashMap<String, Value>,
+}
+
+#[derive(Clone)]
+struct TableSettings {
+    file: PathBuf,
+    format: Format,
+    key_column: String,
+    column_types: HashMap<String, ColumnType>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Csv,
+    Json,
+    NdJson,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnType {
+    String,
+    Number,
+    Boolean,
+}
+
+impl TableSettings {
+    fn parse(parameters: &HashMap<String, VarValue>) -> Result<TableSettings, WrongParameter> {
+        let string = |name: &'static str| match Parameters(parameters).get(name) {
+            Value::String(value) => Ok(value),
+            other => Err(WrongParameter(name, other)),
+        };
+        let file = PathBuf::from(string(FILE_PARAM)?);
+        let format = match parameters.get(FORMAT_PARAM) {
+            None | Some(Value::Null) => file.extension().and_then(|e| e.to_str()),
+            Some(Value::String(format)) => Some(format.as_str()),
+            Some(_) => None,
+        };
+        let format = match format {
+            Some("csv") => Format::Csv,
+            Some("json") => Format::Json,
+            Some("ndjson" | "jsonl") => Format::NdJson,
+            _ => {
+                return Err(WrongParameter(
+                    FORMAT_PARAM,
+                    Parameters(parameters).get(FORMAT_PARAM),
+                ))
+            }
+        };
+        let column_types = match parameters.get(COLUMN_TYPES_PARAM) {
+            None | Some(Value::Null) => HashMap::new(),
+            Some(Value::Object(types)) => types
+                .iter()
+                .map(|(column, column_type)| {
+                    let column_type = match column_type.as_str() {
+                        Some("string") => ColumnType::String,
+                        Some("number") => ColumnType::Number,
+                        Some("boolean") => ColumnType::Boolean,
+                        _ => {
+                            return Err(WrongParameter(
+                                COLUMN_TYPES_PARAM,
+                                Value::Object(types.clone()),
+                            ))
+                        }
+                    };
+                    Ok((column.clone(), column_type))
+                })
+                .collect::<Result<_, _>>()?,
+            Some(other) => return Err(WrongParameter(COLUMN_TYPES_PARAM, other.clone())),
+        };
+        Ok(TableSettings {
+            file,
+            format,
+            key_column: string(KEY_COLUMN_PARAM)?,
+            column_types,
+        })
+    }
+}
+
+impl Table {
+    fn load(settings: TableSettings) -> Result<Table, LookupError> {
+        let error = |error: String| LookupError::InvalidFile {
+            file: settings.file.clone(),
+            error,
+        };
+        let version = file_version(&settings.file);
+        let content = fs::read_to_string(&settings.file).map_err(|err| error(err.to_string()))?;
+        let records: Vec<Value> = match settings.format {
+            Format::Csv => parse_csv(&content).map_err(error)?,
+            Format::Json => serde_json::from_str(&content).map_err(|err| error(err.to_string()))?,
+            Format::NdJson => content
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(serde_json::from_str)
+                .collect::<Result<_, _>>()
+                .map_err(|err| error(err.to_string()))?,
+        };
+        let checked = Mutex::new(Instant::now());
+        let mut rows = HashMap::new();
+        for record in records {
+            let Value::Object(record) = record else {
+                return Err(error(format!("Row {} is not an object", record)));
+            };
+            let record = record
+                .into_iter()
+                .map(|(column, value)| match settings.column_types.get(&column) {
+                    Some(column_type) => convert(value, *column_type)
+                        .map(|value| (column.clone(), value))
+                        .ok_or_else(|| error(format!("Invalid value of column {}", column))),
+                    None => Ok((column, value)),
+                })
+                .collect::<Result<Map<String, Value>, _>>()?;
+            let key = record.get(&settings.key_column).map(key_string);
+            let key =
+                key.ok_or_else(|| error(format!("Row without column {}", settings.key_column)))?;
+            match rows.entry(key) {
+                Entry::Occupied(entry) => {
+                    return Err(error(format!("Duplicate key {}", entry.key())))
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(Value::Object(record));
+                }
+            }
+        }
+        Ok(Table {
+            settings,
+            version,
+            checked,
+            rows,
+        })
+    }
+
+    fn modified(&self, check_interval: Duration) -> bool {
+        let mut checked = self
+            .checked
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if checked.elapsed() < check_interval {
+            return false;
+        }
+        *checked = Instant::now();
+        file_version(&self.settings.file) != self.version
+    }
+}
+
+//size is compared too, as modification time may not change if the file is written quickly
+type FileVersion = (SystemTime, u64);
+
+fn file_version(file: &Path) -> Option<FileVersion> {
+    let metadata = fs::metadata(file).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+//None if the value cannot be converted
+fn convert(value: Value, column_type: ColumnType) -> Option<Value> {
+    match (value, column_type) {
+        (Value::Null, _) => Some(Value::Null),
+        (Value::String(value), _) if value.is_empty() => Some(Value::Null),
+        (Value::String(value), ColumnType::Number) => {
+            serde_json::from_str::<serde_json::Number>(value.trim())
+                .ok()
+                .map(Value::Number)
+        }
+        (Value::String(value), ColumnType::Boolean) => value.trim().parse().ok().map(Value::Bool),
+        (Value::Number(value), ColumnType::String) => Some(Value::String(value.to_string())),
+        (Value::Bool(value), ColumnType::String) => Some(Value::String(value.to_string())),
+        (value @ Value::String(_), ColumnType::String)
+        | (value @ Value::Number(_), ColumnType::Number)
+        | (value @ Value::Bool(_), ColumnType::Boolean) => Some(value),
+        _ => None,
+    }
+}
+
+//header row gives names of columns, fields can be quoted ("" is escaped quote)
+fn parse_csv(content: &str) -> Result<Vec<Value>, String> {
+    let mut rows: Vec<Vec<String>> = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.is_empty() => quoted = true,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\r', false) => (),
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (c, _) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(String::from("Unterminated quoted field"));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    let mut rows = rows
+        .into_iter()
+        .filter(|row| !(row.len() == 1 && row[0].is_empty()));
+    let header = rows.next().ok_or_else(|| String::from("Missing header"))?;
+    rows.enumerate()
+        .map(|(index, row)| {
+            if row.len() != header.len() {
+                return Err(format!(
+                    "Row {} has {} fields, {} expected",
+                    index + 1,
+                    row.len(),
+                    header.len()
+                ));
+            }
+            let record = header
+                .iter()
+                .cloned()
+                .zip(row.into_iter().map(Value::String))
+                .collect();
+            Ok(Value::Object(record))
+        })
+        .collect()
+}
+
+impl Default for Lookup {
+    fn default() -> Self {
+        Lookup::with_reload_interval(Duration::from_secs(1))
+    }
+}
+
+impl Lookup {
+    ///The file is checked for modifications at most once per reload_interval
+    pub fn with_reload_interval(reload_interval: Duration) -> Lookup {
+        Lookup {
+            tables: Mutex::default(),
+            reload_interval,
+        }
+    }
+
+    fn store(&self, node_id: &NodeId, table: Table) -> Arc<Table> {
+        let table = Arc::new(table);
+        self.tables
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(node_id.to_string(), table.clone());
+        table
+    }
+
+    //loaded again if the node was closed, reloaded if the file was changed
+    fn table(
+        &self,
+        node_id: &NodeId,
+        parameters: &HashMap<String, VarValue>,
+    ) -> Result<Arc<Table>, ScenarioRuntimeError> {
+        let current = self
+            .tables
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(&node_id.to_string())
+            .cloned();
+        match current {
+            Some(table) if !table.modified(self.reload_interval) => Ok(table),
+            Some(table) => Ok(match Table::load(table.settings.clone()) {
+                Ok(reloaded) => self.store(node_id, reloaded),
+                Err(_) => table,
+            }),
+            None => {
+                let table = Table::load(TableSettings::parse(parameters)?)?;
+                Ok(self.store(node_id, table))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl CustomNode for Lookup {
+    async fn run(
+        &self,
+        node_id: &NodeId,
+        output_var: Option<&str>,
+        parameters: &HashMap<String, VarValue>,
+        _lazy_parameters: &LazyParameters,
+        data: &VarContext,
+        next_part: &Arc<dyn Interpreter>,
+    ) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+        let table = self.table(node_id, parameters)?;
+        let key = Parameters(parameters).key(KEY_PARAM);
+        let row = table.rows.get(&key).cloned().unwrap_or_default();
+        next_part.run(&data.with_output_var(output_var, row)).await
+    }
+
+    fn parameters(&self) -> Option<Vec<ParameterDefinition>> {
+        Some(vec![
+            ParameterDefinition::required(FILE_PARAM)
+                .with_type(ResultType::String)
+                .literal(),
+            ParameterDefinition::optional(FORMAT_PARAM, None)
+                .with_type(ResultType::String)
+                .literal(),
+            ParameterDefinition::required(KEY_COLUMN_PARAM)
+                .with_type(ResultType::String)
+                .literal(),
+            ParameterDefinition::optional(COLUMN_TYPES_PARAM, None)
+                .with_type(ResultType::Map)
+                .literal(),
+            ParameterDefinition::required(KEY_PARAM),
+        ])
+    }
+
+    fn output_type(&self, _parameter_types: &HashMap<String, ResultType>) -> Option<ResultType> {
+        Some(ResultType::Map)
+    }
+
+    fn validate(
+        &self,
+        node_id: &NodeId,
+        parameters: &HashMap<String, VarValue>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let table = Table::load(TableSettings::parse(parameters)?)?;
+        self.store(node_id, table);
+        Ok(())
+    }
+
+    async fn open(&self, context: &NodeContext) -> Result<(), ScenarioRuntimeError> {
+        self.table(context.node_id, context.parameters)?;
+        Ok(())
+    }
+
+    async fn close(&self, context: &NodeContext) {
+        self.tables
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&context.node_id.to_string());
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LookupError {
+    InvalidFile { file: PathBuf, error: String },
+}
+
+impl From<LookupError> for ScenarioRuntimeError {
+    fn from(error: LookupError) -> Self {
+        ScenarioRuntimeError::CustomNodeError(Box::new(error))
+    }
+}
+
+impl Display for LookupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidFile { file, error } => {
+                write!(f, "Failed to load table from {}: {}", file.display(), error)
+            }
+        }
+    }
+}
+impl Error for LookupError {}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::Duration,
+    };
+
+    use serde_json::{json, Value};
+
+    use crate::{
+        interpreter::{
+            compiler::{
+                tests::{self, custom_node_scenario, parameter, single_output},
+                Compiler,
+            },
+            data::ScenarioCompilationError,
+            CompilationResult, Interpreter,
+        },
+        scenariomodel::{NodeId, Parameter},
+    };
+
+    use super::{parse_csv, Lookup};
+
+    //file is checked for modifications on each record
+    fn compile_lookup(file: &Path, column_types: Option<&str>) -> CompilationResult {
+        let mut parameters = vec![
+            parameter("file", &format!("'{}'", file.display())),
+            parameter("keyColumn", "'code'"),
+            parameter("key", "input.country"),
+        ];
+        parameters.extend(column_types.map(|types| Parameter {
+            name: String::from("columnTypes"),
+            expression: tests::js(types),
+        }));
+        let scenario = custom_node_scenario("lookup", Some("country"), parameters);
+        Compiler::default()
+            .with_custom_node(
+                "lookup",
+                Arc::new(Lookup::with_reload_interval(Duration::ZERO)),
+            )
+            .compile(&scenario)
+    }
+
+    fn lookup(file: &Path, column_types: Option<&str>) -> Box<dyn Interpreter> {
+        compile_lookup(file, column_types).unwrap()
+    }
+
+    fn run(interpreter: &dyn Interpreter, country: &str) -> Value {
+        single_output(interpreter, json!({ "country": country }), "country")
+    }
+
+    fn temp_file(name: &str, content: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("lookup-test-{}-{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_csv_with_types() {
+        let file = temp_file(
+            "countries.csv",
+            "code,name,risk,sanctioned\nPL,Poland,2,false\n\"XX\",\"Nowhere, \"\"Land\"\"\",,true\n",
+        );
+        let interpreter = lookup(&file, Some("({risk: 'number', sanctioned: 'boolean'})"));
+        assert_eq!(
+            run(interpreter.as_ref(), "PL"),
+            json!({"code": "PL", "name": "Poland", "risk": 2, "sanctioned": false})
+        );
+        assert_eq!(
+            run(interpreter.as_ref(), "XX"),
+            json!({"code": "XX", "name": "Nowhere, \"Land\"", "risk": null, "sanctioned": true})
+        );
+        assert_eq!(run(interpreter.as_ref(), "DE"), Value::Null);
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_reload_ndjson() {
+        let file = temp_file("countries.ndjson", "{\"code\": \"PL\", \"risk\": 2}\n");
+        let interpreter = lookup(&file, None);
+        assert_eq!(
+            run(interpreter.as_ref(), "PL"),
+            json!({"code": "PL", "risk": 2})
+        );
+
+        fs::write(
+            &file,
+            "{\"code\": \"PL\", \"risk\": 3}\n\n{\"code\": \"DE\", \"risk\": 1}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            run(interpreter.as_ref(), "PL"),
+            json!({"code": "PL", "risk": 3})
+        );
+        assert_eq!(
+            run(interpreter.as_ref(), "DE"),
+            json!({"code": "DE", "risk": 1})
+        );
+
+        //invalid version is ignored
+        fs::write(&file, "{\"code\": ").unwrap();
+        assert_eq!(
+            run(interpreter.as_ref(), "DE"),
+            json!({"code": "DE", "risk": 1})
+        );
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_file_fails_compilation() {
+        let invalid = |file: &Path| match compile_lookup(file, None).err() {
+            Some(ScenarioCompilationError::InvalidNode { node_id, error }) => {
+                assert_eq!(node_id, NodeId::new("lookup"));
+                error.to_string()
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        };
+        let missing = env::temp_dir().join("lookup-test-missing.csv");
+        assert!(invalid(&missing).starts_with("Failed to load table from"));
+
+        let file = temp_file("duplicates.csv", "code,risk\nPL,2\nDE,1\nPL,3\n");
+        assert!(invalid(&file).ends_with("Duplicate key PL"));
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_csv() {
+        assert_eq!(
+            parse_csv("a,b\n1,2,3\n"),
+            Err(String::from("Row 1 has 3 fields, 2 expected"))
+        );
+        assert_eq!(
+            parse_csv("a,b\n\"1,2\n"),
+            Err(String::from("Unterminated quoted field"))
+        );
+    }
+}
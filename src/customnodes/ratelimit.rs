@@ -0,0 +1,354 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::{
+    expression::ResultType,
+    interpreter::{
+        clock::Clock,
+        data::{ScenarioOutput, ScenarioRuntimeError, VarContext, VarValue},
+        state::StateStore,
+        CustomNode, Interpreter, LazyParameters, ParameterDefinition,
+    },
+    scenariomodel::NodeId,
+};
+
+use super::Parameters;
+
+const LIMIT_PARAM: &str = "limit";
+const PERIOD_PARAM: &str = "period";
+const KEY_PARAM: &str = "key";
+const MAX_DELAY_PARAM: &str = "maxDelay";
+
+const DEFAULT_MAX_DELAY: u64 = 5_000;
+
+///What happens with records exceeding the limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitAction {
+    ///The rest of the scenario is not invoked
+    Drop,
+    ///The record waits until the limit allows it (at most "maxDelay" milliseconds, 5 seconds by default, otherwise
+    ///it's dropped)
+    Delay,
+    ///The rest of the scenario is invoked for all records, only the (boolean) output variable tells if the limit was
+    ///exceeded - the scenario has to branch on it itself, e.g. with filter or switch
+    Route,
+}
+
+///Token bucket: at most "limit" records per "period" milliseconds, for each value of "key" (if given).
+///Buckets are kept in the state store, with node id as namespace. Buckets which are full again are removed, as they
+///don't differ from new ones
+pub struct RateLimit {
+    action: LimitAction,
+    store: Arc<dyn StateStore>,
+    clock: Arc<dyn Clock>,
+    //when full buckets were last removed, by namespace
+    evicted: Mutex<HashMap<String, u64>>,
+}
+
+impl RateLimit {
+    pub fn new(
+        action: LimitAction,
+        store: Arc<dyn StateStore>,
+        clock: Arc<dyn Clock>,
+    ) -> RateLimit {
+        RateLimit {
+            action,
+            store,
+            clock,
+            evicted: Mutex::new(HashMap::new()),
+        }
+    }
+
+    //at most once per period, so that it doesn't scan all buckets for each record
+    fn evict_full(&self, namespace: &str, now: u64, limit: f64, period: f64) {
+        let mut evicted = self
+            .evicted
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let last = evicted.entry(String::from(namespace)).or_insert(now);
+        if (now.saturating_sub(*last) as f64) < period {
+            return;
+        }
+        *last = now;
+        drop(evicted);
+        self.store.retain(namespace, &mut |_, state| {
+            let mut bucket = Bucket::read(Some(state), limit, period, now);
+            bucket.refill(now);
+            bucket.tokens < limit
+        });
+    }
+}
+
+//how long the record has to wait for the token, None if it would be longer than max_delay
+fn take_token(bucket: &mut Bucket, now: u64, max_delay: u64) -> Option<u64> {
+    bucket.refill(now);
+    let delay = if bucket.tokens >= 1.0 {
+        0
+    } else {
+        ((1.0 - bucket.tokens) * bucket.period / bucket.limit).ceil() as u64
+    };
+    if delay > max_delay {
+        return None;
+    }
+    //with delay, the token is reserved, so that the following records wait longer
+    bucket.tokens -= 1.0;
+    Some(delay)
+}
+
+struct Bucket {
+    limit: f64,
+    period: f64,
+    tokens: f64,
+    updated: u64,
+}
+
+impl Bucket {
+    //missing state is a full bucket
+    fn read(state: Option<&Value>, limit: f64, period: f64, now: u64) -> Bucket {
+        let field = |name: &str| state.and_then(|state| state.get(name));
+        Bucket {
+            limit,
+            period,
+            tokens: field("tokens").and_then(Value::as_f64).unwrap_or(limit),
+            updated: field("updated").and_then(Value::as_u64).unwrap_or(now),
+        }
+    }
+
+    fn refill(&mut self, now: u64) {
+        let refilled = now.saturating_sub(self.updated) as f64 * self.limit / self.period;
+        self.tokens = (self.tokens + refilled).min(self.limit);
+        self.updated = self.updated.max(now);
+    }
+}
+
+#[async_trait]
+impl CustomNode for RateLimit {
+    async fn run(
+        &self,
+        node_id: &NodeId,
+        output_var: Option<&str>,
+        parameters: &HashMap<String, VarValue>,
+        _lazy_parameters: &LazyParameters,
+        data: &VarContext,
+        next_part: &Arc<dyn Interpreter>,
+    ) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+        let parameters = Parameters(parameters);
+        let limit = parameters.positive(LIMIT_PARAM)? as f64;
+        let period = parameters.positive(PERIOD_PARAM)? as f64;
+        let max_delay = match self.action {
+            //records which don't get the token immediately are dropped or routed
+            LimitAction::Drop | LimitAction::Route => 0,
+            LimitAction::Delay => parameters
+                .optional_u64(MAX_DELAY_PARAM)?
+                .unwrap_or(DEFAULT_MAX_DELAY),
+        };
+        let key = parameters.key(KEY_PARAM);
+
+        let now = self.clock.now();
+        let namespace = node_id.to_string();
+        let mut delay = None;
+        self.store.update(&namespace, &key, &mut |state| {
+            let mut bucket = Bucket::read(state.as_ref(), limit, period, now);
+            delay = take_token(&mut bucket, now, max_delay);
+            Some(json!({"tokens": bucket.tokens, "updated": bucket.updated}))
+        });
+        self.evict_full(&namespace, now, limit, period);
+        match (self.action, delay) {
+            (LimitAction::Route, delay) => {
+                let limited = Value::Bool(delay.is_none());
+                next_part
+                    .run(&data.with_output_var(output_var, limited))
+                    .await
+            }
+            (_, None) => Ok(ScenarioOutput(vec![])),
+            (_, Some(delay)) => {
+                if delay > 0 {
+                    self.clock.sleep(delay).await;
+                }
+                next_part.run(data).await
+            }
+        }
+    }
+
+    fn parameters(&self) -> Option<Vec<ParameterDefinition>> {
+        let mut parameters = vec![
+            ParameterDefinition::required(LIMIT_PARAM)
+                .with_type(ResultType::Number)
+                .literal(),
+            ParameterDefinition::required(PERIOD_PARAM)
+                .with_type(ResultType::Number)
+                .literal(),
+            ParameterDefinition::optional(KEY_PARAM, None),
+        ];
+        if self.action == LimitAction::Delay {
+            parameters.push(
+                ParameterDefinition::optional(
+                    MAX_DELAY_PARAM,
+                    Some(Value::from(DEFAULT_MAX_DELAY)),
+                )
+                .with_type(ResultType::Number)
+                .literal(),
+            );
+        }
+        Some(parameters)
+    }
+
+    fn output_type(&self, _parameter_types: &HashMap<String, ResultType>) -> Option<ResultType> {
+        match self.action {
+            LimitAction::Route => Some(ResultType::Boolean),
+            LimitAction::Drop | LimitAction::Delay => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::{json, Value};
+
+    use crate::{
+        interpreter::{
+            clock::{Clock, ManualClock},
+            compiler::{
+                tests::{self, custom_node_scenario, parameter},
+                Compiler,
+            },
+            state::{InMemoryStateStore, StateStore},
+            Interpreter,
+        },
+        scenariomodel::NodeId,
+    };
+
+    fn rate_limit(
+        node_type: &str,
+        output_var: Option<&str>,
+        extra: &[(&str, &str)],
+        clock: Arc<ManualClock>,
+    ) -> Box<dyn Interpreter> {
+        let mut parameters = vec![parameter("limit", "2"), parameter("period", "1000")];
+        parameters.extend(extra.iter().map(|(name, value)| parameter(name, value)));
+        let scenario = custom_node_scenario(node_type, output_var, parameters);
+        Compiler::default()
+            .with_clock(clock)
+            .compile(&scenario)
+            .unwrap()
+    }
+
+    //returns values of `limited` variable, null if it's not set and nothing if record was dropped
+    fn run(interpreter: &dyn Interpreter, input: Value) -> Vec<Value> {
+        tests::run(interpreter, input)
+            .var_in_sink(&NodeId::new("sink"), "limited")
+            .into_iter()
+            .map(|value| value.cloned().unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn test_drop() {
+        let clock = Arc::new(ManualClock::new(10_000));
+        let interpreter = rate_limit(
+            "rateLimitDrop",
+            None,
+            &[("key", "input.user")],
+            clock.clone(),
+        );
+        let passed = |user: &str| run(interpreter.as_ref(), json!({ "user": user })).len();
+        assert_eq!([passed("a"), passed("a"), passed("a")], [1, 1, 0]);
+        assert_eq!(passed("b"), 1);
+        clock.advance(500);
+        assert_eq!([passed("a"), passed("a")], [1, 0]);
+        clock.advance(5000);
+        assert_eq!([passed("a"), passed("a"), passed("a")], [1, 1, 0]);
+    }
+
+    #[test]
+    fn test_delay() {
+        let clock = Arc::new(ManualClock::new(10_000));
+        //returns if the record passed and when
+        let passed_at = |interpreter: &dyn Interpreter| {
+            let passed = run(interpreter, json!({})).len();
+            (passed, clock.now())
+        };
+        let interpreter = rate_limit(
+            "rateLimitDelay",
+            None,
+            &[("maxDelay", "1000")],
+            clock.clone(),
+        );
+        let interpreter = interpreter.as_ref();
+        assert_eq!(
+            [
+                passed_at(interpreter),
+                passed_at(interpreter),
+                passed_at(interpreter),
+                passed_at(interpreter)
+            ],
+            [(1, 10_000), (1, 10_000), (1, 10_500), (1, 11_000)]
+        );
+
+        //waiting for the token would take more than maxDelay
+        let interpreter = rate_limit(
+            "rateLimitDelay",
+            None,
+            &[("maxDelay", "400")],
+            clock.clone(),
+        );
+        let interpreter = interpreter.as_ref();
+        assert_eq!(
+            [
+                passed_at(interpreter),
+                passed_at(interpreter),
+                passed_at(interpreter)
+            ],
+            [(1, 11_000), (1, 11_000), (0, 11_000)]
+        );
+    }
+
+    #[test]
+    fn test_full_buckets_evicted() {
+        let clock = Arc::new(ManualClock::new(10_000));
+        let store = Arc::new(InMemoryStateStore::default());
+        let scenario = custom_node_scenario(
+            "rateLimitDrop",
+            None,
+            vec![
+                parameter("limit", "2"),
+                parameter("period", "1000"),
+                parameter("key", "input.user"),
+            ],
+        );
+        let interpreter = Compiler::default()
+            .with_clock(clock.clone())
+            .with_state_store(store.clone())
+            .compile(&scenario)
+            .unwrap();
+        let passed = |user: &str| run(interpreter.as_ref(), json!({ "user": user })).len();
+        assert_eq!([passed("a"), passed("a")], [1, 1]);
+        clock.advance(500);
+        assert_eq!([passed("b"), passed("b")], [1, 1]);
+        //bucket of a is full again, the one of b only half
+        clock.advance(500);
+        assert_eq!(passed("c"), 1);
+        assert!(store.get("rateLimitDrop", "a").is_none());
+        assert!(store.get("rateLimitDrop", "b").is_some());
+        assert!(store.get("rateLimitDrop", "c").is_some());
+        assert_eq!([passed("a"), passed("a"), passed("a")], [1, 1, 0]);
+    }
+
+    #[test]
+    fn test_route() {
+        let clock = Arc::new(ManualClock::new(10_000));
+        let interpreter = rate_limit("rateLimitRoute", Some("limited"), &[], clock);
+        let limited = || run(interpreter.as_ref(), json!({}));
+        assert_eq!(
+            [limited(), limited(), limited()],
+            [[json!(false)], [json!(false)], [json!(true)]]
+        );
+    }
+}
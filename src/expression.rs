@@ -1,4 +1,8 @@
 use super::javascriptexpression::JavaScriptParser;
+use super::jsonpathexpression::JsonPathParser;
+use super::simpleexpression::SimpleParser;
+use super::spelexpression::SpelParser;
+use super::templateexpression::TemplateParser;
 use crate::interpreter::data::{
     CompilationVarContext, ScenarioCompilationError, ScenarioCompilationError::UnknownLanguage,
     ScenarioRuntimeError, VarContext, VarValue,
@@ -16,8 +20,48 @@ pub trait Parser {
     ) -> Result<Box<dyn CompiledExpression>, Box<dyn ParseError>>;
 }
 
+///Static information about expressions is optional, the defaults are the safe ones (nothing is known)
 pub trait CompiledExpression: Sync + Send {
     fn execute(&self, data: &VarContext) -> Result<VarValue, ScenarioRuntimeError>;
+
+    ///Names of variables read by the expression, None if it cannot be determined
+    fn used_variables(&self) -> Option<Vec<String>> {
+        None
+    }
+
+    fn result_type(&self) -> ResultType {
+        ResultType::Unknown
+    }
+
+    ///Constant expression gives the same result for each invocation, regardless of the context
+    fn is_constant(&self) -> bool {
+        false
+    }
+}
+
+///Type of expression result, as far as it can be inferred without evaluating it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultType {
+    Unknown,
+    Null,
+    Boolean,
+    Number,
+    String,
+    List,
+    Map,
+}
+
+impl ResultType {
+    pub fn of(value: &VarValue) -> ResultType {
+        match value {
+            VarValue::Null => ResultType::Null,
+            VarValue::Bool(_) => ResultType::Boolean,
+            VarValue::Number(_) => ResultType::Number,
+            VarValue::String(_) => ResultType::String,
+            VarValue::Array(_) => ResultType::List,
+            VarValue::Object(_) => ResultType::Map,
+        }
+    }
 }
 
 pub struct LanguageParser {
@@ -25,6 +69,12 @@ pub struct LanguageParser {
 }
 
 impl LanguageParser {
+    ///Registers additional language, or replaces the parser of already known one (e.g. to configure javascript differently)
+    pub fn with_parser(mut self, language: &str, parser: Box<dyn Parser>) -> LanguageParser {
+        self.parsers.insert(String::from(language), parser);
+        self
+    }
+
     pub fn parse(
         &self,
         node_id: &NodeId,
@@ -38,20 +88,84 @@ impl LanguageParser {
                 node_id: node_id.clone(),
                 language: expression.language.to_string(),
             })?;
-        parser
+        let expression = parser
             .parse(&expression.expression, var_context)
             .map_err(|error| ScenarioCompilationError::ParseError {
                 node_id: node_id.clone(),
                 error,
+            })?;
+        Ok(Box::new(NodeExpression {
+            node_id: node_id.clone(),
+            expression,
+        }))
+    }
+}
+
+///Result of expression evaluated once, during compilation
+pub struct ConstantExpression(pub VarValue);
+
+impl CompiledExpression for ConstantExpression {
+    fn execute(&self, _data: &VarContext) -> Result<VarValue, ScenarioRuntimeError> {
+        Ok(self.0.clone())
+    }
+
+    fn used_variables(&self) -> Option<Vec<String>> {
+        Some(vec![])
+    }
+
+    fn result_type(&self) -> ResultType {
+        ResultType::of(&self.0)
+    }
+
+    fn is_constant(&self) -> bool {
+        true
+    }
+}
+
+///Adds id of the node to errors, so that it's known which expression failed
+struct NodeExpression {
+    node_id: NodeId,
+    expression: Box<dyn CompiledExpression>,
+}
+
+impl CompiledExpression for NodeExpression {
+    fn execute(&self, data: &VarContext) -> Result<VarValue, ScenarioRuntimeError> {
+        self.expression
+            .execute(data)
+            .map_err(|error| ScenarioRuntimeError::NodeError {
+                node_id: self.node_id.clone(),
+                error: Box::new(error),
             })
     }
+
+    fn used_variables(&self) -> Option<Vec<String>> {
+        self.expression.used_variables()
+    }
+
+    fn result_type(&self) -> ResultType {
+        self.expression.result_type()
+    }
+
+    fn is_constant(&self) -> bool {
+        self.expression.is_constant()
+    }
 }
 
 impl Default for LanguageParser {
     fn default() -> LanguageParser {
-        let javascript: Box<dyn Parser> = Box::new(JavaScriptParser);
+        let javascript: Box<dyn Parser> = Box::new(JavaScriptParser::default());
+        let simple: Box<dyn Parser> = Box::new(SimpleParser);
+        let spel: Box<dyn Parser> = Box::new(SpelParser);
+        let jsonpath: Box<dyn Parser> = Box::new(JsonPathParser);
+        let template: Box<dyn Parser> = Box::new(TemplateParser);
         LanguageParser {
-            parsers: HashMap::from([(String::from("javascript"), javascript)]),
+            parsers: HashMap::from([
+                (String::from("javascript"), javascript),
+                (String::from("simple"), simple),
+                (String::from("spel"), spel),
+                (String::from("jsonpath"), jsonpath),
+                (String::from("template"), template),
+            ]),
         }
     }
 }
@@ -1,36 +1,157 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use async_trait::async_trait;
 
+use crate::{
+    expression::{CompiledExpression, ResultType},
+    scenariomodel::{MetaData, NodeId},
+};
+
 use self::data::{
     ScenarioCompilationError, ScenarioOutput, ScenarioRuntimeError, VarContext, VarValue,
 };
 
+pub mod clock;
 pub mod compiler;
 pub mod data;
+pub mod state;
+pub mod workers;
 
 ///This is the main API of the rusty-nussknacker library. It represents 'compiled' scenario,
 ///which can transform input - VarContext into ScenarioOutput
 #[async_trait]
 pub trait Interpreter: Sync + Send {
     async fn run(&self, data: &VarContext) -> Result<ScenarioOutput, ScenarioRuntimeError>;
+
+    ///Prepares the scenario (e.g. opens custom nodes). Compiled scenario opens itself on first run if it's not called
+    async fn open(&self) -> Result<(), ScenarioRuntimeError> {
+        Ok(())
+    }
+
+    ///Releases resources, should be invoked on shutdown
+    async fn close(&self) {}
 }
 
 pub type CompilationResult = Result<Box<dyn Interpreter>, ScenarioCompilationError>;
 
+///Parameters evaluated by the component itself, against contexts it builds (e.g. for each element of a list).
+///It's the equivalent of LazyParameter in Nussknacker
+pub type LazyParameters = HashMap<String, Box<dyn CompiledExpression>>;
+
 ///This is the API of different kinds of components that may be plugged into the library.
 ///Given input, evaluated parameters and continuation of rest of the scenario (next_part parameter),
 ///implementations of the trait compute the output.
 ///Note, that the API allows next_part to be invoked 0..many times, which allows to implement different types
 ///of components, from filter to for-each types.
 ///Sample implementation of for-each is provided in customnodes module.
+///Parameters declared as lazy are passed in lazy_parameters, the rest are evaluated before invocation.
+///node_id identifies the node in the scenario, e.g. to keep its state separate. output_var is None for nodes which declare no output.
 #[async_trait]
 pub trait CustomNode: Sync + Send {
     async fn run(
         &self,
-        output_var: &str,
+        node_id: &NodeId,
+        output_var: Option<&str>,
         parameters: &HashMap<String, VarValue>,
+        lazy_parameters: &LazyParameters,
         input: &VarContext,
-        next_part: &dyn Interpreter,
+        next_part: &Arc<dyn Interpreter>,
     ) -> Result<ScenarioOutput, ScenarioRuntimeError>;
+
+    ///Parameters accepted by the node, checked during compilation. None means that anything is accepted
+    fn parameters(&self) -> Option<Vec<ParameterDefinition>> {
+        None
+    }
+
+    ///Type of the output variable, based on types of parameters. None means that the node has no output variable
+    fn output_type(&self, _parameter_types: &HashMap<String, ResultType>) -> Option<ResultType> {
+        Some(ResultType::Unknown)
+    }
+
+    ///Invoked during compilation with parameters which don't depend on the input (as in NodeContext),
+    ///e.g. to check that configured file can be read. Returned error fails the compilation
+    fn validate(
+        &self,
+        _node_id: &NodeId,
+        _parameters: &HashMap<String, VarValue>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Ok(())
+    }
+
+    ///Invoked once for each node using the component, before the scenario is run - e.g. to create connection pool
+    ///or load lookup table
+    async fn open(&self, _context: &NodeContext) -> Result<(), ScenarioRuntimeError> {
+        Ok(())
+    }
+
+    ///Invoked when the scenario is closed, for each node opened before
+    async fn close(&self, _context: &NodeContext) {}
+}
+
+///Passed to custom nodes when they are opened and closed
+pub struct NodeContext<'a> {
+    pub meta_data: &'a MetaData,
+    pub node_id: &'a NodeId,
+    ///Set with Compiler::with_custom_node_config, null by default
+    pub config: &'a VarValue,
+    ///Parameters which don't depend on the input (e.g. literal ones), evaluated during compilation
+    pub parameters: &'a HashMap<String, VarValue>,
+}
+
+///Declaration of custom node parameter, e.g. `ParameterDefinition::required("value").with_type(ResultType::List)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterDefinition {
+    pub name: String,
+    pub required: bool,
+    ///Unknown means that any type is accepted
+    pub expected_type: ResultType,
+    ///Used when optional parameter is not given
+    pub default: Option<VarValue>,
+    ///Literal parameters have to be constant (e.g. configuration), expression parameters can use variables
+    pub literal: bool,
+    ///Lazy parameters are not evaluated before invoking the node
+    pub lazy: bool,
+    ///Variables which the node adds to the context of lazy parameter (e.g. list element)
+    pub additional_variables: Vec<String>,
+}
+
+impl ParameterDefinition {
+    pub fn required(name: &str) -> ParameterDefinition {
+        ParameterDefinition {
+            name: String::from(name),
+            required: true,
+            expected_type: ResultType::Unknown,
+            default: None,
+            literal: false,
+            lazy: false,
+            additional_variables: vec![],
+        }
+    }
+
+    pub fn optional(name: &str, default: Option<VarValue>) -> ParameterDefinition {
+        ParameterDefinition {
+            required: false,
+            default,
+            ..ParameterDefinition::required(name)
+        }
+    }
+
+    pub fn with_type(mut self, expected_type: ResultType) -> ParameterDefinition {
+        self.expected_type = expected_type;
+        self
+    }
+
+    pub fn literal(mut self) -> ParameterDefinition {
+        self.literal = true;
+        self
+    }
+
+    pub fn lazy(mut self, additional_variables: &[&str]) -> ParameterDefinition {
+        self.lazy = true;
+        self.additional_variables = additional_variables
+            .iter()
+            .map(|name| String::from(*name))
+            .collect();
+        self
+    }
 }
@@ -0,0 +1,188 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use futures::{channel::oneshot, future};
+use once_cell::sync::Lazy;
+
+///Source of time for components which depend on it (e.g. windows, rate limits), can be replaced in tests
+#[async_trait]
+pub trait Clock: Sync + Send {
+    ///Milliseconds since epoch
+    fn now(&self) -> u64;
+
+    async fn sleep(&self, millis: u64);
+}
+
+pub struct SystemClock;
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default()
+    }
+
+    async fn sleep(&self, millis: u64) {
+        let deadline = match Instant::now().checked_add(Duration::from_millis(millis)) {
+            Some(deadline) => deadline,
+            None => return future::pending().await,
+        };
+        let (wake, woken) = oneshot::channel();
+        TIMER.schedule(deadline, move || {
+            let _ = wake.send(());
+        });
+        let _ = woken.await;
+    }
+}
+
+//the library doesn't depend on any async runtime, so all sleeping futures are woken by one timer thread.
+//It also stops JavaScript expressions which run too long
+pub(crate) static TIMER: Lazy<&'static Timer> = Lazy::new(|| {
+    let timer: &'static Timer = Box::leak(Box::default());
+    thread::Builder::new()
+        .name(String::from("clock-timer"))
+        .spawn(move || timer.run())
+        .expect("Failed to start timer thread");
+    timer
+});
+
+#[derive(Default)]
+pub(crate) struct Timer {
+    tasks: Mutex<Tasks>,
+    changed: Condvar,
+}
+
+type Action = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct Tasks {
+    next_id: u64,
+    deadlines: BinaryHeap<Reverse<(Instant, u64)>>,
+    actions: HashMap<u64, Action>,
+}
+
+impl Timer {
+    ///Runs the action on the timer thread at the deadline, unless it's cancelled earlier.
+    ///Actions should be short (e.g. waking a future), as they delay the following ones
+    pub(crate) fn schedule(
+        &self,
+        deadline: Instant,
+        action: impl FnOnce() + Send + 'static,
+    ) -> u64 {
+        let mut tasks = self.lock();
+        let id = tasks.next_id;
+        tasks.next_id += 1;
+        tasks.deadlines.push(Reverse((deadline, id)));
+        tasks.actions.insert(id, Box::new(action));
+        drop(tasks);
+        self.changed.notify_one();
+        id
+    }
+
+    ///Once it returns, the action is either finished or will never run
+    pub(crate) fn cancel(&self, id: u64) {
+        self.lock().actions.remove(&id);
+    }
+
+    //actions are run with the lock held, so that cancel can wait for them
+    fn run(&self) {
+        let mut tasks = self.lock();
+        loop {
+            let now = Instant::now();
+            while let Some(Reverse((deadline, id))) = tasks.deadlines.peek().copied() {
+                if deadline > now {
+                    break;
+                }
+                tasks.deadlines.pop();
+                if let Some(action) = tasks.actions.remove(&id) {
+                    action();
+                }
+            }
+            tasks = match tasks.deadlines.peek() {
+                Some(Reverse((deadline, _))) => {
+                    let timeout = deadline.saturating_duration_since(now);
+                    self.changed
+                        .wait_timeout(tasks, timeout)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+                None => self
+                    .changed
+                    .wait(tasks)
+                    .unwrap_or_else(PoisonError::into_inner),
+            };
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Tasks> {
+        self.tasks.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+///Time changes only when it's advanced or when something sleeps, so that tests are deterministic
+#[derive(Default)]
+pub struct ManualClock(AtomicU64);
+
+impl ManualClock {
+    pub fn new(now: u64) -> ManualClock {
+        ManualClock(AtomicU64::new(now))
+    }
+
+    pub fn advance(&self, millis: u64) {
+        self.0.fetch_add(millis, Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    async fn sleep(&self, millis: u64) {
+        self.advance(millis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::join_all;
+    use tokio_test::block_on;
+
+    use super::{Clock, ManualClock, SystemClock};
+
+    #[test]
+    fn test_sleep() {
+        let clock = ManualClock::new(100);
+        block_on(clock.sleep(20));
+        clock.advance(5);
+        assert_eq!(clock.now(), 125);
+
+        let start = SystemClock.now();
+        block_on(SystemClock.sleep(20));
+        assert!(SystemClock.now() >= start + 20);
+    }
+
+    #[test]
+    fn test_concurrent_sleeps() {
+        let start = SystemClock.now();
+        let finished = block_on(join_all([60, 20, 40, 0].map(|millis| async move {
+            SystemClock.sleep(millis).await;
+            (millis, SystemClock.now())
+        })));
+        for (millis, now) in finished {
+            assert!(now >= start + millis);
+        }
+    }
+}
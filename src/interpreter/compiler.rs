@@ -1,44 +1,175 @@
+use self::lifecycle::{LifecycleInterpreter, OpenedNode};
 use super::{
-    data::{CompilationVarContext, ScenarioCompilationError},
+    clock::{Clock, SystemClock},
+    data::{
+        CompilationVarContext, CompilationWarning, FieldTypes, ScenarioCompilationError,
+        VarContext, VarType, VarValue,
+    },
+    state::{InMemoryStateStore, StateStore},
+    workers::WorkerPool,
     CompilationResult, Interpreter,
 };
 use crate::{
-    customnodes::ForEach,
-    expression::LanguageParser,
-    scenariomodel::{Node, Node::*, NodeId, Scenario},
+    customnodes::{
+        Collect, Deduplicate, ForEach, LimitAction, Lookup, RateLimit, Window, WindowAggregate,
+    },
+    expression::{CompiledExpression, ConstantExpression, LanguageParser},
+    scenariomodel::{Expression, Node, Node::*, NodeId, Scenario},
+};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    sync::Arc,
 };
-use std::{collections::HashMap, sync::Arc};
 
 ///The compiler can be customized with additional language runtimes and additional custom components.
-/// By default, simple javascript language parser and for-each components are provided
+/// By default, simple javascript language parser, for-each, lookup, windowed aggregate, deduplicate
+/// and rate limit components are provided
 pub struct Compiler {
     custom_nodes: HashMap<String, Arc<dyn super::CustomNode>>,
+    //types of nodes provided by the library, which are recreated when e.g. the clock changes - unlike the ones
+    //registered by the user
+    default_node_types: HashSet<String>,
     parser: LanguageParser,
+    //configuration of custom nodes, by node type
+    config: HashMap<String, VarValue>,
+    //if set, split branches and for-each elements run on the workers
+    workers: Option<Arc<WorkerPool>>,
+    //used by stateful components
+    state_store: Arc<dyn StateStore>,
+    clock: Arc<dyn Clock>,
 }
 
 impl Default for Compiler {
     fn default() -> Compiler {
-        let for_each: Arc<dyn super::CustomNode> = Arc::new(ForEach);
         Compiler {
-            custom_nodes: HashMap::from([(String::from("forEach"), for_each)]),
+            custom_nodes: HashMap::new(),
+            default_node_types: HashSet::new(),
             parser: LanguageParser::default(),
+            config: HashMap::new(),
+            workers: None,
+            state_store: Arc::new(InMemoryStateStore::default()),
+            clock: Arc::new(SystemClock),
         }
+        .with_default_node("forEach", Arc::new(ForEach::default()))
+        .with_default_node("collect", Arc::new(Collect))
+        .with_default_node("lookup", Arc::new(Lookup::default()))
+        .with_stateful_nodes()
     }
 }
 
 impl Compiler {
+    pub fn with_language_parser(mut self, parser: LanguageParser) -> Compiler {
+        self.parser = parser;
+        self
+    }
+
+    pub fn with_custom_node(
+        mut self,
+        node_type: &str,
+        node: Arc<dyn super::CustomNode>,
+    ) -> Compiler {
+        self.default_node_types.remove(node_type);
+        self.custom_nodes.insert(String::from(node_type), node);
+        self
+    }
+
+    //nodes registered by the user are not replaced
+    fn with_default_node(mut self, node_type: &str, node: Arc<dyn super::CustomNode>) -> Compiler {
+        if self.default_node_types.contains(node_type) || !self.custom_nodes.contains_key(node_type)
+        {
+            self.default_node_types.insert(String::from(node_type));
+            self.custom_nodes.insert(String::from(node_type), node);
+        }
+        self
+    }
+
+    ///Configuration is passed to custom nodes of given type when they are opened
+    pub fn with_custom_node_config(mut self, node_type: &str, config: VarValue) -> Compiler {
+        self.config.insert(String::from(node_type), config);
+        self
+    }
+
+    ///Split branches and for-each elements (unless forEach node was replaced by the user) are run
+    ///in parallel on the workers, each having its own JavaScript runtime.
+    ///The workers run these parts of the scenario with a simple executor (`futures::executor::block_on`), outside of any
+    ///async runtime - so custom nodes which need e.g. tokio reactor or timers cannot be used in them
+    pub fn with_worker_pool(mut self, workers: Arc<WorkerPool>) -> Compiler {
+        self.workers = Some(workers.clone());
+        self.with_default_node("forEach", Arc::new(ForEach::with_workers(workers)))
+    }
+
+    ///State of stateful components (e.g. aggregates) is kept in the store, in memory by default
+    pub fn with_state_store(mut self, store: Arc<dyn StateStore>) -> Compiler {
+        self.state_store = store;
+        self.with_stateful_nodes()
+    }
+
+    ///Time used by stateful components (when it's not given in parameters), system time by default
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Compiler {
+        self.clock = clock;
+        self.with_stateful_nodes()
+    }
+
+    fn with_stateful_nodes(self) -> Compiler {
+        let (store, clock) = (self.state_store.clone(), self.clock.clone());
+        let windows = [
+            ("aggregateTumbling", Window::Tumbling),
+            ("aggregateSliding", Window::Sliding),
+            ("aggregateSession", Window::Session),
+        ];
+        let limits = [
+            ("rateLimitDrop", LimitAction::Drop),
+            ("rateLimitDelay", LimitAction::Delay),
+            ("rateLimitRoute", LimitAction::Route),
+        ];
+        let compiler = windows
+            .into_iter()
+            .fold(self, |compiler, (node_type, window)| {
+                compiler.with_default_node(
+                    node_type,
+                    Arc::new(WindowAggregate::new(window, store.clone(), clock.clone())),
+                )
+            });
+        let compiler = limits
+            .into_iter()
+            .fold(compiler, |compiler, (node_type, action)| {
+                compiler.with_default_node(
+                    node_type,
+                    Arc::new(RateLimit::new(action, store.clone(), clock.clone())),
+                )
+            });
+        compiler.with_default_node(
+            "deduplicate",
+            Arc::new(Deduplicate::new(store.clone(), clock.clone())),
+        )
+    }
+
     pub fn compile(&self, scenario: &Scenario) -> CompilationResult {
+        self.compile_with_warnings(scenario)
+            .map(|(interpreter, _)| interpreter)
+    }
+
+    pub fn compile_with_warnings(
+        &self,
+        scenario: &Scenario,
+    ) -> Result<(Box<dyn Interpreter>, Vec<CompilationWarning>), ScenarioCompilationError> {
         let nodes = &scenario.nodes;
         let initial_input = CompilationVarContext::default();
-        return match nodes.first() {
+        let state = RefCell::new(CompilationState::default());
+        let interpreter = match nodes.first() {
             //in fact, the source is not needed here, just a marker node.
             //in real implementation it has some parameters etc. Here it's left just for JSON model compatibility
-            Some(Source { id }) => self.compile_next(id, &nodes[1..], &initial_input),
+            Some(Source { id }) => self.compile_next(id, &nodes[1..], &initial_input, &state),
             Some(other) => Err(ScenarioCompilationError::FirstNodeNotSource(
                 other.id().clone(),
             )),
             None => Err(ScenarioCompilationError::EmptyScenario()),
-        };
+        }?;
+        let state = state.into_inner();
+        let interpreter =
+            LifecycleInterpreter::new(interpreter, scenario.meta_data.clone(), state.custom_nodes);
+        Ok((Box::new(interpreter), state.warnings))
     }
 
     fn compile_next(
@@ -46,9 +177,10 @@ impl Compiler {
         node_id: &NodeId,
         next_nodes: &[Node],
         var_names: &CompilationVarContext,
+        state: &RefCell<CompilationState>,
     ) -> CompilationResult {
         match next_nodes.first() {
-            Some(first) => self.compile_next_node(first, &next_nodes[1..], var_names),
+            Some(first) => self.compile_next_node(first, &next_nodes[1..], var_names, state),
             None => Err(ScenarioCompilationError::InvalidEnd(node_id.clone())),
         }
     }
@@ -58,13 +190,16 @@ impl Compiler {
         head: &Node,
         next_nodes: &[Node],
         var_names: &CompilationVarContext,
+        state: &RefCell<CompilationState>,
     ) -> CompilationResult {
         let ctx = CompilationContext {
             parser: &self.parser,
             var_names,
             rest: next_nodes,
             node_id: head.id(),
-            compiler: &|nds, ctx| self.compile_next(head.id(), nds, ctx),
+            compiler: &|nds, ctx| self.compile_next(head.id(), nds, ctx, state),
+            state,
+            workers: self.workers.as_ref(),
         };
         match head {
             Filter { id: _, expression } => filter::compile(ctx, expression),
@@ -73,20 +208,31 @@ impl Compiler {
                 var_name,
                 value,
             } => variable::compile(ctx, var_name, value),
+            RecordVariable {
+                id: _,
+                var_name,
+                fields,
+            } => recordvariable::compile(ctx, var_name, fields),
             Switch { id: _, nexts } => switch::compile(ctx, nexts),
             Split { id: _, nexts } => split::compile(ctx, nexts),
             Sink { id } => sink::compile(ctx, id),
+            RemoveVariables { id: _, var_names } => removevariables::compile(ctx, var_names),
             CustomNode {
                 id,
                 output_var,
                 node_type,
                 parameters,
-            } => customnode::compile(
-                ctx,
-                output_var,
-                parameters,
-                self.custom_node(id, node_type)?,
-            ),
+            } => {
+                let implementation = self.custom_node(id, node_type)?;
+                let config = self.config.get(node_type).cloned().unwrap_or_default();
+                customnode::compile(
+                    ctx,
+                    output_var.as_deref(),
+                    parameters,
+                    implementation,
+                    config,
+                )
+            }
             other => Err(ScenarioCompilationError::UnknownNode(other.id().clone())),
         }
     }
@@ -107,6 +253,9 @@ impl Compiler {
 
 mod customnode;
 mod filter;
+mod lifecycle;
+mod recordvariable;
+mod removevariables;
 mod sink;
 mod split;
 mod switch;
@@ -118,9 +267,113 @@ struct CompilationContext<'a> {
     var_names: &'a CompilationVarContext,
     rest: &'a [Node],
     node_id: &'a NodeId,
+    state: &'a RefCell<CompilationState>,
+    workers: Option<&'a Arc<WorkerPool>>,
+}
+
+//gathered while compiling the whole scenario
+#[derive(Default)]
+struct CompilationState {
+    warnings: Vec<CompilationWarning>,
+    //variables referenced in expressions compiled so far, in the current scope
+    used_variables: HashSet<String>,
+    //custom nodes which have to be opened before running the scenario
+    custom_nodes: Vec<OpenedNode>,
 }
 
 impl CompilationContext<'_> {
+    ///Expressions which don't depend on the context are evaluated once, here
+    fn parse(
+        &self,
+        expression: &Expression,
+    ) -> Result<Box<dyn CompiledExpression>, ScenarioCompilationError> {
+        self.parse_with_vars(expression, self.var_names)
+    }
+
+    fn parse_with_vars(
+        &self,
+        expression: &Expression,
+        var_names: &CompilationVarContext,
+    ) -> Result<Box<dyn CompiledExpression>, ScenarioCompilationError> {
+        let compiled = self.parser.parse(self.node_id, expression, var_names)?;
+        self.mark_used(compiled.used_variables());
+        if !is_foldable(compiled.as_ref()) {
+            return Ok(compiled);
+        }
+        //if it fails, we leave it to fail at runtime (with the same error), as it used to
+        Ok(match compiled.execute(&VarContext::empty()) {
+            Ok(value) => Box::new(ConstantExpression(value)),
+            Err(_) => compiled,
+        })
+    }
+
+    fn mark_used(&self, variables: Option<Vec<String>>) {
+        let used = &mut self.state.borrow_mut().used_variables;
+        match variables {
+            Some(variables) => used.extend(variables),
+            //we cannot tell what is used, so we assume everything is
+            None => used.extend(self.var_names.0.keys().cloned()),
+        }
+    }
+
+    ///Compiles rest of the scenario with new variable, reporting if it shadows another one or is not used
+    fn compile_rest_with_var(&self, var_name: &str, var_type: VarType) -> CompilationResult {
+        self.compile_with_var(self.rest, var_name, var_type)
+    }
+
+    fn compile_with_var(
+        &self,
+        nodes: &[Node],
+        var_name: &str,
+        var_type: VarType,
+    ) -> CompilationResult {
+        let var_names = self
+            .var_names
+            .with_typed_var(self.node_id, var_name, var_type)?;
+        self.compile_with_var_names(nodes, var_name, &var_names)
+    }
+
+    ///Like `compile_rest_with_var`, expressions in the rest can only access the given fields of the record
+    fn compile_rest_with_record(&self, var_name: &str, fields: FieldTypes) -> CompilationResult {
+        let var_names = self
+            .var_names
+            .with_record_var(self.node_id, var_name, fields)?;
+        self.compile_with_var_names(self.rest, var_name, &var_names)
+    }
+
+    fn compile_with_var_names(
+        &self,
+        nodes: &[Node],
+        var_name: &str,
+        var_names: &CompilationVarContext,
+    ) -> CompilationResult {
+        if self.var_names.0.contains_key(var_name) {
+            self.warn(CompilationWarning::ShadowedVariable {
+                node_id: self.node_id.clone(),
+                var_name: String::from(var_name),
+            });
+        }
+        //usages in the rest refer to the new variable, so we have to restore usage of the shadowed one afterwards
+        let used_before = self.state.borrow_mut().used_variables.remove(var_name);
+        let rest = (self.compiler)(nodes, var_names)?;
+        let mut state = self.state.borrow_mut();
+        let used = state.used_variables.remove(var_name);
+        if used_before {
+            state.used_variables.insert(String::from(var_name));
+        }
+        if !used {
+            state.warnings.push(CompilationWarning::UnusedVariable {
+                node_id: self.node_id.clone(),
+                var_name: String::from(var_name),
+            });
+        }
+        Ok(rest)
+    }
+
+    fn warn(&self, warning: CompilationWarning) {
+        self.state.borrow_mut().warnings.push(warning);
+    }
+
     fn assert_end(&self, value: Box<dyn Interpreter>) -> CompilationResult {
         if self.rest.is_empty() {
             Ok(value)
@@ -133,32 +386,64 @@ impl CompilationContext<'_> {
     }
 }
 
+//value of the expression, if it's known during compilation
+//expressions claiming to be constant are evaluated during compilation only if they surely don't read variables
+fn is_foldable(expression: &dyn CompiledExpression) -> bool {
+    expression.is_constant()
+        && matches!(expression.used_variables(), Some(variables) if variables.is_empty())
+}
+
+fn constant_value(expression: &dyn CompiledExpression) -> Option<VarValue> {
+    if is_foldable(expression) {
+        expression.execute(&VarContext::empty()).ok()
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 //These tests are a bit too high-level (at least some of them), but I've figured out how to split compiler only at last time
-mod tests {
+pub(crate) mod tests {
+    use crate::{
+        expression::{CompiledExpression, LanguageParser, ParseError, Parser, ResultType},
+        interpreter::data::CompilationWarning,
+    };
+    use crate::{
+        interpreter::{
+            clock::ManualClock, data::CompilationVarContext, workers::WorkerPool,
+            CompilationResult, CustomNode, Interpreter, LazyParameters,
+        },
+        scenariomodel::{Node, NodeId, Parameter},
+    };
     use crate::{
         interpreter::{
             compiler::Compiler,
-            data::{ScenarioOutput, SingleScenarioOutput, VarContext, DEFAULT_INPUT_NAME},
+            data::{
+                ScenarioOutput, ScenarioRuntimeError, SingleScenarioOutput, VarContext,
+                DEFAULT_INPUT_NAME,
+            },
         },
         scenariomodel::{
-            Expression, MetaData,
-            Node::{Filter, Sink, Source, Variable},
+            Case, Expression, MetaData,
+            Node::{Filter, Sink, Source, Split, Switch, Variable},
             Scenario,
         },
     };
-    use crate::{
-        interpreter::{data::CompilationVarContext, CompilationResult},
-        scenariomodel::{Node, NodeId},
-    };
+    use async_trait::async_trait;
     use serde_json::json;
     use serde_json::Value;
-    use std::collections::HashMap;
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
     use tokio_test::block_on;
 
     pub fn compile_node(node: Node, rest: &[Node]) -> CompilationResult {
         let var_ctx = CompilationVarContext::default();
-        Compiler::default().compile_next_node(&node, rest, &var_ctx)
+        Compiler::default().compile_next_node(&node, rest, &var_ctx, &Default::default())
     }
 
     pub fn js(value: &str) -> Expression {
@@ -168,12 +453,52 @@ mod tests {
         }
     }
 
+    pub fn simple(value: &str) -> Expression {
+        Expression {
+            language: String::from("simple"),
+            expression: String::from(value),
+        }
+    }
+
     pub fn sink(id: &NodeId) -> Vec<Node> {
         vec![{ Node::Sink { id: id.clone() } }]
     }
 
-    fn compile_invoke_to_output(node: Node, input: Value) -> ScenarioOutput {
-        let scenario = Scenario {
+    pub fn parameter(name: &str, expression: &str) -> Parameter {
+        Parameter {
+            name: String::from(name),
+            expression: simple(expression),
+        }
+    }
+
+    ///Custom node (with id equal to its type) between the source and "sink"
+    pub fn custom_node_scenario(
+        node_type: &str,
+        output_var: Option<&str>,
+        parameters: Vec<Parameter>,
+    ) -> Scenario {
+        scenario_with(Node::CustomNode {
+            id: NodeId::new(node_type),
+            output_var: output_var.map(String::from),
+            node_type: String::from(node_type),
+            parameters,
+        })
+    }
+
+    pub fn run(interpreter: &dyn Interpreter, input: Value) -> ScenarioOutput {
+        block_on(interpreter.run(&VarContext::default_context_for_value(input))).unwrap()
+    }
+
+    ///Value of the variable in the only output of "sink"
+    pub fn single_output(interpreter: &dyn Interpreter, input: Value, var_name: &str) -> Value {
+        let output = run(interpreter, input);
+        let values = output.var_in_sink(&NodeId::new("sink"), var_name);
+        assert_eq!(values.len(), 1);
+        values[0].cloned().unwrap_or_default()
+    }
+
+    fn scenario_with(node: Node) -> Scenario {
+        Scenario {
             meta_data: MetaData {
                 id: String::from(""),
             },
@@ -186,8 +511,11 @@ mod tests {
                     id: NodeId::new("sink"),
                 },
             ],
-        };
-        let compiled_scenario = Compiler::default().compile(&scenario).unwrap();
+        }
+    }
+
+    fn compile_invoke_to_output(node: Node, input: Value) -> ScenarioOutput {
+        let compiled_scenario = Compiler::default().compile(&scenario_with(node)).unwrap();
         block_on(compiled_scenario.run(&VarContext::default_context_for_value(input))).unwrap()
     }
 
@@ -236,4 +564,255 @@ mod tests {
         let output_false = compile_invoke_to_output(node, json!(11));
         assert_eq!(output_false, ScenarioOutput(vec![]))
     }
+
+    //drops every record
+    struct Dropping;
+
+    #[async_trait]
+    impl CustomNode for Dropping {
+        async fn run(
+            &self,
+            _node_id: &NodeId,
+            _output_var: Option<&str>,
+            _parameters: &HashMap<String, Value>,
+            _lazy_parameters: &LazyParameters,
+            _input: &VarContext,
+            _next_part: &Arc<dyn Interpreter>,
+        ) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+            Ok(ScenarioOutput(vec![]))
+        }
+
+        fn output_type(
+            &self,
+            _parameter_types: &HashMap<String, ResultType>,
+        ) -> Option<ResultType> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_user_nodes_not_replaced() {
+        let compiler = Compiler::default()
+            .with_custom_node("forEach", Arc::new(Dropping))
+            .with_custom_node("deduplicate", Arc::new(Dropping))
+            .with_worker_pool(Arc::new(WorkerPool::new(1)))
+            .with_clock(Arc::new(ManualClock::new(0)));
+        for node_type in ["forEach", "deduplicate"] {
+            let scenario = custom_node_scenario(node_type, None, vec![parameter("key", "1")]);
+            let interpreter = compiler.compile(&scenario).unwrap();
+            assert_eq!(run(interpreter.as_ref(), json!(1)), ScenarioOutput(vec![]));
+        }
+    }
+
+    #[test]
+    fn test_runtime_error_contains_node_id() {
+        let compiled = compile_node(
+            Variable {
+                id: NodeId::new("var"),
+                var_name: String::from("new_var"),
+                value: js("input.foo.bar"),
+            },
+            &sink(&NodeId::new("sink")),
+        )
+        .unwrap();
+        match block_on(compiled.run(&VarContext::default_context_for_value(json!({})))) {
+            Err(ScenarioRuntimeError::NodeError { node_id, error: _ }) => {
+                assert_eq!(node_id, NodeId::new("var"))
+            }
+            other => panic!("Unexpected: {:?}", other),
+        }
+    }
+
+    //constant expression, counting its executions
+    struct CountingParser(Arc<AtomicUsize>);
+
+    struct CountingExpression(Arc<AtomicUsize>);
+
+    impl Parser for CountingParser {
+        fn parse(
+            &self,
+            _: &str,
+            _: &CompilationVarContext,
+        ) -> Result<Box<dyn CompiledExpression>, Box<dyn ParseError>> {
+            Ok(Box::new(CountingExpression(self.0.clone())))
+        }
+    }
+
+    impl CompiledExpression for CountingExpression {
+        fn execute(&self, _: &VarContext) -> Result<Value, ScenarioRuntimeError> {
+            Ok(json!(self.0.fetch_add(1, Ordering::SeqCst)))
+        }
+
+        fn used_variables(&self) -> Option<Vec<String>> {
+            Some(vec![])
+        }
+
+        fn is_constant(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_constant_evaluated_once() {
+        let executions = Arc::new(AtomicUsize::new(0));
+        let parser = LanguageParser::default()
+            .with_parser("counting", Box::new(CountingParser(executions.clone())));
+        let node = Variable {
+            id: NodeId::new("var"),
+            var_name: String::from("counter"),
+            value: Expression {
+                language: String::from("counting"),
+                expression: String::from(""),
+            },
+        };
+        let compiled = Compiler::default()
+            .with_language_parser(parser)
+            .compile(&scenario_with(node))
+            .unwrap();
+        for _ in 0..3 {
+            let output =
+                block_on(compiled.run(&VarContext::default_context_for_value(json!(1)))).unwrap();
+            assert_eq!(
+                output.var_in_sink(&NodeId::new("sink"), "counter"),
+                [Some(&json!(0))]
+            );
+        }
+        assert_eq!(executions.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_only_literals_folded() {
+        //it would never end if it was evaluated during compilation
+        let looping = Filter {
+            id: NodeId::new("filter"),
+            expression: js("(() => { while (true) {} })()"),
+        };
+        assert!(Compiler::default().compile(&scenario_with(looping)).is_ok());
+
+        let random = Variable {
+            id: NodeId::new("var"),
+            var_name: String::from("random"),
+            value: js("''.constructor.constructor('return Math.random()')()"),
+        };
+        let compiled = Compiler::default().compile(&scenario_with(random)).unwrap();
+        let random = || {
+            block_on(compiled.run(&VarContext::default_context_for_value(json!(1))))
+                .unwrap()
+                .var_in_sink(&NodeId::new("sink"), "random")[0]
+                .cloned()
+        };
+        assert_ne!(random(), random());
+    }
+
+    #[test]
+    fn test_shadowed_variable_not_folded() {
+        let node = Variable {
+            id: NodeId::new("var"),
+            var_name: String::from("list"),
+            value: js("[1].map(input => input).concat(input)"),
+        };
+        let compiled = Compiler::default().compile(&scenario_with(node)).unwrap();
+        for input in [2, 3] {
+            let output =
+                block_on(compiled.run(&VarContext::default_context_for_value(json!(input))))
+                    .unwrap();
+            assert_eq!(
+                output.var_in_sink(&NodeId::new("sink"), "list"),
+                [Some(&json!([1, input]))]
+            );
+        }
+    }
+
+    #[test]
+    fn test_always_false_filter_warning() {
+        let node = Filter {
+            id: NodeId::new("filter"),
+            expression: js("1 > 2"),
+        };
+        let (compiled, warnings) = Compiler::default()
+            .compile_with_warnings(&scenario_with(node))
+            .unwrap();
+        assert_eq!(
+            warnings,
+            vec![CompilationWarning::FilterAlwaysFalse(NodeId::new("filter"))]
+        );
+        let output =
+            block_on(compiled.run(&VarContext::default_context_for_value(json!(1)))).unwrap();
+        assert_eq!(output, ScenarioOutput(vec![]));
+    }
+
+    #[test]
+    fn test_warnings() {
+        let variable = |id: &str, var_name: &str, value: &str| Variable {
+            id: NodeId::new(id),
+            var_name: String::from(var_name),
+            value: js(value),
+        };
+        let case = |expression: &str, sink_id: &str| Case {
+            expression: js(expression),
+            nodes: sink(&NodeId::new(sink_id)),
+        };
+        let scenario = Scenario {
+            meta_data: MetaData {
+                id: String::from(""),
+            },
+            nodes: vec![
+                Source {
+                    id: NodeId::new("source"),
+                },
+                variable("unused", "unused", "input"),
+                variable("used", "used", "input"),
+                Split {
+                    id: NodeId::new("split"),
+                    nexts: vec![
+                        vec![
+                            variable("shadowing", "used", "used + 1"),
+                            Filter {
+                                id: NodeId::new("filter"),
+                                expression: js("used > 0"),
+                            },
+                            Switch {
+                                id: NodeId::new("switch"),
+                                nexts: vec![
+                                    case("input > 0", "sink1"),
+                                    case("true", "sink2"),
+                                    case("input < 0", "sink3"),
+                                ],
+                            },
+                        ],
+                        vec![
+                            Filter {
+                                id: NodeId::new("constant"),
+                                expression: js("1 + 1 == 2"),
+                            },
+                            Sink {
+                                id: NodeId::new("sink4"),
+                            },
+                        ],
+                    ],
+                },
+            ],
+        };
+        let (_, warnings) = Compiler::default()
+            .compile_with_warnings(&scenario)
+            .unwrap();
+        assert_eq!(
+            warnings,
+            vec![
+                CompilationWarning::ShadowedVariable {
+                    node_id: NodeId::new("shadowing"),
+                    var_name: String::from("used")
+                },
+                CompilationWarning::UnreachableCase {
+                    node_id: NodeId::new("switch"),
+                    case: 2
+                },
+                CompilationWarning::FilterAlwaysTrue(NodeId::new("constant")),
+                CompilationWarning::UnusedVariable {
+                    node_id: NodeId::new("unused"),
+                    var_name: String::from("unused")
+                },
+            ]
+        );
+    }
 }
@@ -1,56 +1,199 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use async_trait::async_trait;
 
 use crate::{
-    expression::CompiledExpression,
+    expression::{CompiledExpression, ConstantExpression, ResultType},
     interpreter::{
         data::{
             ScenarioCompilationError, ScenarioOutput, ScenarioRuntimeError, VarContext, VarValue,
         },
-        CompilationResult, CustomNode, Interpreter,
+        CompilationResult, CustomNode, Interpreter, LazyParameters, ParameterDefinition,
     },
-    scenariomodel::Parameter,
+    scenariomodel::{NodeId, Parameter},
 };
 
-use super::CompilationContext;
+use super::{constant_value, is_foldable, CompilationContext, OpenedNode};
 
 struct CompiledCustomNode {
-    rest: Box<dyn Interpreter>,
-    output_var: String,
+    node_id: NodeId,
+    rest: Arc<dyn Interpreter>,
+    output_var: Option<String>,
     params: HashMap<String, Box<dyn CompiledExpression>>,
+    lazy_params: LazyParameters,
     custom_node: Arc<dyn CustomNode>,
 }
 
 pub(super) fn compile(
     ctx: CompilationContext,
-    output_var: &str,
+    output_var: Option<&str>,
     parameters: &[Parameter],
     implementation: &Arc<dyn CustomNode>,
+    config: VarValue,
 ) -> CompilationResult {
-    let next_part = (ctx.compiler)(ctx.rest, &ctx.var_names.with_var(ctx.node_id, output_var)?)?;
+    let mut names = HashSet::new();
+    if let Some(duplicate) = parameters.iter().find(|p| !names.insert(p.name.as_str())) {
+        return Err(ScenarioCompilationError::DuplicateParameter {
+            node_id: ctx.node_id.clone(),
+            name: duplicate.name.clone(),
+        });
+    }
+    let definitions = implementation.parameters();
+    let definition = |name: &str| {
+        definitions
+            .iter()
+            .flatten()
+            .find(|definition| definition.name == name)
+    };
     let compiled_parameters: Result<
         HashMap<String, Box<dyn CompiledExpression>>,
         ScenarioCompilationError,
     > = parameters
         .iter()
-        .map(|p| compile_parameter(&ctx, p))
+        .map(|p| compile_parameter(&ctx, p, definition(&p.name)))
+        .collect();
+    let mut compiled_parameters = compiled_parameters?;
+    if let Some(definitions) = &definitions {
+        check_parameters(&ctx, parameters, definitions, &mut compiled_parameters)?;
+    }
+    let constant_parameters = compiled_parameters
+        .iter()
+        .filter_map(|(name, expression)| {
+            constant_value(expression.as_ref()).map(|value| (name.clone(), value))
+        })
+        .collect();
+    implementation
+        .validate(ctx.node_id, &constant_parameters)
+        .map_err(|error| ScenarioCompilationError::InvalidNode {
+            node_id: ctx.node_id.clone(),
+            error,
+        })?;
+    ctx.state.borrow_mut().custom_nodes.push(OpenedNode {
+        node_id: ctx.node_id.clone(),
+        config,
+        parameters: constant_parameters,
+        implementation: implementation.clone(),
+    });
+    let parameter_types = compiled_parameters
+        .iter()
+        .map(|(name, expression)| (name.clone(), expression.result_type()))
         .collect();
+    let next_part = match (output_var, implementation.output_type(&parameter_types)) {
+        (Some(output_var), Some(output_type)) => {
+            ctx.compile_rest_with_var(output_var, output_type)?
+        }
+        (None, None) => (ctx.compiler)(ctx.rest, ctx.var_names)?,
+        (None, Some(_)) => {
+            return Err(ScenarioCompilationError::MissingOutputVariable(
+                ctx.node_id.clone(),
+            ))
+        }
+        (Some(_), None) => {
+            return Err(ScenarioCompilationError::UnexpectedOutputVariable(
+                ctx.node_id.clone(),
+            ))
+        }
+    };
+    let (lazy_params, params) = compiled_parameters
+        .into_iter()
+        .partition(|(name, _)| matches!(definition(name), Some(d) if d.lazy));
     Ok(Box::new(CompiledCustomNode {
-        rest: next_part,
-        output_var: String::from(output_var),
-        params: compiled_parameters?,
+        node_id: ctx.node_id.clone(),
+        rest: Arc::from(next_part),
+        output_var: output_var.map(String::from),
+        params,
+        lazy_params,
         custom_node: implementation.clone(),
     }))
 }
 
+//defaults of missing parameters are added to compiled ones
+fn check_parameters(
+    ctx: &CompilationContext,
+    parameters: &[Parameter],
+    definitions: &[ParameterDefinition],
+    compiled: &mut HashMap<String, Box<dyn CompiledExpression>>,
+) -> Result<(), ScenarioCompilationError> {
+    let node_id = || ctx.node_id.clone();
+    if let Some(unknown) = parameters
+        .iter()
+        .find(|p| !definitions.iter().any(|d| d.name == p.name))
+    {
+        return Err(ScenarioCompilationError::UnknownParameter {
+            node_id: node_id(),
+            name: unknown.name.clone(),
+        });
+    }
+    for definition in definitions {
+        let name = || definition.name.clone();
+        let expression = match compiled.get(&definition.name) {
+            Some(expression) => expression,
+            None if definition.required => {
+                return Err(ScenarioCompilationError::MissingParameter {
+                    node_id: node_id(),
+                    name: name(),
+                })
+            }
+            None => {
+                if let Some(default) = &definition.default {
+                    compiled.insert(name(), Box::new(ConstantExpression(default.clone())));
+                }
+                continue;
+            }
+        };
+        if definition.literal && !is_foldable(expression.as_ref()) {
+            return Err(ScenarioCompilationError::ParameterNotLiteral {
+                node_id: node_id(),
+                name: name(),
+            });
+        }
+        let actual = expression.result_type();
+        let matches = match (definition.expected_type, actual) {
+            (ResultType::Unknown, _) | (_, ResultType::Unknown) => true,
+            (_, ResultType::Null) => !definition.required,
+            (expected, actual) => expected == actual,
+        };
+        if !matches {
+            return Err(ScenarioCompilationError::InvalidParameterType {
+                node_id: node_id(),
+                name: name(),
+                expected: definition.expected_type,
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
+
 fn compile_parameter(
     ctx: &CompilationContext,
     parameter: &Parameter,
+    definition: Option<&ParameterDefinition>,
 ) -> Result<(String, Box<dyn CompiledExpression>), ScenarioCompilationError> {
-    let compiled_expression =
-        ctx.parser
-            .parse(ctx.node_id, &parameter.expression, ctx.var_names)?;
+    //lazy parameters may use variables added by the node
+    let additional_variables: Vec<&String> = definition
+        .iter()
+        .flat_map(|d| &d.additional_variables)
+        .collect();
+    let mut var_names = ctx.var_names.clone();
+    for name in &additional_variables {
+        var_names = var_names.with_var(ctx.node_id, name)?;
+    }
+    //usages of additional variables don't count as usages of the outer ones with the same names
+    let used_before: Vec<bool> = additional_variables
+        .iter()
+        .map(|name| ctx.state.borrow().used_variables.contains(*name))
+        .collect();
+    let compiled_expression = ctx.parse_with_vars(&parameter.expression, &var_names)?;
+    let used = &mut ctx.state.borrow_mut().used_variables;
+    for (name, used_before) in additional_variables.into_iter().zip(used_before) {
+        if !used_before {
+            used.remove(name);
+        }
+    }
     Ok((parameter.name.clone(), compiled_expression))
 }
 
@@ -64,7 +207,328 @@ impl Interpreter for CompiledCustomNode {
             .map(|e| e.1.execute(data).map(|r| (String::from(e.0), r)))
             .collect();
         self.custom_node
-            .run(&self.output_var, &parameters?, data, self.rest.as_ref())
+            .run(
+                &self.node_id,
+                self.output_var.as_deref(),
+                &parameters?,
+                &self.lazy_params,
+                data,
+                &self.rest,
+            )
             .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use crate::{
+        expression::ResultType,
+        interpreter::{
+            compiler::{tests, Compiler},
+            data::{
+                CompilationVarContext, CompilationWarning, ScenarioCompilationError,
+                ScenarioOutput, ScenarioRuntimeError, VarContext, VarValue,
+            },
+            CustomNode, Interpreter, LazyParameters, ParameterDefinition,
+        },
+        scenariomodel::{Expression, MetaData, Node, NodeId, Parameter, Scenario},
+    };
+    use async_trait::async_trait;
+    use serde_json::{json, Value};
+    use tokio_test::block_on;
+
+    //evaluates 'mapper' for each element of 'list'
+    struct MapList;
+
+    #[async_trait]
+    impl CustomNode for MapList {
+        async fn run(
+            &self,
+            _node_id: &NodeId,
+            output_var: Option<&str>,
+            parameters: &HashMap<String, VarValue>,
+            lazy_parameters: &LazyParameters,
+            input: &VarContext,
+            next_part: &Arc<dyn Interpreter>,
+        ) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+            let mapper = &lazy_parameters["mapper"];
+            let mapped: Result<Vec<Value>, ScenarioRuntimeError> = parameters["list"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|element| mapper.execute(&input.with_new_var("element", element.clone())))
+                .collect();
+            next_part
+                .run(&input.with_output_var(output_var, Value::Array(mapped?)))
+                .await
+        }
+
+        fn parameters(&self) -> Option<Vec<ParameterDefinition>> {
+            Some(vec![
+                ParameterDefinition::required("list"),
+                ParameterDefinition::required("mapper").lazy(&["element"]),
+            ])
+        }
+    }
+
+    fn for_each(parameters: &[(&str, &str)]) -> Result<(), ScenarioCompilationError> {
+        let node = Node::CustomNode {
+            id: NodeId::new("forEach"),
+            output_var: Some(String::from("element")),
+            node_type: String::from("forEach"),
+            parameters: parameters
+                .iter()
+                .map(|(name, expression)| Parameter {
+                    name: String::from(*name),
+                    expression: tests::js(expression),
+                })
+                .collect(),
+        };
+        tests::compile_node(node, &tests::sink(&NodeId::new("sink"))).map(|_| ())
+    }
+
+    #[test]
+    fn test_parameters_validation() {
+        assert!(for_each(&[("value", "[1, 2]")]).is_ok());
+        //type is not known during compilation
+        assert!(for_each(&[("value", "input")]).is_ok());
+        assert!(matches!(
+            for_each(&[]),
+            Err(ScenarioCompilationError::MissingParameter { name, .. }) if name == "value"
+        ));
+        assert!(matches!(
+            for_each(&[("value", "[]"), ("other", "1")]),
+            Err(ScenarioCompilationError::UnknownParameter { name, .. }) if name == "other"
+        ));
+        assert!(matches!(
+            for_each(&[("value", "[1]"), ("value", "[2]")]),
+            Err(ScenarioCompilationError::DuplicateParameter { name, .. }) if name == "value"
+        ));
+        assert!(matches!(
+            for_each(&[("value", "'text'")]),
+            Err(ScenarioCompilationError::InvalidParameterType {
+                expected: ResultType::List,
+                actual: ResultType::String,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_runs_with_valid_parameters() -> Result<(), Box<dyn std::error::Error>> {
+        let sink_id = NodeId::new("sink");
+        let node = Node::CustomNode {
+            id: NodeId::new("forEach"),
+            output_var: Some(String::from("element")),
+            node_type: String::from("forEach"),
+            parameters: vec![Parameter {
+                name: String::from("value"),
+                expression: tests::js("[input, input + 1]"),
+            }],
+        };
+        let compiled = tests::compile_node(node, &tests::sink(&sink_id))?;
+        let result = block_on(compiled.run(&VarContext::default_context_for_value(json!(1))))?;
+        assert_eq!(
+            result.var_in_sink(&sink_id, "element"),
+            [Some(&json!(1)), Some(&json!(2))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lazy_parameters() -> Result<(), Box<dyn std::error::Error>> {
+        let sink_id = NodeId::new("sink");
+        let map_list = |list: &str, mapper: &str| Node::CustomNode {
+            id: NodeId::new("mapList"),
+            output_var: Some(String::from("mapped")),
+            node_type: String::from("mapList"),
+            parameters: vec![
+                Parameter {
+                    name: String::from("list"),
+                    expression: tests::js(list),
+                },
+                Parameter {
+                    name: String::from("mapper"),
+                    expression: tests::js(mapper),
+                },
+            ],
+        };
+        let compile = |node: Node| {
+            Compiler::default()
+                .with_custom_node("mapList", Arc::new(MapList))
+                .compile_next_node(
+                    &node,
+                    &tests::sink(&sink_id),
+                    &CompilationVarContext::default(),
+                    &Default::default(),
+                )
+        };
+
+        let compiled = compile(map_list("[1, 2, 3]", "element * input"))?;
+        let result = block_on(compiled.run(&VarContext::default_context_for_value(json!(10))))?;
+        assert_eq!(
+            result.var_in_sink(&sink_id, "mapped"),
+            [Some(&json!([10, 20, 30]))]
+        );
+        //variables of lazy parameters are not available elsewhere
+        assert!(compile(map_list("[element]", "element")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lazy_parameter_variables_not_marked_used() {
+        let scenario = Scenario {
+            meta_data: MetaData {
+                id: String::from("mapList"),
+            },
+            nodes: vec![
+                Node::Source {
+                    id: NodeId::new("source"),
+                },
+                Node::Variable {
+                    id: NodeId::new("outer"),
+                    var_name: String::from("element"),
+                    value: tests::js("1"),
+                },
+                Node::CustomNode {
+                    id: NodeId::new("mapList"),
+                    output_var: Some(String::from("element")),
+                    node_type: String::from("mapList"),
+                    parameters: vec![
+                        Parameter {
+                            name: String::from("list"),
+                            expression: tests::js("[1, 2]"),
+                        },
+                        Parameter {
+                            name: String::from("mapper"),
+                            expression: tests::js("element * 2"),
+                        },
+                    ],
+                },
+                Node::Sink {
+                    id: NodeId::new("sink"),
+                },
+            ],
+        };
+        let (_, warnings) = Compiler::default()
+            .with_custom_node("mapList", Arc::new(MapList))
+            .compile_with_warnings(&scenario)
+            .unwrap();
+        assert!(warnings.contains(&CompilationWarning::UnusedVariable {
+            node_id: NodeId::new("outer"),
+            var_name: String::from("element"),
+        }));
+    }
+
+    //only side effects, no output variable
+    struct Log;
+
+    #[async_trait]
+    impl CustomNode for Log {
+        async fn run(
+            &self,
+            _node_id: &NodeId,
+            _output_var: Option<&str>,
+            _parameters: &HashMap<String, VarValue>,
+            _lazy_parameters: &LazyParameters,
+            input: &VarContext,
+            next_part: &Arc<dyn Interpreter>,
+        ) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+            next_part.run(input).await
+        }
+
+        fn output_type(&self, _: &HashMap<String, ResultType>) -> Option<ResultType> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_output_variable() {
+        let custom_node = |node_type: &str, output_var: Option<&str>| Node::CustomNode {
+            id: NodeId::new("custom"),
+            output_var: output_var.map(String::from),
+            node_type: String::from(node_type),
+            parameters: vec![Parameter {
+                name: String::from("list"),
+                expression: tests::js("[1]"),
+            }],
+        };
+        let compile = |node: Node, rest: &[Node]| {
+            Compiler::default()
+                .with_custom_node("log", Arc::new(Log))
+                .with_custom_node("counting", Arc::new(Counting))
+                .compile_next_node(
+                    &node,
+                    rest,
+                    &CompilationVarContext::default(),
+                    &Default::default(),
+                )
+        };
+        let sink = tests::sink(&NodeId::new("sink"));
+
+        assert!(compile(custom_node("log", None), &sink).is_ok());
+        assert!(matches!(
+            compile(custom_node("log", Some("logged")), &sink),
+            Err(ScenarioCompilationError::UnexpectedOutputVariable(_))
+        ));
+        assert!(matches!(
+            compile(custom_node("counting", None), &sink),
+            Err(ScenarioCompilationError::MissingOutputVariable(_))
+        ));
+    }
+
+    #[test]
+    fn test_typed_output_variable() {
+        let node = Node::CustomNode {
+            id: NodeId::new("counting"),
+            output_var: Some(String::from("counted")),
+            node_type: String::from("counting"),
+            parameters: vec![],
+        };
+        let filter = |expression: &str| Node::Filter {
+            id: NodeId::new("filter"),
+            expression: Expression {
+                language: String::from("simple"),
+                expression: String::from(expression),
+            },
+        };
+        let compile = |rest: &[Node]| {
+            Compiler::default()
+                .with_custom_node("counting", Arc::new(Counting))
+                .compile_next_node(
+                    &node,
+                    rest,
+                    &CompilationVarContext::default(),
+                    &Default::default(),
+                )
+        };
+        let sink = tests::sink(&NodeId::new("sink"));
+        assert!(compile(&[vec![filter("counted > 1")], sink.clone()].concat()).is_ok());
+        assert!(compile(&[vec![filter("counted.field > 1")], sink].concat()).is_err());
+    }
+
+    struct Counting;
+
+    #[async_trait]
+    impl CustomNode for Counting {
+        async fn run(
+            &self,
+            _node_id: &NodeId,
+            output_var: Option<&str>,
+            _parameters: &HashMap<String, VarValue>,
+            _lazy_parameters: &LazyParameters,
+            input: &VarContext,
+            next_part: &Arc<dyn Interpreter>,
+        ) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+            next_part
+                .run(&input.with_output_var(output_var, json!(1)))
+                .await
+        }
+
+        fn output_type(&self, _: &HashMap<String, ResultType>) -> Option<ResultType> {
+            Some(ResultType::Number)
+        }
+    }
+}
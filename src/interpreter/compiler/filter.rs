@@ -1,8 +1,8 @@
-use super::CompilationContext;
+use super::{constant_value, CompilationContext};
 use crate::{
     expression::CompiledExpression,
     interpreter::{
-        data::{ScenarioOutput, ScenarioRuntimeError, VarContext},
+        data::{CompilationWarning, ScenarioOutput, ScenarioRuntimeError, VarContext},
         CompilationResult, Interpreter,
     },
     scenariomodel::Expression,
@@ -17,11 +17,33 @@ struct CompiledFilter {
 
 pub(super) fn compile(ctx: CompilationContext, expression: &Expression) -> CompilationResult {
     let rest = (ctx.compiler)(ctx.rest, ctx.var_names)?;
-    let expression = ctx.parser.parse(ctx.node_id, expression, ctx.var_names)?;
+    let expression = ctx.parse(expression)?;
+    match constant_value(expression.as_ref()) {
+        Some(Bool(true)) => {
+            ctx.warn(CompilationWarning::FilterAlwaysTrue(ctx.node_id.clone()));
+            return Ok(rest);
+        }
+        Some(Bool(false)) => {
+            ctx.warn(CompilationWarning::FilterAlwaysFalse(ctx.node_id.clone()));
+            return Ok(Box::new(PrunedBranch));
+        }
+        //invalid type will fail at runtime
+        _ => (),
+    }
     let res = CompiledFilter { rest, expression };
     Ok(Box::new(res))
 }
 
+//rest of the scenario is still compiled, so that errors in it are reported
+struct PrunedBranch;
+
+#[async_trait]
+impl Interpreter for PrunedBranch {
+    async fn run(&self, _: &VarContext) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+        Ok(ScenarioOutput(vec![]))
+    }
+}
+
 #[async_trait]
 impl Interpreter for CompiledFilter {
     async fn run(&self, data: &VarContext) -> Result<ScenarioOutput, ScenarioRuntimeError> {
@@ -68,4 +90,28 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_constant_filters() -> Result<(), Box<dyn std::error::Error>> {
+        let sink_id = NodeId::new("sink1");
+        let input = json!(3);
+        let run = |expression: &str| -> Result<_, Box<dyn std::error::Error>> {
+            let node_to_test = Node::Filter {
+                id: NodeId::new("filter"),
+                expression: tests::js(expression),
+            };
+            let compiled = tests::compile_node(node_to_test, &tests::sink(&sink_id))?;
+            Ok(block_on(compiled.run(
+                &VarContext::default_context_for_value(input.clone()),
+            ))?)
+        };
+
+        assert_eq!(
+            run("1 < 2")?.var_in_sink(&sink_id, DEFAULT_INPUT_NAME),
+            [Some(&input)]
+        );
+        assert_eq!(run("1 > 2")?.var_in_sink(&sink_id, DEFAULT_INPUT_NAME), []);
+        assert!(run("'not boolean'").is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,210 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use async_trait::async_trait;
+use futures::lock::Mutex;
+
+use crate::{
+    interpreter::{
+        data::{ScenarioOutput, ScenarioRuntimeError, VarContext, VarValue},
+        CustomNode, Interpreter, NodeContext,
+    },
+    scenariomodel::{MetaData, NodeId},
+};
+
+pub(super) struct OpenedNode {
+    pub node_id: NodeId,
+    pub config: VarValue,
+    pub parameters: HashMap<String, VarValue>,
+    pub implementation: Arc<dyn CustomNode>,
+}
+
+impl OpenedNode {
+    fn context<'a>(&'a self, meta_data: &'a MetaData) -> NodeContext<'a> {
+        NodeContext {
+            meta_data,
+            node_id: &self.node_id,
+            config: &self.config,
+            parameters: &self.parameters,
+        }
+    }
+}
+
+///Opens custom nodes of the scenario before the first invocation and closes them on close
+pub(super) struct LifecycleInterpreter {
+    interpreter: Box<dyn Interpreter>,
+    meta_data: MetaData,
+    nodes: Vec<OpenedNode>,
+    //number of nodes opened so far, so that only those are closed if opening fails
+    opened: Mutex<usize>,
+    //to avoid locking on each invocation
+    ready: AtomicBool,
+}
+
+impl LifecycleInterpreter {
+    pub fn new(
+        interpreter: Box<dyn Interpreter>,
+        meta_data: MetaData,
+        nodes: Vec<OpenedNode>,
+    ) -> LifecycleInterpreter {
+        LifecycleInterpreter {
+            interpreter,
+            meta_data,
+            nodes,
+            opened: Mutex::new(0),
+            ready: AtomicBool::new(false),
+        }
+    }
+}
+
+#[async_trait]
+impl Interpreter for LifecycleInterpreter {
+    async fn run(&self, data: &VarContext) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+        if !self.ready.load(Ordering::Acquire) {
+            self.open().await?;
+        }
+        self.interpreter.run(data).await
+    }
+
+    async fn open(&self) -> Result<(), ScenarioRuntimeError> {
+        let mut opened = self.opened.lock().await;
+        while *opened < self.nodes.len() {
+            let node = &self.nodes[*opened];
+            node.implementation
+                .open(&node.context(&self.meta_data))
+                .await
+                .map_err(|error| ScenarioRuntimeError::NodeError {
+                    node_id: node.node_id.clone(),
+                    error: Box::new(error),
+                })?;
+            *opened += 1;
+        }
+        self.ready.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    async fn close(&self) {
+        let mut opened = self.opened.lock().await;
+        self.ready.store(false, Ordering::Release);
+        for node in self.nodes[..*opened].iter().rev() {
+            node.implementation
+                .close(&node.context(&self.meta_data))
+                .await;
+        }
+        *opened = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use async_trait::async_trait;
+    use serde_json::json;
+    use tokio_test::block_on;
+
+    use crate::{
+        interpreter::{
+            compiler::{tests, Compiler},
+            data::{ScenarioOutput, ScenarioRuntimeError, VarContext, VarValue},
+            CustomNode, Interpreter, LazyParameters, NodeContext,
+        },
+        scenariomodel::{MetaData, Node, NodeId, Scenario},
+    };
+
+    //records lifecycle events
+    struct Recording(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl CustomNode for Recording {
+        async fn run(
+            &self,
+            _node_id: &NodeId,
+            output_var: Option<&str>,
+            _parameters: &HashMap<String, VarValue>,
+            _lazy_parameters: &LazyParameters,
+            input: &VarContext,
+            next_part: &Arc<dyn Interpreter>,
+        ) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+            self.0.lock().unwrap().push(String::from("run"));
+            next_part
+                .run(&input.with_output_var(output_var, json!(1)))
+                .await
+        }
+
+        async fn open(&self, context: &NodeContext) -> Result<(), ScenarioRuntimeError> {
+            self.0.lock().unwrap().push(format!(
+                "open {} {} {}",
+                context.meta_data.id, context.node_id, context.config
+            ));
+            Ok(())
+        }
+
+        async fn close(&self, context: &NodeContext) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("close {}", context.node_id));
+        }
+    }
+
+    #[test]
+    fn test_opens_and_closes_custom_nodes() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let custom_node = |id: &str| Node::CustomNode {
+            id: NodeId::new(id),
+            output_var: Some(String::from(id)),
+            node_type: String::from("recording"),
+            parameters: vec![],
+        };
+        let scenario = Scenario {
+            meta_data: MetaData {
+                id: String::from("scenario"),
+            },
+            nodes: [
+                vec![
+                    Node::Source {
+                        id: NodeId::new("source"),
+                    },
+                    custom_node("first"),
+                    custom_node("second"),
+                ],
+                tests::sink(&NodeId::new("sink")),
+            ]
+            .concat(),
+        };
+        let interpreter = Compiler::default()
+            .with_custom_node("recording", Arc::new(Recording(events.clone())))
+            .with_custom_node_config("recording", json!({"size": 10}))
+            .compile(&scenario)
+            .unwrap();
+        assert!(events.lock().unwrap().is_empty());
+
+        //opened on first run, only once
+        for _ in 0..2 {
+            block_on(interpreter.run(&VarContext::default_context_for_value(json!(1)))).unwrap();
+        }
+        block_on(interpreter.close());
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "open scenario first {\"size\":10}",
+                "open scenario second {\"size\":10}",
+                "run",
+                "run",
+                "run",
+                "run",
+                "close second",
+                "close first"
+            ]
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+
+use crate::{
+    expression::CompiledExpression,
+    interpreter::{
+        data::{ScenarioCompilationError, ScenarioOutput, ScenarioRuntimeError, VarContext},
+        CompilationResult, Interpreter,
+    },
+    scenariomodel::Field,
+};
+
+use super::CompilationContext;
+
+struct CompiledRecordVariable {
+    rest: Box<dyn Interpreter>,
+    fields: Vec<(String, Box<dyn CompiledExpression>)>,
+    var_name: String,
+}
+
+pub(super) fn compile(
+    ctx: CompilationContext,
+    var_name: &str,
+    fields: &[Field],
+) -> CompilationResult {
+    let mut names = HashSet::new();
+    let mut compiled_fields = vec![];
+    for field in fields {
+        if !names.insert(field.name.as_str()) {
+            return Err(ScenarioCompilationError::DuplicateField {
+                node_id: ctx.node_id.clone(),
+                field: field.name.clone(),
+            });
+        }
+        let expression = ctx.parse(&field.expression).map_err(|error| {
+            ScenarioCompilationError::InvalidField {
+                field: field.name.clone(),
+                error: Box::new(error),
+            }
+        })?;
+        compiled_fields.push((field.name.clone(), expression));
+    }
+    let field_types = compiled_fields
+        .iter()
+        .map(|(name, expression)| (name.clone(), expression.result_type()))
+        .collect::<HashMap<_, _>>();
+    let rest = ctx.compile_rest_with_record(var_name, field_types)?;
+    Ok(Box::new(CompiledRecordVariable {
+        rest,
+        fields: compiled_fields,
+        var_name: String::from(var_name),
+    }))
+}
+
+//errors of expressions already contain node id, we put field inside it
+fn field_error(field: &str, error: ScenarioRuntimeError) -> ScenarioRuntimeError {
+    let with_field = |error| ScenarioRuntimeError::FieldError {
+        field: String::from(field),
+        error: Box::new(error),
+    };
+    match error {
+        ScenarioRuntimeError::NodeError { node_id, error } => ScenarioRuntimeError::NodeError {
+            node_id,
+            error: Box::new(with_field(*error)),
+        },
+        other => with_field(other),
+    }
+}
+
+#[async_trait]
+impl Interpreter for CompiledRecordVariable {
+    async fn run(&self, data: &VarContext) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+        let mut record = Map::new();
+        for (name, expression) in &self.fields {
+            let value = expression
+                .execute(data)
+                .map_err(|error| field_error(name, error))?;
+            record.insert(name.clone(), value);
+        }
+        let with_var = data.with_new_var(&self.var_name, Value::Object(record));
+        self.rest.run(&with_var).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::{
+            compiler::tests,
+            data::{ScenarioCompilationError, VarContext},
+        },
+        scenariomodel::{Field, Node, NodeId},
+    };
+    use serde_json::json;
+    use tokio_test::block_on;
+
+    fn record(fields: &[(&str, &str)]) -> Node {
+        Node::RecordVariable {
+            id: NodeId::new("record"),
+            var_name: String::from("record"),
+            fields: fields
+                .iter()
+                .map(|(name, expression)| Field {
+                    name: String::from(*name),
+                    expression: tests::js(expression),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_outputs() -> Result<(), Box<dyn std::error::Error>> {
+        let sink_id = NodeId::new("sink1");
+        let compiled = tests::compile_node(
+            record(&[("doubled", "input * 2"), ("text", "'value: ' + input")]),
+            &tests::sink(&sink_id),
+        )?;
+
+        let result = block_on(compiled.run(&VarContext::default_context_for_value(json!(4))))?;
+        assert_eq!(
+            result.var_in_sink(&sink_id, "record"),
+            [Some(&json!({"doubled": 8, "text": "value: 4"}))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_typed_fields() {
+        let filter = |expression: &str| {
+            let rest = [
+                vec![Node::Filter {
+                    id: NodeId::new("filter"),
+                    expression: tests::simple(expression),
+                }],
+                tests::sink(&NodeId::new("sink1")),
+            ]
+            .concat();
+            tests::compile_node(record(&[("name", "input.name")]), &rest)
+                .err()
+                .map(|error| error.to_string())
+        };
+        assert_eq!(filter("record.name != null"), None);
+        assert!(filter("record.missing != null")
+            .unwrap()
+            .contains("Unknown field missing of record"));
+
+        let typed = Node::RecordVariable {
+            id: NodeId::new("record"),
+            var_name: String::from("record"),
+            fields: vec![Field {
+                name: String::from("count"),
+                expression: tests::simple("length(input)"),
+            }],
+        };
+        let rest = [
+            vec![Node::Filter {
+                id: NodeId::new("filter"),
+                expression: tests::simple("record.count.value > 0"),
+            }],
+            tests::sink(&NodeId::new("sink1")),
+        ]
+        .concat();
+        assert!(tests::compile_node(typed, &rest)
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("Field value not available on Number"));
+    }
+
+    #[test]
+    fn test_errors_contain_field() {
+        let sink = tests::sink(&NodeId::new("sink1"));
+        match tests::compile_node(record(&[("ok", "input"), ("wrong", "unknown")]), &sink) {
+            Err(ScenarioCompilationError::InvalidField { field, error: _ }) => {
+                assert_eq!(field, "wrong")
+            }
+            other => panic!("Unexpected: {:?}", other.err()),
+        }
+        assert!(matches!(
+            tests::compile_node(record(&[("a", "1"), ("a", "2")]), &sink),
+            Err(ScenarioCompilationError::DuplicateField { .. })
+        ));
+
+        let compiled = tests::compile_node(record(&[("failing", "input.foo.bar")]), &sink).unwrap();
+        let error = block_on(compiled.run(&VarContext::default_context_for_value(json!({}))))
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("Error in node record: Error in field failing:"));
+    }
+}
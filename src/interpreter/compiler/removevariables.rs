@@ -0,0 +1,107 @@
+use async_trait::async_trait;
+
+use crate::interpreter::{
+    data::{ScenarioOutput, ScenarioRuntimeError, VarContext},
+    CompilationResult, Interpreter,
+};
+
+use super::CompilationContext;
+
+struct CompiledRemoveVariables {
+    rest: Box<dyn Interpreter>,
+    var_names: Vec<String>,
+}
+
+pub(super) fn compile(ctx: CompilationContext, var_names: &[String]) -> CompilationResult {
+    let mut rest_var_names = ctx.var_names.clone();
+    for var_name in var_names {
+        rest_var_names = rest_var_names.without_var(ctx.node_id, var_name)?;
+    }
+    let rest = (ctx.compiler)(ctx.rest, &rest_var_names)?;
+    Ok(Box::new(CompiledRemoveVariables {
+        rest,
+        var_names: var_names.to_vec(),
+    }))
+}
+
+#[async_trait]
+impl Interpreter for CompiledRemoveVariables {
+    async fn run(&self, data: &VarContext) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+        self.rest.run(&data.without_vars(&self.var_names)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        interpreter::{
+            compiler::tests,
+            data::{ScenarioCompilationError, VarContext},
+        },
+        scenariomodel::{Node, NodeId},
+    };
+    use serde_json::json;
+    use tokio_test::block_on;
+
+    #[test]
+    fn test_outputs() -> Result<(), Box<dyn std::error::Error>> {
+        let node_to_test = Node::RemoveVariables {
+            id: NodeId::new("remove"),
+            var_names: vec![String::from("temporary")],
+        };
+        let sink_id = NodeId::new("sink1");
+
+        let compiled = tests::compile_node(
+            Node::Variable {
+                id: NodeId::new("variable"),
+                var_name: String::from("temporary"),
+                value: tests::js("input * 2"),
+            },
+            &[vec![node_to_test], tests::sink(&sink_id)].concat(),
+        )?;
+
+        let result = block_on(compiled.run(&VarContext::default_context_for_value(json!(1))))?;
+        assert_eq!(
+            result.vars_in_sink(&sink_id),
+            [&HashMap::from([(String::from("input"), json!(1))])]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_removed_variable_cannot_be_used() {
+        let compiled = tests::compile_node(
+            Node::RemoveVariables {
+                id: NodeId::new("remove"),
+                var_names: vec![String::from("input")],
+            },
+            &[
+                Node::Filter {
+                    id: NodeId::new("filter"),
+                    expression: tests::js("input > 0"),
+                },
+                Node::Sink {
+                    id: NodeId::new("sink"),
+                },
+            ],
+        );
+        assert!(matches!(
+            compiled,
+            Err(ScenarioCompilationError::ParseError { .. })
+        ));
+
+        let compiled = tests::compile_node(
+            Node::RemoveVariables {
+                id: NodeId::new("remove"),
+                var_names: vec![String::from("unknown")],
+            },
+            &tests::sink(&NodeId::new("sink")),
+        );
+        assert!(matches!(
+            compiled,
+            Err(ScenarioCompilationError::UnknownVariable { var_name, .. }) if var_name == "unknown"
+        ));
+    }
+}
@@ -15,6 +15,7 @@ pub struct CompiledSink {
 }
 
 pub(super) fn compile(ctx: CompilationContext, sink_id: &NodeId) -> CompilationResult {
+    //sink outputs the whole context, it doesn't count as usage - otherwise no variable reaching a sink would be unused
     ctx.assert_end(Box::new(CompiledSink {
         node_id: sink_id.clone(),
     }))
@@ -1,9 +1,12 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use futures::future::join_all;
 
 use crate::{
     interpreter::{
         data::{ScenarioCompilationError, ScenarioOutput, ScenarioRuntimeError, VarContext},
+        workers::WorkerPool,
         CompilationResult, Interpreter,
     },
     scenariomodel::Node,
@@ -12,40 +15,84 @@ use crate::{
 use super::CompilationContext;
 
 pub(super) struct CompiledSplit {
-    nexts: Vec<Box<dyn Interpreter>>,
+    nexts: Vec<Arc<dyn Interpreter>>,
+    workers: Option<Arc<WorkerPool>>,
 }
 
 pub(super) fn compile(ctx: CompilationContext, nexts: &[Vec<Node>]) -> CompilationResult {
-    let compiled: Result<Vec<Box<dyn Interpreter>>, ScenarioCompilationError> = nexts
+    let compiled: Result<Vec<Arc<dyn Interpreter>>, ScenarioCompilationError> = nexts
         .iter()
-        .map(|n| (ctx.compiler)(&n[..], ctx.var_names))
+        .map(|n| (ctx.compiler)(&n[..], ctx.var_names).map(Arc::from))
         .collect();
-    ctx.assert_end(Box::new(CompiledSplit { nexts: compiled? }))
+    ctx.assert_end(Box::new(CompiledSplit {
+        nexts: compiled?,
+        workers: ctx.workers.cloned(),
+    }))
 }
 
 #[async_trait]
 impl Interpreter for CompiledSplit {
     async fn run(&self, data: &VarContext) -> Result<ScenarioOutput, ScenarioRuntimeError> {
-        let output_result: Result<Vec<ScenarioOutput>, ScenarioRuntimeError> =
-            join_all(self.nexts.iter().map(|one| one.run(data)))
+        let outputs = match &self.workers {
+            Some(workers) => {
+                join_all(
+                    self.nexts
+                        .iter()
+                        .map(|one| workers.run(one.clone(), data.clone())),
+                )
                 .await
-                .into_iter()
-                .collect();
+            }
+            None => join_all(self.nexts.iter().map(|one| one.run(data))).await,
+        };
+        let output_result: Result<Vec<ScenarioOutput>, ScenarioRuntimeError> =
+            outputs.into_iter().collect();
         output_result.map(ScenarioOutput::flatten)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{collections::HashMap, sync::Arc, thread};
+
     use crate::{
-        interpreter::data::{VarContext, DEFAULT_INPUT_NAME},
+        interpreter::{
+            compiler::Compiler,
+            data::{
+                CompilationVarContext, ScenarioOutput, ScenarioRuntimeError, VarContext,
+                DEFAULT_INPUT_NAME,
+            },
+            workers::WorkerPool,
+            CustomNode, Interpreter, LazyParameters,
+        },
         scenariomodel::{Node, NodeId},
     };
-    use serde_json::json;
+    use async_trait::async_trait;
+    use serde_json::{json, Value};
     use tokio_test::block_on;
 
     use super::super::tests;
 
+    //passes name of the thread it's invoked on as the output variable
+    struct ThreadName;
+
+    #[async_trait]
+    impl CustomNode for ThreadName {
+        async fn run(
+            &self,
+            _node_id: &NodeId,
+            output_var: Option<&str>,
+            _parameters: &HashMap<String, Value>,
+            _lazy_parameters: &LazyParameters,
+            input: &VarContext,
+            next_part: &Arc<dyn Interpreter>,
+        ) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+            let name = thread::current().name().map(String::from);
+            next_part
+                .run(&input.with_output_var(output_var, json!(name)))
+                .await
+        }
+    }
+
     #[test]
     fn test_outputs() -> Result<(), Box<dyn std::error::Error>> {
         let branch1 = NodeId::new("branch1");
@@ -71,4 +118,48 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_outputs_on_workers() -> Result<(), Box<dyn std::error::Error>> {
+        let branches: Vec<NodeId> = (0..4)
+            .map(|i| NodeId::new(&format!("branch{}", i)))
+            .collect();
+        let node_to_test = Node::Split {
+            id: NodeId::new("split"),
+            nexts: branches
+                .iter()
+                .map(|branch| {
+                    let mut nodes = vec![Node::CustomNode {
+                        id: NodeId::new(&format!("{}-thread", branch)),
+                        output_var: Some(String::from("thread")),
+                        node_type: String::from("threadName"),
+                        parameters: vec![],
+                    }];
+                    nodes.extend(tests::sink(branch));
+                    nodes
+                })
+                .collect(),
+        };
+        let compiler = Compiler::default()
+            .with_custom_node("threadName", Arc::new(ThreadName))
+            .with_worker_pool(Arc::new(WorkerPool::new(2)));
+        let compiled = compiler.compile_next_node(
+            &node_to_test,
+            &[],
+            &CompilationVarContext::default(),
+            &Default::default(),
+        )?;
+
+        let input = json!("to_copy");
+        let result = block_on(compiled.run(&VarContext::default_context_for_value(input.clone())))?;
+        for branch in &branches {
+            assert_eq!(
+                result.var_in_sink(branch, DEFAULT_INPUT_NAME),
+                [Some(&input)]
+            );
+            let thread = result.var_in_sink(branch, "thread")[0].and_then(Value::as_str);
+            assert!(matches!(thread, Some(name) if name.starts_with("scenario-worker-")));
+        }
+        Ok(())
+    }
 }
@@ -1,7 +1,7 @@
 use crate::{
     expression::CompiledExpression,
     interpreter::{
-        data::{ScenarioCompilationError, ScenarioOutput, ScenarioRuntimeError, VarContext},
+        data::{CompilationWarning, ScenarioOutput, ScenarioRuntimeError, VarContext},
         CompilationResult, Interpreter,
     },
     scenariomodel::Case,
@@ -9,22 +9,27 @@ use crate::{
 use async_trait::async_trait;
 use serde_json::Value::Bool;
 
-use super::CompilationContext;
+use super::{constant_value, CompilationContext};
 struct CompiledSwitch {
     nexts: Vec<CompiledCase>,
 }
 
 pub(super) fn compile(ctx: CompilationContext, nexts: &[Case]) -> CompilationResult {
-    let parse_case = |case: &Case| {
+    let mut compiled = vec![];
+    let mut matched_always = false;
+    for (index, case) in nexts.iter().enumerate() {
+        if matched_always {
+            ctx.warn(CompilationWarning::UnreachableCase {
+                node_id: ctx.node_id.clone(),
+                case: index,
+            });
+        }
         let rest = (ctx.compiler)(&case.nodes[..], ctx.var_names)?;
-        let expression = ctx
-            .parser
-            .parse(ctx.node_id, &case.expression, ctx.var_names)?;
-        Ok(CompiledCase { rest, expression })
-    };
-    let compiled: Result<Vec<CompiledCase>, ScenarioCompilationError> =
-        nexts.iter().map(parse_case).collect();
-    ctx.assert_end(Box::new(CompiledSwitch { nexts: compiled? }))
+        let expression = ctx.parse(&case.expression)?;
+        matched_always |= constant_value(expression.as_ref()) == Some(Bool(true));
+        compiled.push(CompiledCase { rest, expression });
+    }
+    ctx.assert_end(Box::new(CompiledSwitch { nexts: compiled }))
 }
 
 struct CompiledCase {
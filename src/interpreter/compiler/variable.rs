@@ -22,10 +22,8 @@ pub(super) fn compile(
     var_name: &str,
     raw_expression: &Expression,
 ) -> Result<Box<dyn Interpreter>, ScenarioCompilationError> {
-    let expression = ctx
-        .parser
-        .parse(ctx.node_id, raw_expression, ctx.var_names)?;
-    let rest = (ctx.compiler)(ctx.rest, &ctx.var_names.with_var(ctx.node_id, var_name)?)?;
+    let expression = ctx.parse(raw_expression)?;
+    let rest = ctx.compile_rest_with_var(var_name, expression.result_type())?;
     Ok(Box::new(CompiledVariable {
         rest,
         expression,
@@ -3,7 +3,10 @@ use serde::Serialize;
 use serde_json::Value;
 use std::{collections::HashMap, sync::Arc};
 
-use crate::scenariomodel::{Node, NodeId};
+use crate::{
+    expression::ResultType,
+    scenariomodel::{Node, NodeId},
+};
 use once_cell::sync::Lazy;
 
 /// Data passed through scenario
@@ -33,11 +36,38 @@ impl VarContext {
             .map(|f| (f.0.clone(), f.1.as_ref().to_owned()))
             .collect();
     }
+    pub fn get(&self, name: &str) -> Option<&VarValue> {
+        self.0.get(name).map(Arc::as_ref)
+    }
+    ///Variables sorted by name
+    pub fn vars(&self) -> Vec<(&str, &VarValue)> {
+        let mut vars: Vec<(&str, &VarValue)> = self
+            .0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_ref()))
+            .collect();
+        vars.sort_by_key(|(name, _)| *name);
+        vars
+    }
     pub fn with_new_var(&self, name: &str, value: Value) -> VarContext {
         let mut result = self.clone();
         result.0.insert(String::from(name), Arc::new(value));
         result
     }
+    ///Adds the variable only if the name is given, e.g. for the optional output of a custom node.
+    pub fn with_output_var(&self, name: Option<&str>, value: Value) -> VarContext {
+        match name {
+            Some(name) => self.with_new_var(name, value),
+            None => self.clone(),
+        }
+    }
+    pub fn without_vars(&self, names: &[String]) -> VarContext {
+        let mut result = self.clone();
+        for name in names {
+            result.0.remove(name);
+        }
+        result
+    }
 }
 
 ///Output data of the scenario
@@ -75,26 +105,43 @@ pub struct SingleScenarioOutput {
 /// At the moment we assume JSON model. It's certainly a simplification, but for the purpose of this excerise it should be enough;
 pub type VarValue = Value;
 
-/*
-We leave possiblity of typing variables, but for now we'll be only interested in variable presence, as it makes
-JS evaluation simpler.
- */
-pub type VarType = ();
+///Types are known only for some variables (e.g. created by Variable node with simple expression),
+///for others (e.g. input) it's ResultType::Unknown.
+pub type VarType = ResultType;
 
+///Types of fields of a record variable (e.g. created by RecordVariable node), by field name
+pub type FieldTypes = HashMap<String, VarType>;
+
+///Types of variables, and fields of the ones which are records
 #[derive(Clone, PartialEq, Eq, Debug)]
-pub struct CompilationVarContext(pub HashMap<String, VarType>);
+pub struct CompilationVarContext(
+    pub HashMap<String, VarType>,
+    pub HashMap<String, FieldTypes>,
+);
 
 static VAR_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new("^[a-z][a-z0-9_]*$").unwrap());
 
 impl CompilationVarContext {
     pub fn default() -> CompilationVarContext {
-        CompilationVarContext(HashMap::from([(DEFAULT_INPUT_NAME.to_string(), ())]))
+        CompilationVarContext(
+            HashMap::from([(DEFAULT_INPUT_NAME.to_string(), ResultType::Unknown)]),
+            HashMap::new(),
+        )
     }
 
     pub fn with_var(
         &self,
         node_id: &NodeId,
         name: &str,
+    ) -> Result<CompilationVarContext, ScenarioCompilationError> {
+        self.with_typed_var(node_id, name, ResultType::Unknown)
+    }
+
+    pub fn with_typed_var(
+        &self,
+        node_id: &NodeId,
+        name: &str,
+        var_type: VarType,
     ) -> Result<CompilationVarContext, ScenarioCompilationError> {
         if !VAR_PATTERN.is_match(name) {
             return Err(ScenarioCompilationError::IncorrectVariableName {
@@ -104,9 +151,42 @@ impl CompilationVarContext {
         }
         //we clone to be able to pass it freely to different branches e.g. in split.
         let mut new_ctx = self.clone();
-        new_ctx.0.insert(String::from(name), ());
+        new_ctx.0.insert(String::from(name), var_type);
+        new_ctx.1.remove(name);
         Ok(new_ctx)
     }
+
+    ///Record has to contain exactly the given fields, so that access to other ones can be rejected
+    pub fn with_record_var(
+        &self,
+        node_id: &NodeId,
+        name: &str,
+        fields: FieldTypes,
+    ) -> Result<CompilationVarContext, ScenarioCompilationError> {
+        let mut new_ctx = self.with_typed_var(node_id, name, ResultType::Map)?;
+        new_ctx.1.insert(String::from(name), fields);
+        Ok(new_ctx)
+    }
+
+    pub fn record_fields(&self, name: &str) -> Option<&FieldTypes> {
+        self.1.get(name)
+    }
+
+    pub fn without_var(
+        &self,
+        node_id: &NodeId,
+        name: &str,
+    ) -> Result<CompilationVarContext, ScenarioCompilationError> {
+        let mut new_ctx = self.clone();
+        new_ctx.1.remove(name);
+        match new_ctx.0.remove(name) {
+            Some(_) => Ok(new_ctx),
+            None => Err(ScenarioCompilationError::UnknownVariable {
+                node_id: node_id.clone(),
+                var_name: name.to_string(),
+            }),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -115,6 +195,10 @@ pub enum ScenarioCompilationError {
         node_id: NodeId,
         var_name: String,
     },
+    UnknownVariable {
+        node_id: NodeId,
+        var_name: String,
+    },
     UnknownLanguage {
         node_id: NodeId,
         language: String,
@@ -124,6 +208,14 @@ pub enum ScenarioCompilationError {
         node_id: NodeId,
         error: Box<dyn crate::expression::ParseError>,
     },
+    InvalidField {
+        field: String,
+        error: Box<ScenarioCompilationError>,
+    },
+    DuplicateField {
+        node_id: NodeId,
+        field: String,
+    },
     InvalidEnd(NodeId),
     FirstNodeNotSource(NodeId),
     UnknownNode(NodeId),
@@ -131,6 +223,34 @@ pub enum ScenarioCompilationError {
         node_id: NodeId,
         node_type: String,
     },
+    UnknownParameter {
+        node_id: NodeId,
+        name: String,
+    },
+    DuplicateParameter {
+        node_id: NodeId,
+        name: String,
+    },
+    MissingParameter {
+        node_id: NodeId,
+        name: String,
+    },
+    InvalidParameterType {
+        node_id: NodeId,
+        name: String,
+        expected: ResultType,
+        actual: ResultType,
+    },
+    ParameterNotLiteral {
+        node_id: NodeId,
+        name: String,
+    },
+    InvalidNode {
+        node_id: NodeId,
+        error: Box<dyn std::error::Error + Send + Sync>,
+    },
+    MissingOutputVariable(NodeId),
+    UnexpectedOutputVariable(NodeId),
     NodesAfterEndingNode {
         node_id: NodeId,
         unexpected_nodes: Vec<Node>,
@@ -140,13 +260,91 @@ pub enum ScenarioCompilationError {
 
 impl std::fmt::Display for ScenarioCompilationError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        //this should be nicely handled, just like in ForEachError...
-        write!(f, "Error occurred: {:?}", self)
+        match self {
+            Self::ParseError { node_id, error } => {
+                write!(f, "Invalid expression in node {}: {}", node_id, error)
+            }
+            Self::InvalidField { field, error } => write!(f, "{} (field {})", error, field),
+            Self::UnknownParameter { node_id, name } => {
+                write!(f, "Unknown parameter {} in node {}", name, node_id)
+            }
+            Self::DuplicateParameter { node_id, name } => {
+                write!(f, "Duplicate parameter {} in node {}", name, node_id)
+            }
+            Self::MissingParameter { node_id, name } => {
+                write!(f, "Missing parameter {} in node {}", name, node_id)
+            }
+            Self::InvalidParameterType {
+                node_id,
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Parameter {} in node {} should be {:?}, but is {:?}",
+                name, node_id, expected, actual
+            ),
+            Self::ParameterNotLiteral { node_id, name } => write!(
+                f,
+                "Parameter {} in node {} has to be constant",
+                name, node_id
+            ),
+            Self::InvalidNode { node_id, error } => {
+                write!(f, "Invalid node {}: {}", node_id, error)
+            }
+            //this should be nicely handled, just like in ForEachError...
+            other => write!(f, "Error occurred: {:?}", other),
+        }
     }
 }
 
 impl std::error::Error for ScenarioCompilationError {}
 
+///Problems found during compilation, which don't prevent the scenario from running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompilationWarning {
+    ///The filter can be removed
+    FilterAlwaysTrue(NodeId),
+    ///The branch after the filter is never executed
+    FilterAlwaysFalse(NodeId),
+    ///Case (counting from 0) follows a case which always matches
+    UnreachableCase { node_id: NodeId, case: usize },
+    ///Variable with the same name was defined earlier
+    ShadowedVariable { node_id: NodeId, var_name: String },
+    ///Variable is not read by any expression after the node, sinks outputting it don't count
+    UnusedVariable { node_id: NodeId, var_name: String },
+}
+
+impl std::fmt::Display for CompilationWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FilterAlwaysTrue(node_id) => {
+                write!(f, "Filter {} is always true, it can be removed", node_id)
+            }
+            Self::FilterAlwaysFalse(node_id) => write!(
+                f,
+                "Filter {} is always false, nodes after it are never executed",
+                node_id
+            ),
+            Self::UnreachableCase { node_id, case } => write!(
+                f,
+                "Case {} in switch {} is unreachable, previous case always matches",
+                case, node_id
+            ),
+            Self::ShadowedVariable { node_id, var_name } => write!(
+                f,
+                "Variable {} defined in node {} shadows earlier variable",
+                var_name, node_id
+            ),
+            Self::UnusedVariable { node_id, var_name } => write!(
+                f,
+                "Variable {} defined in node {} is not read by any expression",
+                var_name, node_id
+            ),
+        }
+    }
+}
+
 #[derive(Debug)]
 //TODO: pass NodeId in runtime errors, like in compilation errors
 pub enum ScenarioRuntimeError {
@@ -155,12 +353,26 @@ pub enum ScenarioRuntimeError {
     InvalidFilterType(Value),
     ExpressionError(Box<dyn std::error::Error + Send>),
     CustomNodeError(Box<dyn std::error::Error + Send>),
+    NodeError {
+        node_id: NodeId,
+        error: Box<ScenarioRuntimeError>,
+    },
+    FieldError {
+        field: String,
+        error: Box<ScenarioRuntimeError>,
+    },
+    WorkerFailure(String),
 }
 
 impl std::fmt::Display for ScenarioRuntimeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        //this should be nicely handled, just like in ForEachError...
-        write!(f, "Error occurred: {:?}", self)
+        match self {
+            Self::NodeError { node_id, error } => write!(f, "Error in node {}: {}", node_id, error),
+            Self::FieldError { field, error } => write!(f, "Error in field {}: {}", field, error),
+            Self::ExpressionError(error) => write!(f, "{}", error),
+            //this should be nicely handled, just like in ForEachError...
+            other => write!(f, "Error occurred: {:?}", other),
+        }
     }
 }
 
@@ -170,7 +382,9 @@ impl std::error::Error for ScenarioRuntimeError {}
 mod tests {
     use std::collections::HashMap;
 
-    use crate::{interpreter::data::ScenarioCompilationError, scenariomodel::NodeId};
+    use crate::{
+        expression::ResultType, interpreter::data::ScenarioCompilationError, scenariomodel::NodeId,
+    };
 
     use super::CompilationVarContext;
 
@@ -184,7 +398,10 @@ mod tests {
         let new_ctx = context.with_var(&node_id(), "abc")?;
         assert_eq!(
             new_ctx.0,
-            HashMap::from([("input".to_string(), ()), ("abc".to_string(), ())])
+            HashMap::from([
+                ("input".to_string(), ResultType::Unknown),
+                ("abc".to_string(), ResultType::Unknown)
+            ])
         );
         Ok(())
     }
@@ -205,4 +422,36 @@ mod tests {
         assert_incorrent_name("1abc");
         assert_incorrent_name("");
     }
+
+    #[test]
+    fn replaces_record_fields() -> Result<(), ScenarioCompilationError> {
+        let fields = HashMap::from([("count".to_string(), ResultType::Number)]);
+        let context =
+            CompilationVarContext::default().with_record_var(&node_id(), "abc", fields.clone())?;
+        assert_eq!(context.0.get("abc"), Some(&ResultType::Map));
+        assert_eq!(context.record_fields("abc"), Some(&fields));
+        //shadowing variable is not a record
+        let shadowed = context.with_var(&node_id(), "abc")?;
+        assert_eq!(shadowed.record_fields("abc"), None);
+        assert_eq!(
+            context.without_var(&node_id(), "abc")?.record_fields("abc"),
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn removes_var_from_context() -> Result<(), ScenarioCompilationError> {
+        let context = CompilationVarContext::default().with_var(&node_id(), "abc")?;
+        let new_ctx = context.without_var(&node_id(), "input")?;
+        assert_eq!(
+            new_ctx.0,
+            HashMap::from([("abc".to_string(), ResultType::Unknown)])
+        );
+        assert!(matches!(
+            new_ctx.without_var(&node_id(), "input"),
+            Err(ScenarioCompilationError::UnknownVariable { .. })
+        ));
+        Ok(())
+    }
 }
@@ -0,0 +1,272 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use super::data::{ScenarioRuntimeError, VarValue};
+
+type Namespaces = HashMap<String, HashMap<String, VarValue>>;
+
+///State of custom nodes (e.g. aggregates), which survives between invocations of the scenario.
+///Values are stored by namespace (usually node id) and key within the namespace
+pub trait StateStore: Sync + Send {
+    fn get(&self, namespace: &str, key: &str) -> Option<VarValue>;
+
+    ///Replaces value with result of the function (None removes it), atomically with respect to other updates.
+    ///Returns the new value
+    fn update(
+        &self,
+        namespace: &str,
+        key: &str,
+        update: &mut dyn FnMut(Option<VarValue>) -> Option<VarValue>,
+    ) -> Option<VarValue>;
+
+    ///Number of entries in the namespace
+    fn count(&self, namespace: &str) -> usize;
+
+    ///All entries of the namespace, in no particular order
+    fn entries(&self, namespace: &str) -> Vec<(String, VarValue)>;
+
+    ///Keeps only entries of the namespace matching the predicate, e.g. to remove expired ones
+    fn retain(&self, namespace: &str, predicate: &mut dyn FnMut(&str, &VarValue) -> bool);
+
+    ///Persists the state. It's not invoked by the library - the owner of the store should call it, e.g. after
+    ///the scenario is closed
+    fn flush(&self) -> Result<(), StateStoreError> {
+        Ok(())
+    }
+}
+
+///State is lost when the process ends
+#[derive(Default)]
+pub struct InMemoryStateStore {
+    namespaces: Mutex<Namespaces>,
+}
+
+impl InMemoryStateStore {
+    fn with_namespaces<T>(&self, action: impl FnOnce(&mut Namespaces) -> T) -> T {
+        //the state is still consistent if another invocation panicked
+        let mut namespaces = match self.namespaces.lock() {
+            Ok(namespaces) => namespaces,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        action(&mut namespaces)
+    }
+}
+
+impl StateStore for InMemoryStateStore {
+    fn get(&self, namespace: &str, key: &str) -> Option<VarValue> {
+        self.with_namespaces(|namespaces| namespaces.get(namespace)?.get(key).cloned())
+    }
+
+    fn update(
+        &self,
+        namespace: &str,
+        key: &str,
+        update: &mut dyn FnMut(Option<VarValue>) -> Option<VarValue>,
+    ) -> Option<VarValue> {
+        self.with_namespaces(|namespaces| {
+            let values = namespaces.entry(String::from(namespace)).or_default();
+            let new_value = update(values.remove(key));
+            if let Some(new_value) = &new_value {
+                values.insert(String::from(key), new_value.clone());
+            }
+            new_value
+        })
+    }
+
+    fn count(&self, namespace: &str) -> usize {
+        self.with_namespaces(|namespaces| namespaces.get(namespace).map_or(0, HashMap::len))
+    }
+
+    fn entries(&self, namespace: &str) -> Vec<(String, VarValue)> {
+        self.with_namespaces(|namespaces| {
+            namespaces
+                .get(namespace)
+                .iter()
+                .flat_map(|values| values.iter())
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()
+        })
+    }
+
+    fn retain(&self, namespace: &str, predicate: &mut dyn FnMut(&str, &VarValue) -> bool) {
+        self.with_namespaces(|namespaces| {
+            if let Some(values) = namespaces.get_mut(namespace) {
+                values.retain(|key, value| predicate(key, value));
+            }
+        })
+    }
+}
+
+///Keeps the state in memory and writes it to JSON file on flush, so that it survives restart of the service
+pub struct FileStateStore {
+    path: PathBuf,
+    state: InMemoryStateStore,
+}
+
+impl FileStateStore {
+    ///Loads the state written previously, the file doesn't have to exist
+    pub fn open(path: &Path) -> Result<FileStateStore, StateStoreError> {
+        let namespaces = if path.exists() {
+            let content = fs::read_to_string(path).map_err(|err| StateStoreError::Io {
+                path: path.to_path_buf(),
+                error: err.to_string(),
+            })?;
+            serde_json::from_str(&content).map_err(|err| StateStoreError::InvalidContent {
+                path: path.to_path_buf(),
+                error: err.to_string(),
+            })?
+        } else {
+            Namespaces::new()
+        };
+        Ok(FileStateStore {
+            path: path.to_path_buf(),
+            state: InMemoryStateStore {
+                namespaces: Mutex::new(namespaces),
+            },
+        })
+    }
+}
+
+impl StateStore for FileStateStore {
+    fn get(&self, namespace: &str, key: &str) -> Option<VarValue> {
+        self.state.get(namespace, key)
+    }
+
+    fn update(
+        &self,
+        namespace: &str,
+        key: &str,
+        update: &mut dyn FnMut(Option<VarValue>) -> Option<VarValue>,
+    ) -> Option<VarValue> {
+        self.state.update(namespace, key, update)
+    }
+
+    fn count(&self, namespace: &str) -> usize {
+        self.state.count(namespace)
+    }
+
+    fn entries(&self, namespace: &str) -> Vec<(String, VarValue)> {
+        self.state.entries(namespace)
+    }
+
+    fn retain(&self, namespace: &str, predicate: &mut dyn FnMut(&str, &VarValue) -> bool) {
+        self.state.retain(namespace, predicate)
+    }
+
+    //written to temporary file first, so that the previous state is not lost if writing fails
+    fn flush(&self) -> Result<(), StateStoreError> {
+        let content = self
+            .state
+            .with_namespaces(|namespaces| serde_json::to_string(namespaces))
+            .map_err(|err| StateStoreError::InvalidContent {
+                path: self.path.clone(),
+                error: err.to_string(),
+            })?;
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, content)
+            .and_then(|_| fs::rename(&temporary, &self.path))
+            .map_err(|err| StateStoreError::Io {
+                path: self.path.clone(),
+                error: err.to_string(),
+            })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StateStoreError {
+    Io { path: PathBuf, error: String },
+    InvalidContent { path: PathBuf, error: String },
+}
+
+impl From<StateStoreError> for ScenarioRuntimeError {
+    fn from(error: StateStoreError) -> Self {
+        ScenarioRuntimeError::CustomNodeError(Box::new(error))
+    }
+}
+
+impl Display for StateStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io { path, error } => {
+                write!(
+                    f,
+                    "Failed to access state file {}: {}",
+                    path.display(),
+                    error
+                )
+            }
+            Self::InvalidContent { path, error } => {
+                write!(f, "Invalid state in file {}: {}", path.display(), error)
+            }
+        }
+    }
+}
+impl Error for StateStoreError {}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use serde_json::json;
+
+    use super::{FileStateStore, InMemoryStateStore, StateStore, StateStoreError};
+
+    #[test]
+    fn test_update() {
+        let store = InMemoryStateStore::default();
+        let increment = |store: &InMemoryStateStore, key: &str| {
+            store.update("node", key, &mut |value| {
+                Some(json!(value.and_then(|v| v.as_u64()).unwrap_or(0) + 1))
+            })
+        };
+        assert_eq!(increment(&store, "a"), Some(json!(1)));
+        assert_eq!(increment(&store, "a"), Some(json!(2)));
+        assert_eq!(increment(&store, "b"), Some(json!(1)));
+        assert_eq!(store.get("node", "a"), Some(json!(2)));
+        assert_eq!(store.get("other", "a"), None);
+
+        assert_eq!(store.update("node", "a", &mut |_| None), None);
+        assert_eq!(store.get("node", "a"), None);
+    }
+
+    #[test]
+    fn test_retain() {
+        let store = InMemoryStateStore::default();
+        for (key, value) in [("a", 1), ("b", 2), ("c", 3)] {
+            store.update("node", key, &mut |_| Some(json!(value)));
+        }
+        store.update("other", "a", &mut |_| Some(json!(1)));
+
+        store.retain("node", &mut |key, value| key != "a" && value != &json!(3));
+        assert_eq!(store.entries("node"), [(String::from("b"), json!(2))]);
+        assert_eq!(store.count("other"), 1);
+        assert_eq!(store.entries("unknown"), []);
+    }
+
+    #[test]
+    fn test_state_survives_reopening() -> Result<(), StateStoreError> {
+        let path = env::temp_dir().join(format!("state-store-test-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let store = FileStateStore::open(&path)?;
+        store.update("node", "key", &mut |_| Some(json!({"count": 3})));
+        store.flush()?;
+
+        let reopened = FileStateStore::open(&path)?;
+        assert_eq!(reopened.get("node", "key"), Some(json!({"count": 3})));
+
+        fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            FileStateStore::open(&path),
+            Err(StateStoreError::InvalidContent { .. })
+        ));
+        fs::remove_file(&path).unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    cell::Cell,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use futures::{channel::oneshot, executor::block_on};
+
+use super::{
+    data::{ScenarioOutput, ScenarioRuntimeError, VarContext},
+    Interpreter,
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+///Threads which run parts of the scenario (e.g. split branches or for-each elements). JavaScript runtimes are
+///thread-local, so each worker has its own, and CPU-heavy expressions in different branches can use multiple cores.
+///Invocations are run with `block_on`, outside of any async runtime.
+pub struct WorkerPool {
+    jobs: Mutex<Sender<Job>>,
+}
+
+thread_local! {
+    static IS_WORKER: Cell<bool> = const { Cell::new(false) };
+}
+
+impl WorkerPool {
+    pub fn new(size: usize) -> WorkerPool {
+        let (sender, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..size.max(1) {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("scenario-worker-{}", i))
+                .spawn(move || work(&receiver))
+                .expect("Failed to start worker thread");
+        }
+        WorkerPool {
+            jobs: Mutex::new(sender),
+        }
+    }
+
+    ///Runs the interpreter on one of the workers. If invoked from a worker (e.g. for-each inside split branch),
+    ///it runs on the current thread, so that workers don't wait for each other
+    pub async fn run(
+        &self,
+        interpreter: Arc<dyn Interpreter>,
+        data: VarContext,
+    ) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+        if IS_WORKER.with(Cell::get) {
+            return interpreter.run(&data).await;
+        }
+        let (result_sender, result) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            //the receiver may be gone if the invocation was cancelled, nothing to do then
+            let _ = result_sender.send(block_on(interpreter.run(&data)));
+        });
+        self.jobs
+            .lock()
+            .map_err(|_| worker_failure())?
+            .send(job)
+            .map_err(|_| worker_failure())?;
+        result.await.map_err(|_| worker_failure())?
+    }
+}
+
+fn worker_failure() -> ScenarioRuntimeError {
+    ScenarioRuntimeError::WorkerFailure(String::from("Worker failed to complete invocation"))
+}
+
+//workers end when the pool is dropped
+fn work(jobs: &Mutex<Receiver<Job>>) {
+    IS_WORKER.with(|is_worker| is_worker.set(true));
+    loop {
+        let job = match jobs.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return,
+        };
+        match job {
+            //panic drops the result sender, so it's reported as failure of the invocation
+            Ok(job) => {
+                let _ = catch_unwind(AssertUnwindSafe(job));
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use async_trait::async_trait;
+    use futures::future::join_all;
+    use serde_json::json;
+    use tokio_test::block_on;
+
+    use super::WorkerPool;
+    use crate::interpreter::{
+        data::{ScenarioOutput, ScenarioRuntimeError, VarContext},
+        Interpreter,
+    };
+
+    //records names of threads it's invoked on
+    struct ThreadRecording(Mutex<HashSet<String>>);
+
+    #[async_trait]
+    impl Interpreter for ThreadRecording {
+        async fn run(&self, data: &VarContext) -> Result<ScenarioOutput, ScenarioRuntimeError> {
+            if data.get("input") == Some(&json!("panic")) {
+                panic!("Failure in worker");
+            }
+            let name = thread::current().name().unwrap_or_default().to_string();
+            self.0.lock().unwrap().insert(name);
+            //so that other workers take next invocations
+            thread::sleep(std::time::Duration::from_millis(20));
+            Ok(ScenarioOutput(vec![]))
+        }
+    }
+
+    #[test]
+    fn test_runs_on_workers() {
+        let pool = WorkerPool::new(3);
+        let recording = Arc::new(ThreadRecording(Mutex::new(HashSet::new())));
+        let invocations = (0..6).map(|i| {
+            pool.run(
+                recording.clone(),
+                VarContext::default_context_for_value(json!(i)),
+            )
+        });
+        for result in block_on(join_all(invocations)) {
+            assert!(result.is_ok());
+        }
+        let threads = recording.0.lock().unwrap().clone();
+        assert_eq!(
+            threads,
+            HashSet::from(
+                [
+                    "scenario-worker-0",
+                    "scenario-worker-1",
+                    "scenario-worker-2"
+                ]
+                .map(String::from)
+            )
+        );
+
+        let failed = block_on(pool.run(
+            recording.clone(),
+            VarContext::default_context_for_value(json!("panic")),
+        ));
+        assert!(matches!(
+            failed,
+            Err(ScenarioRuntimeError::WorkerFailure(_))
+        ));
+        //workers survive panics
+        assert!(block_on(pool.run(recording, VarContext::empty())).is_ok());
+    }
+}
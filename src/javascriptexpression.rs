@@ -1,52 +1,324 @@
-use std::{cell::RefCell, collections::HashMap, error::Error, fmt::Display};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
 
 use super::expression::{CompiledExpression, Parser};
 use crate::{
     expression::ParseError,
-    interpreter::data::{CompilationVarContext, ScenarioRuntimeError, VarContext, VarValue},
+    interpreter::{
+        clock::TIMER,
+        data::{CompilationVarContext, ScenarioRuntimeError, VarContext, VarValue},
+    },
 };
+use deno_core::{v8, JsRuntime, RuntimeOptions, Snapshot};
 use js_sandbox::{AnyError, Script};
+use once_cell::sync::OnceCell;
 use serde_json::Value;
 
-pub struct JavaScriptParser;
+mod identifiers;
+mod location;
+
+pub use location::SourceLocation;
+use location::{ExpressionLayout, SCRIPT_NAME};
+
+///Controls whether state created by one invocation of an expression (e.g. `globalThis.x = input`)
+///can be seen by the following invocations on the same thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum Isolation {
+    ///Each expression has its own runtime cached per thread and shares its global object between invocations.
+    ///Fast, but not safe if invocations for different users/requests must not see each other's data.
+    #[default]
+    Shared,
+    ///Like Shared, but expressions parsed by the same parser also share one runtime per thread (with helpers
+    ///evaluated once), so they see each other's global state. Uses less memory if there are many expressions.
+    SingleRuntime,
+    ///Each expression has its own runtime cached per thread, the expression runs in strict mode and the global object
+    ///(with everything reachable from it, including builtin prototypes) is deeply frozen before first invocation,
+    ///so there is no place where an expression can leave state for the next one.
+    Frozen,
+    ///Each invocation gets a new runtime restored from the snapshot. Guaranteed isolation, but much slower.
+    FreshContext,
+}
+
+///Resources a single invocation of an expression may use. Exceeding them ends the invocation with
+///JavascriptExecutionError::ExecutionTimeExceeded or JavascriptExecutionError::HeapLimitExceeded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub struct Limits {
+    ///Expressions are synchronous, so wall-clock time of invocation is (more or less) the CPU time it takes
+    pub max_execution_time: Option<Duration>,
+    ///Heap of the runtime, which is shared by expressions with Isolation::SingleRuntime
+    pub max_heap_bytes: Option<usize>,
+}
+
+#[derive(Default)]
+pub struct JavaScriptParser {
+    isolation: Isolation,
+    limits: Limits,
+    ///Code evaluated once in each runtime, in addition to helpers baked into the snapshot (see build.rs)
+    helpers: String,
+    global_names: OnceCell<HashSet<String>>,
+}
+
+impl JavaScriptParser {
+    pub fn new(isolation: Isolation) -> JavaScriptParser {
+        JavaScriptParser {
+            isolation,
+            ..JavaScriptParser::default()
+        }
+    }
+
+    ///Makes functions defined in given code available to all expressions,
+    ///e.g. `globalThis.helpers.vat = (amount) => amount * 0.23`
+    pub fn with_helpers(mut self, code: &str) -> JavaScriptParser {
+        self.helpers.push_str(code);
+        self.helpers.push('\n');
+        self.global_names = OnceCell::new();
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> JavaScriptParser {
+        self.limits = limits;
+        self
+    }
+}
+
+//name of the function evaluating expression in its own runtime
+const EXPRESSION_FUNCTION: &str = "expression";
+
+//defines function evaluating expression in runtime shared by many expressions
+const DEFINE_EXPRESSION_SCRIPT: &str =
+    "function defineExpression(name, source) { globalThis[name] = (0, eval)(source) }";
+
+//Walks all properties (and prototypes) reachable from global object and freezes them
+const LOCKDOWN_SCRIPT: &str = r#"
+(function lockdown(root) {
+    const seen = new Set();
+    const freeze = (obj) => {
+        if (obj === null || obj === undefined || (typeof obj !== 'object' && typeof obj !== 'function') || seen.has(obj)) {
+            return;
+        }
+        seen.add(obj);
+        Object.freeze(obj);
+        for (const key of Reflect.ownKeys(obj)) {
+            const descriptor = Object.getOwnPropertyDescriptor(obj, key);
+            if (descriptor) {
+                freeze(descriptor.value);
+                freeze(descriptor.get);
+                freeze(descriptor.set);
+            }
+        }
+        freeze(Object.getPrototypeOf(obj));
+    };
+    freeze(root);
+})(globalThis);
+"#;
 
 ///Essentially we wrap given expression in a function, that takes one argument (as it's required by js-sandbox crate), and destructures in
-///as we assume that the argument is an object composed of all variables available
+///as we assume that the argument is an object composed of variables used by the expression.
+///The function is defined with (indirect) eval in a runtime where helpers have already been evaluated
 impl Parser for JavaScriptParser {
     fn parse(
         &self,
         expression: &str,
         var_context: &CompilationVarContext,
     ) -> Result<Box<dyn CompiledExpression>, Box<dyn ParseError>> {
-        let keys = var_context
+        let identifiers = identifiers::analyse(expression);
+        //we don't care if the variable is shadowed by local declaration, passing it is harmless.
+        //If the expression couldn't be analysed, it gets all variables
+        let mut used_vars: Vec<String> = var_context
             .0
             .keys()
-            //TODO: is it possible to get rid of it?
+            .filter(|name| match &identifiers {
+                Some(identifiers) => identifiers.referenced.contains(*name),
+                None => true,
+            })
             .cloned()
-            .collect::<Vec<String>>()
-            .join(", ");
-        let expanded = format!(
+            .collect();
+        used_vars.sort();
+        let directive = match self.isolation {
+            Isolation::Frozen => "\"use strict\";",
+            Isolation::Shared | Isolation::SingleRuntime | Isolation::FreshContext => "",
+        };
+        let prefix = format!(
             r#"function run (argMap) {{
+            {}
             const {{ {} }} = argMap
-            return ({})
+            return ("#,
+            directive,
+            used_vars.join(", "),
+        );
+        let function = format!(
+            r#"{}{})
         }}"#,
-            keys, expression
+            prefix, expression
         );
-        let buffer = include_bytes!("../snapshots/query_runtime.snap");
+        let layout = ExpressionLayout::new(&prefix, expression);
         //we ignore the result, as we just want to check if expression is correct
-        let _compiled = Script::from_string(&expanded, Some(buffer)).map_err(|err| {
-            //looks clumsy, but type inference fails here :/
-            let ret: Box<dyn ParseError> = Box::new(JavascriptParseError(err));
-            ret
+        let _compiled = Script::from_string(&function, None).map_err(|error| {
+            to_parse_error(JavascriptParseError::Syntax {
+                location: layout.locate(&error),
+                error,
+            })
+        })?;
+        let globals = self.global_names().map_err(|error| {
+            to_parse_error(JavascriptParseError::Syntax {
+                error,
+                location: None,
+            })
         })?;
-        Ok(Box::new(JavascriptExpression {
-            transformed: expanded,
-        }))
+        //positions in code passed to eval are reported relative to it, with the name given in sourceURL
+        let function = format!("({})\n//# sourceURL={}", function, SCRIPT_NAME);
+        let mut compiled = JavascriptExpression {
+            runtime_key: RuntimeKey {
+                helpers: self.helpers.clone(),
+                limits: self.limits,
+                isolation: self.isolation,
+                function: match self.isolation {
+                    Isolation::SingleRuntime => None,
+                    Isolation::Shared | Isolation::Frozen | Isolation::FreshContext => {
+                        Some(function.clone())
+                    }
+                },
+            },
+            function,
+            layout,
+            used_vars,
+            analysed: false,
+            constant: false,
+            isolation: self.isolation,
+        };
+        let identifiers = match identifiers {
+            Some(identifiers) => identifiers,
+            None => return Ok(Box::new(compiled)),
+        };
+        let unknown: Vec<String> = identifiers
+            .required
+            .iter()
+            .filter(|name| !var_context.0.contains_key(*name) && !globals.contains(*name))
+            .cloned()
+            .collect();
+        if !unknown.is_empty() {
+            return Err(to_parse_error(JavascriptParseError::UnknownIdentifiers(
+                unknown,
+            )));
+        }
+        //even without identifiers, an expression could reach global state through builtins
+        //(e.g. `''.constructor.constructor('return Math.random()')()`) or never end, so only literals are constant
+        compiled.constant = identifiers::is_literal(expression);
+        compiled.analysed = true;
+        Ok(Box::new(compiled))
+    }
+}
+
+//looks clumsy, but type inference fails without it :/
+fn to_parse_error(error: JavascriptParseError) -> Box<dyn ParseError> {
+    Box::new(error)
+}
+
+impl JavaScriptParser {
+    ///Names available in global object of the runtime (Math, JSON, helpers etc.) - they are not reported as unknown identifiers
+    fn global_names(&self) -> Result<&HashSet<String>, AnyError> {
+        self.global_names.get_or_try_init(|| {
+            let buffer = include_bytes!("../snapshots/query_runtime.snap");
+            let mut script = Script::from_string(
+                &format!(
+                    "{}function globals() {{ return Object.getOwnPropertyNames(globalThis) }}",
+                    self.helpers
+                ),
+                Some(buffer),
+            )?;
+            script.call::<(Value,), HashSet<String>>("globals", (Value::Null,))
+        })
     }
 }
 
 struct JavascriptExpression {
-    transformed: String,
+    ///Source of the function, to be evaluated in the runtime
+    function: String,
+    runtime_key: RuntimeKey,
+    layout: ExpressionLayout,
+    used_vars: Vec<String>,
+    ///false if identifiers used by the expression are not known, then it gets all variables
+    analysed: bool,
+    constant: bool,
+    isolation: Isolation,
+}
+
+///Runtimes are cached by what is evaluated in them, so that expressions parsed again (e.g. when the scenario
+///is reloaded) reuse them instead of creating new ones on each thread
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct RuntimeKey {
+    helpers: String,
+    limits: Limits,
+    isolation: Isolation,
+    ///None for runtime shared by expressions (see Isolation::SingleRuntime)
+    function: Option<String>,
+}
+
+struct Runtime {
+    script: Script,
+    ///Names of functions defined in shared runtime, by their source
+    defined: HashMap<String, String>,
+    isolate: v8::IsolateHandle,
+    ///Set by whatever terminated the execution - the watchdog or near heap limit callback
+    exceeded: Arc<Mutex<Option<JavascriptExecutionError>>>,
+}
+
+//the limit is recorded before termination, so that the error it causes can be told apart from other ones
+fn terminate(
+    isolate: &v8::IsolateHandle,
+    exceeded: &Mutex<Option<JavascriptExecutionError>>,
+    limit: JavascriptExecutionError,
+) {
+    exceeded
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_or_insert(limit);
+    isolate.terminate_execution();
+}
+
+///Terminates the execution when the time is up, unless it's dropped before
+struct Watchdog(u64);
+
+impl Watchdog {
+    fn start(runtime: &Runtime, time: Duration) -> Option<Watchdog> {
+        let deadline = Instant::now().checked_add(time)?;
+        let isolate = runtime.isolate.clone();
+        let exceeded = runtime.exceeded.clone();
+        Some(Watchdog(TIMER.schedule(deadline, move || {
+            terminate(
+                &isolate,
+                &exceeded,
+                JavascriptExecutionError::ExecutionTimeExceeded(time),
+            )
+        })))
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        TIMER.cancel(self.0);
+    }
+}
+
+impl Runtime {
+    //returns name of the function evaluating the expression, defines it on first use
+    fn define(&mut self, function: &str) -> Result<String, JavascriptExecutionError> {
+        if let Some(name) = self.defined.get(function) {
+            return Ok(name.clone());
+        }
+        let name = format!("expression{}", self.defined.len());
+        self.script
+            .call::<(&str, &str), Value>("defineExpression", (&name, function))
+            .map_err(JavascriptExecutionError::ScriptParse)?;
+        self.defined.insert(String::from(function), name.clone());
+        Ok(name)
+    }
 }
 
 impl JavascriptExpression {
@@ -59,42 +331,163 @@ impl JavascriptExpression {
     like that. Currently, the implementation is "unsafe" - one write `Object.global = input` and pass the state to next invocation on a given thread...
 
     Other way to solve this is to parse Script on each invocation, but it's hopelessly inefficient then.
+    Both ways can be chosen with Isolation (Frozen/FreshContext), if the "unsafe" default is not acceptable.
     */
     thread_local! {
-        static CACHED_SCRIPTS: RefCell<HashMap<String,Script>>  = RefCell::new(HashMap::from([]));
+        //all modes except Isolation::FreshContext
+        static RUNTIMES: RefCell<HashMap<RuntimeKey, Runtime>> = RefCell::new(HashMap::new());
     }
 
     fn execute_script(
-        script: &mut Script,
+        &self,
+        runtime: &mut Runtime,
+        name: &str,
         input_data: &VarContext,
-    ) -> Result<VarValue, ScenarioRuntimeError> {
-        let converted = serde_json::to_value(&input_data.to_external_form())
-            .map_err(JavascriptExecutionError::InputParse)?;
-        script
-            .call::<(Value,), Value>("run", (converted,))
-            .map_err(|err| ScenarioRuntimeError::from(JavascriptExecutionError::RuntimeError(err)))
+    ) -> Result<VarValue, JavascriptExecutionError> {
+        let used: HashMap<&str, &VarValue> = self
+            .used_vars
+            .iter()
+            .filter_map(|name| input_data.get(name).map(|value| (name.as_str(), value)))
+            .collect();
+        let converted = serde_json::to_value(used).map_err(JavascriptExecutionError::InputParse)?;
+        let watchdog = self
+            .runtime_key
+            .limits
+            .max_execution_time
+            .and_then(|time| Watchdog::start(runtime, time));
+        let result = runtime.script.call::<(Value,), Value>(name, (converted,));
+        //once the watchdog is stopped, nothing else can terminate the execution
+        drop(watchdog);
+        let exceeded = runtime
+            .exceeded
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        match exceeded {
+            //termination can't be caught by the expression, it may also happen just after it finished
+            Some(limit) => {
+                runtime.isolate.cancel_terminate_execution();
+                Err(limit)
+            }
+            None => result.map_err(|error| JavascriptExecutionError::RuntimeError {
+                location: self.layout.locate(&error),
+                error,
+            }),
+        }
     }
-}
 
-impl CompiledExpression for JavascriptExpression {
-    fn execute(&self, input_data: &VarContext) -> Result<VarValue, ScenarioRuntimeError> {
-        JavascriptExpression::CACHED_SCRIPTS.with(|c| {
+    //runtime with helpers, and either with the expression (then frozen if needed) or the possibility to define it
+    fn create_runtime(&self) -> Result<Runtime, JavascriptExecutionError> {
+        let key = &self.runtime_key;
+        let code = if key.function.is_some() {
+            let lockdown = match self.isolation {
+                Isolation::Frozen => LOCKDOWN_SCRIPT,
+                Isolation::Shared | Isolation::SingleRuntime | Isolation::FreshContext => "",
+            };
+            let source = serde_json::to_string(&self.function)
+                .map_err(JavascriptExecutionError::InputParse)?;
+            format!(
+                "{}globalThis.{} = (0, eval)({});{}",
+                key.helpers, EXPRESSION_FUNCTION, source, lockdown
+            )
+        } else {
+            format!("{}{}", key.helpers, DEFINE_EXPRESSION_SCRIPT)
+        };
+        let mut create_params = v8::CreateParams::default();
+        if let Some(heap) = key.limits.max_heap_bytes {
+            create_params = create_params.heap_limits(0, heap);
+        }
+        let mut js_runtime = JsRuntime::new(RuntimeOptions {
+            startup_snapshot: Some(Snapshot::Static(include_bytes!(
+                "../snapshots/query_runtime.snap"
+            ))),
+            create_params: Some(create_params),
+            ..Default::default()
+        });
+        let isolate = js_runtime.v8_isolate().thread_safe_handle();
+        let exceeded = Arc::new(Mutex::new(None));
+        if let Some(heap) = key.limits.max_heap_bytes {
+            let (isolate, exceeded) = (isolate.clone(), exceeded.clone());
+            //V8 aborts the process when the heap is exhausted, so the limit is raised to let the terminated execution unwind
+            js_runtime.add_near_heap_limit_callback(move |current, _initial| {
+                terminate(
+                    &isolate,
+                    &exceeded,
+                    JavascriptExecutionError::HeapLimitExceeded(heap),
+                );
+                current * 2
+            });
+        }
+        Ok(Runtime {
+            script: Script::from_runtime(js_runtime, &code)
+                .map_err(JavascriptExecutionError::ScriptParse)?,
+            defined: HashMap::new(),
+            isolate,
+            exceeded,
+        })
+    }
+
+    //expressions are defined in shared runtime on first use
+    fn execute_cached(&self, input_data: &VarContext) -> Result<VarValue, ScenarioRuntimeError> {
+        JavascriptExpression::RUNTIMES.with(|c| {
             let mut map = c.borrow_mut();
-            if !map.contains_key(&self.transformed) {
-                let buffer = include_bytes!("../snapshots/query_runtime.snap");
-                let expression = Script::from_string(&self.transformed, Some(buffer))
-                    .map_err(JavascriptExecutionError::ScriptParse)?;
-                map.insert(self.transformed.clone(), expression);
+            if !map.contains_key(&self.runtime_key) {
+                map.insert(self.runtime_key.clone(), self.create_runtime()?);
             }
             //we are sure the key is present
-            let expression = map.get_mut(&self.transformed).unwrap();
-            JavascriptExpression::execute_script(expression, input_data)
+            let runtime = map.get_mut(&self.runtime_key).unwrap();
+            let name = match &self.runtime_key.function {
+                Some(_) => String::from(EXPRESSION_FUNCTION),
+                None => runtime.define(&self.function)?,
+            };
+            let result = self.execute_script(runtime, &name, input_data);
+            //terminated runtime (and possibly its exhausted heap) should not be reused
+            if let Err(
+                JavascriptExecutionError::ExecutionTimeExceeded(_)
+                | JavascriptExecutionError::HeapLimitExceeded(_),
+            ) = result
+            {
+                map.remove(&self.runtime_key);
+            }
+            Ok(result?)
         })
     }
 }
 
+impl CompiledExpression for JavascriptExpression {
+    fn execute(&self, input_data: &VarContext) -> Result<VarValue, ScenarioRuntimeError> {
+        match self.isolation {
+            Isolation::Shared | Isolation::SingleRuntime | Isolation::Frozen => {
+                self.execute_cached(input_data)
+            }
+            Isolation::FreshContext => {
+                let mut runtime = self.create_runtime()?;
+                Ok(self.execute_script(&mut runtime, EXPRESSION_FUNCTION, input_data)?)
+            }
+        }
+    }
+
+    fn used_variables(&self) -> Option<Vec<String>> {
+        if self.analysed {
+            Some(self.used_vars.clone())
+        } else {
+            None
+        }
+    }
+
+    fn is_constant(&self) -> bool {
+        self.constant
+    }
+}
+
 #[derive(Debug)]
-struct JavascriptParseError(AnyError);
+enum JavascriptParseError {
+    Syntax {
+        error: AnyError,
+        location: Option<SourceLocation>,
+    },
+    UnknownIdentifiers(Vec<String>),
+}
 
 impl Error for JavascriptParseError {}
 
@@ -102,16 +495,32 @@ impl ParseError for JavascriptParseError {}
 
 impl Display for JavascriptParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        //this should be nicely handled, just like in ForEachError...
-        write!(f, "Cannot parse: {}", self.0)
+        match self {
+            Self::Syntax {
+                error,
+                location: Some(location),
+            } => write!(f, "Cannot parse: {} at {}", first_line(error), location),
+            Self::Syntax {
+                error,
+                location: None,
+            } => write!(f, "Cannot parse: {}", error),
+            Self::UnknownIdentifiers(names) => {
+                write!(f, "Unknown identifiers: {}", names.join(", "))
+            }
+        }
     }
 }
 
 #[derive(Debug)]
-enum JavascriptExecutionError {
+pub enum JavascriptExecutionError {
     ScriptParse(AnyError),
     InputParse(serde_json::Error),
-    RuntimeError(AnyError),
+    RuntimeError {
+        error: AnyError,
+        location: Option<SourceLocation>,
+    },
+    ExecutionTimeExceeded(Duration),
+    HeapLimitExceeded(usize),
 }
 
 impl From<JavascriptExecutionError> for ScenarioRuntimeError {
@@ -122,25 +531,55 @@ impl From<JavascriptExecutionError> for ScenarioRuntimeError {
 
 impl Display for JavascriptExecutionError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        //this should be nicely handled, just like in ForEachError...
-        write!(f, "Error occurred: {:?}", self)
+        match self {
+            Self::ExecutionTimeExceeded(time) => {
+                write!(f, "Expression execution exceeded {:?}", time)
+            }
+            Self::HeapLimitExceeded(heap) => {
+                write!(
+                    f,
+                    "Expression execution exceeded heap limit of {} bytes",
+                    heap
+                )
+            }
+            Self::RuntimeError {
+                error,
+                location: Some(location),
+            } => write!(f, "Error occurred: {} at {}", first_line(error), location),
+            //this should be nicely handled, just like in ForEachError...
+            other => write!(f, "Error occurred: {:?}", other),
+        }
     }
 }
 impl Error for JavascriptExecutionError {}
 
+//we skip stack trace, as it refers to generated code anyway
+fn first_line(error: &AnyError) -> String {
+    error
+        .to_string()
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
 #[cfg(test)]
 //There are far too few tests for this parser.
 mod tests {
     use crate::{
         expression::Parser,
-        interpreter::data::{CompilationVarContext, VarContext},
-        javascriptexpression::JavaScriptParser,
+        interpreter::data::{CompilationVarContext, ScenarioRuntimeError, VarContext},
+        javascriptexpression::{
+            Isolation, JavaScriptParser, JavascriptExecutionError, JavascriptExpression, Limits,
+        },
+        scenariomodel::NodeId,
     };
-    use serde_json::json;
+    use serde_json::{json, Value};
+    use std::time::Duration;
 
     #[test]
     fn test_simple_expression() -> Result<(), Box<dyn std::error::Error>> {
-        let expr = JavaScriptParser
+        let expr = JavaScriptParser::default()
             .parse("10 + 5", &CompilationVarContext::default())
             .unwrap();
         let res = expr.execute(&VarContext::empty())?;
@@ -150,13 +589,194 @@ mod tests {
 
     #[test]
     fn test_parse_wrong_expression() {
-        let expr = JavaScriptParser.parse("return aaaa", &CompilationVarContext::default());
+        let expr =
+            JavaScriptParser::default().parse("return aaaa", &CompilationVarContext::default());
         assert!(expr.is_err());
     }
 
+    #[test]
+    fn test_parse_unknown_identifier() {
+        let expr = JavaScriptParser::default().parse(
+            "input + unknown + Math.abs(JSON.parse('1'))",
+            &CompilationVarContext::default(),
+        );
+        assert_eq!(
+            expr.err().map(|err| err.to_string()),
+            Some(String::from("Unknown identifiers: unknown"))
+        );
+    }
+
+    #[test]
+    fn test_passes_only_used_variables() -> Result<(), Box<dyn std::error::Error>> {
+        let var_context = CompilationVarContext::default()
+            .with_var(&NodeId::new("node"), "other")?
+            .with_var(&NodeId::new("node"), "used")?;
+        let data = VarContext::default_context_for_value(json!(1))
+            .with_new_var("used", json!("value"))
+            .with_new_var("other", json!("other"));
+        //the expression is invoked with the variables as the only argument
+        let expr = JavaScriptParser::default()
+            .parse("[used, Object.keys(arguments[0])]", &var_context)
+            .unwrap();
+        assert_eq!(expr.execute(&data)?, json!(["value", ["used"]]));
+
+        //syntax which is not understood by the analysis gets all variables
+        let expr = JavaScriptParser::default()
+            .parse(
+                "({ keys() { return Object.keys(argMap) } }).keys()",
+                &var_context,
+            )
+            .unwrap();
+        assert_eq!(expr.used_variables(), None);
+        assert_eq!(expr.execute(&data)?, json!(["input", "other", "used"]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_valid_syntax_is_not_rejected() -> Result<(), Box<dyn std::error::Error>> {
+        let execute = |expression: &str| {
+            JavaScriptParser::default()
+                .parse(expression, &CompilationVarContext::default())
+                .unwrap()
+                .execute(&VarContext::default_context_for_value(json!(2)))
+        };
+        assert_eq!(execute("({ f() { return input } }).f()")?, json!(2));
+        assert_eq!(
+            execute("({ get double() { return input * 2 } }).double")?,
+            json!(4)
+        );
+        assert_eq!(execute("typeof undeclaredGlobal")?, json!("undefined"));
+        assert_eq!(
+            execute("(() => { outer: for (const i of [1, 2]) { if (i == input) break outer } return input })()")?,
+            json!(2)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_introspection() {
+        let parse = |expression: &str| {
+            JavaScriptParser::default()
+                .parse(expression, &CompilationVarContext::default())
+                .unwrap()
+        };
+        let expr = parse("[1, 2].map(x => x * input.factor)");
+        assert_eq!(expr.used_variables(), Some(vec![String::from("input")]));
+        assert!(!expr.is_constant());
+        assert!(parse("({ risk: 'number', limit: 60 * 1000 })").is_constant());
+        assert!(!parse("['a', 'b', 'c'].map(x => x.toUpperCase())").is_constant());
+        assert!(!parse("''.constructor.constructor('return Math.random()')()").is_constant());
+        assert!(!parse("(() => { while (true) {} })()").is_constant());
+        assert!(!parse("Math.random()").is_constant());
+        assert!(!parse("this.counter").is_constant());
+        //parameter shadows the variable only inside the arrow function
+        let expr = parse("[1].map(input => input).concat(input)");
+        assert_eq!(expr.used_variables(), Some(vec![String::from("input")]));
+        assert!(!expr.is_constant());
+    }
+
+    #[test]
+    fn test_helpers() -> Result<(), Box<dyn std::error::Error>> {
+        let expr = JavaScriptParser::default()
+            .with_helpers("globalThis.helpers.suffix = (value) => value + '-suffix'")
+            .parse(
+                "[helpers.padLeft(input, 4), helpers.roundMoney(1.005), helpers.suffix(input)]",
+                &CompilationVarContext::default(),
+            )
+            .unwrap();
+        let res = expr.execute(&VarContext::default_context_for_value(json!(12)))?;
+        assert_eq!(res, json!(["0012", 1.01, "12-suffix"]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_helpers_evaluated_once_per_runtime() -> Result<(), Box<dyn std::error::Error>> {
+        let parser = JavaScriptParser::new(Isolation::SingleRuntime).with_helpers(
+            "globalThis.helpers.evaluations = (globalThis.helpers.evaluations || 0) + 1",
+        );
+        let evaluations = |expression: &str| {
+            parser
+                .parse(expression, &CompilationVarContext::default())
+                .unwrap()
+                .execute(&VarContext::default_context_for_value(json!(1)))
+        };
+        assert_eq!(evaluations("helpers.evaluations")?, json!(1));
+        assert_eq!(evaluations("helpers.evaluations + input - 1")?, json!(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_runtimes_reused_when_parsed_again() {
+        //runtimes and functions defined in them, on the current thread
+        let cached = || {
+            JavascriptExpression::RUNTIMES.with(|runtimes| {
+                let runtimes = runtimes.borrow();
+                let functions: usize = runtimes.values().map(|r| r.defined.len()).sum();
+                (runtimes.len(), functions)
+            })
+        };
+        for isolation in [
+            Isolation::Shared,
+            Isolation::SingleRuntime,
+            Isolation::Frozen,
+        ] {
+            let execute = || {
+                JavaScriptParser::new(isolation)
+                    .parse("input + 1", &CompilationVarContext::default())
+                    .unwrap()
+                    .execute(&VarContext::default_context_for_value(json!(1)))
+                    .unwrap()
+            };
+            execute();
+            let after_first = cached();
+            assert_eq!(execute(), json!(2));
+            assert_eq!(cached(), after_first, "{:?}", isolation);
+        }
+    }
+
+    #[test]
+    fn test_error_locations() {
+        let parse_error = JavaScriptParser::default()
+            .with_helpers("globalThis.helpers.unused = 1;\n\n")
+            .parse("input +\n  ))", &CompilationVarContext::default())
+            .err()
+            .unwrap();
+        assert!(parse_error.to_string().contains("at line 2, column"));
+
+        //positions must not depend on helpers or on the way expression is evaluated
+        for isolation in [
+            Isolation::Shared,
+            Isolation::SingleRuntime,
+            Isolation::Frozen,
+            Isolation::FreshContext,
+        ] {
+            let expr = JavaScriptParser::new(isolation)
+                .with_helpers("globalThis.helpers.unused = 1;\n\n")
+                .parse(
+                    "input +\n  (() => { throw Error('failed') })()",
+                    &CompilationVarContext::default(),
+                )
+                .unwrap();
+            match expr.execute(&VarContext::default_context_for_value(json!(10))) {
+                Err(ScenarioRuntimeError::ExpressionError(error)) => {
+                    match *error.downcast::<JavascriptExecutionError>().unwrap() {
+                        JavascriptExecutionError::RuntimeError {
+                            location: Some(location),
+                            ..
+                        } => {
+                            assert_eq!((location.line, location.column), (2, 18), "{:?}", isolation)
+                        }
+                        other => panic!("Unexpected: {:?}", other),
+                    }
+                }
+                other => panic!("Unexpected: {:?}", other),
+            }
+        }
+    }
+
     #[test]
     fn test_expression_with_variable() -> Result<(), Box<dyn std::error::Error>> {
-        let expr = JavaScriptParser
+        let expr = JavaScriptParser::default()
             .parse("input + 5", &CompilationVarContext::default())
             .unwrap();
         let res = expr.execute(&VarContext::default_context_for_value(json!(10)))?;
@@ -170,7 +790,7 @@ mod tests {
         let suffix = "+suffix";
         let expected = "my_input+suffix";
 
-        let expr = JavaScriptParser
+        let expr = JavaScriptParser::default()
             .parse(
                 &format!(
                     "[input].map(x => {{
@@ -186,4 +806,149 @@ mod tests {
         assert_eq!(result, json!(expected));
         Ok(())
     }
+
+    //returns value left in global object by previous invocation (if any), and tries to leave the current input there
+    fn previous_global_values(isolation: Isolation) -> Vec<Value> {
+        let expr = JavaScriptParser::new(isolation)
+            .parse(
+                "(() => {
+                    const previous = globalThis.leaked;
+                    try { globalThis.leaked = input } catch (e) {}
+                    return previous === undefined ? null : previous;
+                })()",
+                &CompilationVarContext::default(),
+            )
+            .unwrap();
+        [json!(1), json!(2)]
+            .into_iter()
+            .map(|input| {
+                expr.execute(&VarContext::default_context_for_value(input))
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_shared_isolation_leaks_state() {
+        assert_eq!(
+            previous_global_values(Isolation::Shared),
+            [Value::Null, json!(1)]
+        );
+    }
+
+    #[test]
+    fn test_only_single_runtime_shares_state_between_expressions(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for (isolation, expected) in [
+            (Isolation::Shared, Value::Null),
+            (Isolation::SingleRuntime, json!(1)),
+        ] {
+            let parser = JavaScriptParser::new(isolation);
+            let execute = |expression: &str| {
+                parser
+                    .parse(expression, &CompilationVarContext::default())
+                    .unwrap()
+                    .execute(&VarContext::default_context_for_value(json!(1)))
+            };
+            execute("(() => { globalThis.fromOther = input; return null })()")?;
+            assert_eq!(
+                execute("globalThis.fromOther === undefined ? null : globalThis.fromOther")?,
+                expected,
+                "{:?}",
+                isolation
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_isolated_modes_do_not_leak_state() {
+        for isolation in [Isolation::Frozen, Isolation::FreshContext] {
+            assert_eq!(
+                previous_global_values(isolation),
+                [Value::Null, Value::Null],
+                "{:?}",
+                isolation
+            );
+        }
+    }
+
+    #[test]
+    fn test_frozen_builtins() {
+        let expr = JavaScriptParser::new(Isolation::Frozen)
+            .parse(
+                "(() => { Array.prototype.leaked = input; return [].leaked })()",
+                &CompilationVarContext::default(),
+            )
+            .unwrap();
+        assert!(expr
+            .execute(&VarContext::default_context_for_value(json!(1)))
+            .is_err());
+    }
+
+    fn execution_error(limits: Limits, expression: &str) -> JavascriptExecutionError {
+        let expr = JavaScriptParser::default()
+            .with_limits(limits)
+            .parse(expression, &CompilationVarContext::default())
+            .unwrap();
+        match expr.execute(&VarContext::default_context_for_value(json!(10))) {
+            Err(ScenarioRuntimeError::ExpressionError(error)) => {
+                *error.downcast::<JavascriptExecutionError>().unwrap()
+            }
+            other => panic!("Unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_execution_time_limit() {
+        let time = Duration::from_millis(100);
+        let limits = Limits {
+            max_execution_time: Some(time),
+            ..Limits::default()
+        };
+        match execution_error(limits, "(() => { while (true) {} })()") {
+            JavascriptExecutionError::ExecutionTimeExceeded(exceeded) => assert_eq!(exceeded, time),
+            other => panic!("Unexpected: {:?}", other),
+        }
+        //script is usable after termination
+        let expr = JavaScriptParser::default()
+            .with_limits(limits)
+            .parse("input + 1", &CompilationVarContext::default())
+            .unwrap();
+        let result = expr
+            .execute(&VarContext::default_context_for_value(json!(1)))
+            .unwrap();
+        assert_eq!(result, json!(2));
+
+        //only the watchdog decides that the time was exceeded
+        match execution_error(limits, "(() => { throw Error('execution terminated') })()") {
+            JavascriptExecutionError::RuntimeError { .. } => (),
+            other => panic!("Unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_heap_limit() {
+        let heap = 16 * 1024 * 1024;
+        let limits = Limits {
+            max_heap_bytes: Some(heap),
+            ..Limits::default()
+        };
+        match execution_error(
+            limits,
+            "(() => { const all = []; while (true) { all.push(new Array(input * 1000).fill('x')) } })()",
+        ) {
+            JavascriptExecutionError::HeapLimitExceeded(exceeded) => assert_eq!(exceeded, heap),
+            other => panic!("Unexpected: {:?}", other),
+        }
+        //new runtime is created for the next invocation
+        let expr = JavaScriptParser::default()
+            .with_limits(limits)
+            .parse("input + 1", &CompilationVarContext::default())
+            .unwrap();
+        let result = expr
+            .execute(&VarContext::default_context_for_value(json!(1)))
+            .unwrap();
+        assert_eq!(result, json!(2));
+    }
 }
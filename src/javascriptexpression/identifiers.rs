@@ -0,0 +1,614 @@
+use std::{collections::BTreeSet, ops::Range};
+
+/*
+This is not a real JS parser (the syntax itself is checked by the runtime), just a tokenizer with some heuristics,
+good enough to find out which identifiers are used by (usually short) expressions.
+When in doubt, we rather treat identifier as referenced (we'll pass one variable too many) and as declared
+(we won't report unknown identifier, it will fail at runtime, as it used to).
+Declarations are visible only in their scope (function, arrow function, block or catch clause).
+For syntax the heuristics don't understand (classes, object methods, getters/setters, `with`) there is no result,
+the expression is then treated as if it could use any variable.
+ */
+
+#[derive(Debug, PartialEq, Eq, Default)]
+pub(super) struct Identifiers {
+    ///Identifiers read by the expression (excluding property names), including the ones declared in expression
+    pub referenced: BTreeSet<String>,
+    ///Referenced identifiers which are neither declared in the scope of reference, nor keywords
+    pub free: BTreeSet<String>,
+    ///Free identifiers which have to exist, i.e. are not only checked with `typeof`
+    pub required: BTreeSet<String>,
+}
+
+const KEYWORDS: &[&str] = &[
+    "arguments",
+    "async",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "from",
+    "function",
+    "get",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "let",
+    "new",
+    "null",
+    "of",
+    "return",
+    "set",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "undefined",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+//after these keywords '/' starts regular expression, not division
+const KEYWORDS_BEFORE_EXPRESSION: &[&str] = &[
+    "return",
+    "typeof",
+    "instanceof",
+    "in",
+    "of",
+    "new",
+    "delete",
+    "void",
+    "throw",
+    "case",
+    "do",
+    "else",
+    "yield",
+    "await",
+];
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Token {
+    Identifier(String),
+    Punctuator(&'static str),
+    //strings, numbers, regular expressions, template parts - we don't care about their content
+    Literal,
+}
+
+pub(super) fn analyse(expression: &str) -> Option<Identifiers> {
+    let tokens = tokenize(expression);
+    let mut result = Identifiers::default();
+    let mut declarations: Vec<(String, Range<usize>)> = vec![];
+    let mut references: Vec<usize> = vec![];
+    for (index, token) in tokens.iter().enumerate() {
+        let previous = index.checked_sub(1).map(|i| &tokens[i]);
+        let next = tokens.get(index + 1);
+        match token {
+            Token::Identifier(name) => {
+                if matches!(name.as_str(), "class" | "with") || is_method(&tokens, index) {
+                    return None;
+                }
+                let is_property = matches!(
+                    previous,
+                    Some(Token::Punctuator(".") | Token::Punctuator("?."))
+                );
+                let is_key = matches!(next, Some(Token::Punctuator(":")))
+                    && matches!(
+                        previous,
+                        Some(Token::Punctuator("{") | Token::Punctuator(","))
+                    );
+                let is_label = (matches!(next, Some(Token::Punctuator(":")))
+                    && matches!(
+                        previous,
+                        Some(Token::Punctuator(";") | Token::Punctuator("}"))
+                    ))
+                    || matches!(previous, Some(Token::Identifier(keyword)) if keyword == "break" || keyword == "continue");
+                if !is_property && !is_key && !is_label {
+                    result.referenced.insert(name.clone());
+                    references.push(index);
+                }
+                match (name.as_str(), next) {
+                    ("function", Some(Token::Identifier(declared))) => {
+                        declarations.push((declared.clone(), enclosing_block(&tokens, index)));
+                        let scope = function_scope(&tokens, index);
+                        declare_group(&tokens, index + 2, scope, &mut declarations);
+                    }
+                    ("function", _) => {
+                        let scope = function_scope(&tokens, index);
+                        declare_group(&tokens, index + 1, scope, &mut declarations)
+                    }
+                    ("let" | "const" | "var", _) => {
+                        let scope = enclosing_block(&tokens, index);
+                        declare_variables(&tokens, index + 1, scope, &mut declarations)
+                    }
+                    ("catch", Some(Token::Punctuator("("))) => {
+                        let block = group_end(&tokens, index + 1) + 1;
+                        let scope = index..group_end(&tokens, block) + 1;
+                        declare_group(&tokens, index + 1, scope, &mut declarations)
+                    }
+                    (_, Some(Token::Punctuator("=>"))) => {
+                        let scope = index..arrow_body_end(&tokens, index + 1);
+                        declarations.push((name.clone(), scope));
+                    }
+                    _ => (),
+                }
+            }
+            Token::Punctuator(")") if matches!(next, Some(Token::Punctuator("=>"))) => {
+                if let Some(start) = group_start(&tokens, index) {
+                    let scope = start..arrow_body_end(&tokens, index + 1);
+                    declare_group(&tokens, start, scope, &mut declarations);
+                }
+            }
+            _ => (),
+        }
+    }
+    for index in references {
+        let name = match &tokens[index] {
+            Token::Identifier(name) => name,
+            _ => continue,
+        };
+        let declared = declarations
+            .iter()
+            .any(|(declared, scope)| declared == name && scope.contains(&index));
+        if declared || KEYWORDS.contains(&name.as_str()) {
+            continue;
+        }
+        result.free.insert(name.clone());
+        //`typeof undeclared` is fine, `typeof undeclared.field` is not
+        let only_typeof = matches!(index.checked_sub(1).map(|i| &tokens[i]), Some(Token::Identifier(keyword)) if keyword == "typeof")
+            && !matches!(
+                tokens.get(index + 1),
+                Some(Token::Punctuator("." | "?." | "[" | "("))
+            );
+        if !only_typeof {
+            result.required.insert(name.clone());
+        }
+    }
+    Some(result)
+}
+
+//object literal methods (`{ f() {...} }`, `{ get value() {...} }`) - their names are neither declared, nor referenced
+///Literals and operators on them (e.g. `'sum'`, `60 * 1000` or `({ risk: 'number' })`). Without identifiers,
+///property access and calls they can neither reach global state nor run for long, so they can be evaluated during compilation
+pub(super) fn is_literal(expression: &str) -> bool {
+    let tokens = tokenize(expression);
+    tokens.iter().enumerate().all(|(index, token)| {
+        let previous = index.checked_sub(1).map(|i| &tokens[i]);
+        match token {
+            Token::Literal => true,
+            Token::Identifier(name) => {
+                matches!(name.as_str(), "true" | "false" | "null")
+                    || (matches!(tokens.get(index + 1), Some(Token::Punctuator(":")))
+                        && matches!(previous, Some(Token::Punctuator("{" | ","))))
+            }
+            //computed property access, e.g. `''['constructor']`
+            Token::Punctuator("[") => !matches!(
+                previous,
+                Some(Token::Literal | Token::Identifier(_) | Token::Punctuator(")" | "]" | "}"))
+            ),
+            Token::Punctuator(punctuator) => !matches!(*punctuator, "." | "?." | "=>" | "..."),
+        }
+    })
+}
+
+fn is_method(tokens: &[Token], index: usize) -> bool {
+    let previous = index.checked_sub(1).map(|i| &tokens[i]);
+    let after_separator = matches!(
+        previous,
+        Some(Token::Punctuator("{") | Token::Punctuator(","))
+    );
+    match (&tokens[index], tokens.get(index + 1)) {
+        (Token::Identifier(name), Some(Token::Identifier(_) | Token::Punctuator("[")))
+            if name == "get" || name == "set" =>
+        {
+            after_separator
+        }
+        (Token::Identifier(name), Some(Token::Punctuator("(")))
+            if !KEYWORDS.contains(&name.as_str()) =>
+        {
+            (after_separator
+                || matches!(previous, Some(Token::Identifier(keyword)) if keyword == "async"))
+                && matches!(
+                    tokens.get(group_end(tokens, index + 1) + 1),
+                    Some(Token::Punctuator("{"))
+                )
+        }
+        _ => false,
+    }
+}
+
+//all identifiers in balanced group (...), {...} or [...] starting at given position are treated as declared
+fn declare_group(
+    tokens: &[Token],
+    start: usize,
+    scope: Range<usize>,
+    declarations: &mut Vec<(String, Range<usize>)>,
+) {
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::Punctuator("(" | "{" | "[") => depth += 1,
+            Token::Punctuator(")" | "}" | "]") => depth -= 1,
+            Token::Identifier(name)
+                if index > start
+                    && !matches!(
+                        tokens[index - 1],
+                        Token::Punctuator(".") | Token::Punctuator("?.")
+                    ) =>
+            {
+                declarations.push((name.clone(), scope.clone()));
+            }
+            _ => (),
+        }
+        if depth <= 0 {
+            return;
+        }
+    }
+}
+
+//declarators after let/const/var, e.g. `a = 1, { b } = c`, up to ';' or end of enclosing group
+fn declare_variables(
+    tokens: &[Token],
+    start: usize,
+    scope: Range<usize>,
+    declarations: &mut Vec<(String, Range<usize>)>,
+) {
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate().skip(start) {
+        let declarator_start =
+            depth == 0 && (index == start || tokens[index - 1] == Token::Punctuator(","));
+        match token {
+            Token::Identifier(name) if declarator_start => {
+                declarations.push((name.clone(), scope.clone()))
+            }
+            Token::Punctuator(";") if depth == 0 => return,
+            Token::Punctuator("(" | "{" | "[") => {
+                if declarator_start {
+                    declare_group(tokens, index, scope.clone(), declarations);
+                }
+                depth += 1
+            }
+            Token::Punctuator(")" | "}" | "]") if depth == 0 => return,
+            Token::Punctuator(")" | "}" | "]") => depth -= 1,
+            _ => (),
+        }
+    }
+}
+
+//position of '(' matching ')' at given position
+fn group_start(tokens: &[Token], end: usize) -> Option<usize> {
+    let mut depth = 0;
+    for index in (0..=end).rev() {
+        match tokens[index] {
+            Token::Punctuator(")" | "}" | "]") => depth += 1,
+            Token::Punctuator("(" | "{" | "[") => depth -= 1,
+            _ => (),
+        }
+        if depth == 0 {
+            return Some(index);
+        }
+    }
+    None
+}
+
+//position of bracket closing the one at given position (or the last one, if it's not closed)
+fn group_end(tokens: &[Token], start: usize) -> usize {
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::Punctuator("(" | "{" | "[") => depth += 1,
+            Token::Punctuator(")" | "}" | "]") => depth -= 1,
+            _ => (),
+        }
+        if depth <= 0 {
+            return index;
+        }
+    }
+    tokens.len()
+}
+
+//innermost {...} containing given position, or the whole expression
+fn enclosing_block(tokens: &[Token], index: usize) -> Range<usize> {
+    let mut depth = 0;
+    for start in (0..index).rev() {
+        match tokens[start] {
+            Token::Punctuator(")" | "}" | "]") => depth += 1,
+            Token::Punctuator("{") if depth == 0 => return start..group_end(tokens, start) + 1,
+            Token::Punctuator("(" | "{" | "[") if depth > 0 => depth -= 1,
+            _ => (),
+        }
+    }
+    0..tokens.len()
+}
+
+//from 'function' keyword to the end of function body
+fn function_scope(tokens: &[Token], index: usize) -> Range<usize> {
+    let parameters = tokens
+        .iter()
+        .skip(index)
+        .position(|token| *token == Token::Punctuator("("))
+        .map(|position| index + position)
+        .unwrap_or(tokens.len());
+    index..group_end(tokens, group_end(tokens, parameters) + 1) + 1
+}
+
+//position after body of arrow function, which is either a block or an expression
+fn arrow_body_end(tokens: &[Token], arrow: usize) -> usize {
+    if tokens.get(arrow + 1) == Some(&Token::Punctuator("{")) {
+        return group_end(tokens, arrow + 1) + 1;
+    }
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate().skip(arrow + 1) {
+        match token {
+            Token::Punctuator("(" | "{" | "[") => depth += 1,
+            Token::Punctuator(")" | "}" | "]" | "," | ";") if depth == 0 => return index,
+            Token::Punctuator(")" | "}" | "]") => depth -= 1,
+            _ => (),
+        }
+    }
+    tokens.len()
+}
+
+const PUNCTUATORS: &[&str] = &[
+    "...", "=>", "?.", "(", ")", "{", "}", "[", "]", ",", ":", ";", ".",
+];
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '$'
+}
+
+fn is_identifier_part(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+fn tokenize(expression: &str) -> Vec<Token> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = vec![];
+    //each element is the brace depth inside ${...} of a template literal
+    let mut templates: Vec<usize> = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let rest: String = chars[i..chars.len().min(i + 3)].iter().collect();
+        if c.is_whitespace() {
+            i += 1;
+        } else if rest.starts_with("//") {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if rest.starts_with("/*") {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i += 2;
+        } else if c == '\'' || c == '"' {
+            i = skip_quoted(&chars, i, c);
+            tokens.push(Token::Literal);
+        } else if c == '`' {
+            i = skip_template(&chars, i + 1, &mut templates);
+            tokens.push(Token::Literal);
+        } else if c == '}' && templates.last() == Some(&0) {
+            templates.pop();
+            i = skip_template(&chars, i + 1, &mut templates);
+            tokens.push(Token::Literal);
+        } else if c.is_ascii_digit()
+            || (c == '.' && matches!(chars.get(i + 1), Some(d) if d.is_ascii_digit()))
+        {
+            i += 1;
+            while i < chars.len()
+                && (is_identifier_part(chars[i])
+                    || chars[i] == '.'
+                    || ((chars[i] == '+' || chars[i] == '-') && matches!(chars[i - 1], 'e' | 'E')))
+            {
+                i += 1;
+            }
+            tokens.push(Token::Literal);
+        } else if is_identifier_start(c) {
+            let start = i;
+            while i < chars.len() && is_identifier_part(chars[i]) {
+                i += 1;
+            }
+            tokens.push(Token::Identifier(chars[start..i].iter().collect()));
+        } else if c == '/' && regex_allowed(tokens.last()) {
+            i = skip_regex(&chars, i + 1);
+            tokens.push(Token::Literal);
+        } else if let Some(punctuator) = PUNCTUATORS.iter().find(|p| {
+            rest.starts_with(**p)
+                && !(**p == "?." && rest[2..].starts_with(|d: char| d.is_ascii_digit()))
+        }) {
+            if let Some(depth) = templates.last_mut() {
+                match *punctuator {
+                    "{" => *depth += 1,
+                    "}" => *depth -= 1,
+                    _ => (),
+                }
+            }
+            i += punctuator.chars().count();
+            tokens.push(Token::Punctuator(punctuator));
+        } else {
+            //other operators, we only need to know they are not identifiers
+            i += 1;
+            tokens.push(Token::Punctuator("op"));
+        }
+    }
+    tokens
+}
+
+fn regex_allowed(previous: Option<&Token>) -> bool {
+    match previous {
+        None | Some(Token::Punctuator("op")) => true,
+        Some(Token::Punctuator(p)) => !matches!(*p, ")" | "]" | "}"),
+        Some(Token::Identifier(name)) => KEYWORDS_BEFORE_EXPRESSION.contains(&name.as_str()),
+        Some(Token::Literal) => false,
+    }
+}
+
+//returns position after closing quote
+fn skip_quoted(chars: &[char], start: usize, quote: char) -> usize {
+    let mut i = start + 1;
+    while i < chars.len() && chars[i] != quote {
+        i += if chars[i] == '\\' { 2 } else { 1 };
+    }
+    i + 1
+}
+
+//returns position after closing backtick, or after ${ (then template is pushed to stack)
+fn skip_template(chars: &[char], start: usize, templates: &mut Vec<usize>) -> usize {
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            '`' => return i + 1,
+            '$' if chars.get(i + 1) == Some(&'{') => {
+                templates.push(0);
+                return i + 2;
+            }
+            _ => i += 1,
+        }
+    }
+    i
+}
+
+fn skip_regex(chars: &[char], start: usize) -> usize {
+    let mut i = start;
+    let mut in_class = false;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '/' if !in_class => break,
+            _ => (),
+        }
+        i += 1;
+    }
+    i += 1;
+    while i < chars.len() && is_identifier_part(chars[i]) {
+        i += 1;
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyse, is_literal};
+
+    fn free(expression: &str) -> Vec<String> {
+        analyse(expression).unwrap().free.into_iter().collect()
+    }
+
+    #[test]
+    fn test_skips_properties_and_literals() {
+        assert_eq!(
+            free("input.field + 'other' + \"quoted\" + 1.5e-3 + other?.value"),
+            ["input", "other"]
+        );
+        assert_eq!(free("{ key: input, short }"), ["input", "short"]);
+        assert_eq!(free("a ? b : c"), ["a", "b", "c"]);
+        assert_eq!(
+            free("/ab+c/gi.test(input) && input / 2 / divisor"),
+            ["divisor", "input"]
+        );
+        assert_eq!(free("// comment\ninput /* other */"), ["input"]);
+    }
+
+    #[test]
+    fn test_template_literals() {
+        assert_eq!(
+            free("`prefix ${input.field} ${ {a: 1}[key] } ${`${nested}`}` + after"),
+            ["after", "input", "key", "nested"]
+        );
+    }
+
+    #[test]
+    fn test_skips_declarations() {
+        assert_eq!(
+            free(
+                "[input].map(x => {
+                    function add(v1, v2) { return v1 + v2; }
+                    const { a, b: [c] } = x, g = 1;
+                    let d = (e, { f }) => e + f + c + g;
+                    outer: for (const i of [1]) { if (i) continue outer; }
+                    try { return add(a, d(1, 2)) } catch (err) { return err }
+                })[0]"
+            ),
+            ["input"]
+        );
+    }
+
+    #[test]
+    fn test_declarations_are_scoped() {
+        assert_eq!(free("[1].map(input => input).concat(input)"), ["input"]);
+        assert_eq!(
+            free("((x) => { let y = x; return y })(1) + x + y"),
+            ["x", "y"]
+        );
+        assert_eq!(free("(function (a) { return a })(1) + a"), ["a"]);
+    }
+
+    #[test]
+    fn test_typeof_does_not_require_identifier() {
+        let identifiers = analyse("typeof maybe === 'undefined' ? typeof other.field : 1").unwrap();
+        assert_eq!(
+            identifiers.free.into_iter().collect::<Vec<_>>(),
+            ["maybe", "other"]
+        );
+        assert_eq!(
+            identifiers.required.into_iter().collect::<Vec<_>>(),
+            ["other"]
+        );
+    }
+
+    #[test]
+    fn test_literals() {
+        for literal in [
+            "'sum'",
+            "60 * 1000 + -1",
+            "({ risk: 'number', 'quoted': [true, null] })",
+            "`a${'b' + 1}` > 'a' ? [1, [2]] : {}",
+        ] {
+            assert!(is_literal(literal), "{}", literal);
+        }
+        for not_literal in [
+            "input",
+            "Math.PI",
+            "['a'].join()",
+            "''['constructor']['constructor']('return 1')()",
+            "''.constructor.constructor('return Math.random()')()",
+            "(() => { while (true) {} })()",
+            "{ short }",
+            "[...'ab']",
+        ] {
+            assert!(!is_literal(not_literal), "{}", not_literal);
+        }
+    }
+
+    #[test]
+    fn test_unsupported_syntax() {
+        assert_eq!(analyse("({ f() { return input } }).f()"), None);
+        assert_eq!(analyse("({ get value() { return input } }).value"), None);
+        assert_eq!(analyse("new (class A { m() { return 1 } })().m()"), None);
+    }
+}
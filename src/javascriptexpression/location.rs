@@ -0,0 +1,145 @@
+use std::fmt::Display;
+
+use js_sandbox::AnyError;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+///Name under which js-sandbox evaluates the script, expressions defined with eval are given the same name
+///(with sourceURL comment), so that positions in both are reported in the same way (1-based)
+pub(super) const SCRIPT_NAME: &str = "sandboxed.js";
+
+static POSITION_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(&format!(r"{}:(\d+):(\d+)", regex::escape(SCRIPT_NAME))).unwrap());
+
+///Position in the original expression text (not in the generated wrapper), lines and columns start from 1.
+///Columns count characters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub line: usize,
+    pub column: usize,
+    ///Line of the expression, with caret pointing to the column
+    pub excerpt: String,
+}
+
+impl Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {}:\n{}",
+            self.line, self.column, self.excerpt
+        )
+    }
+}
+
+///Describes where the expression is placed in the generated script, to translate positions reported by the runtime
+#[derive(Debug, Clone)]
+pub(super) struct ExpressionLayout {
+    first_line: usize,
+    first_column: usize,
+    expression: String,
+}
+
+impl ExpressionLayout {
+    pub fn new(script_prefix: &str, expression: &str) -> ExpressionLayout {
+        let last_line = script_prefix.rsplit('\n').next().unwrap_or_default();
+        ExpressionLayout {
+            first_line: script_prefix.matches('\n').count() + 1,
+            first_column: last_line.encode_utf16().count() + 1,
+            expression: String::from(expression),
+        }
+    }
+
+    ///Finds the first position in error (i.e. innermost stack frame) which points inside the expression
+    pub fn locate(&self, error: &AnyError) -> Option<SourceLocation> {
+        POSITION_PATTERN
+            .captures_iter(&error.to_string())
+            .filter_map(|position| {
+                let line = position[1].parse().ok()?;
+                let column = position[2].parse().ok()?;
+                self.translate(line, column)
+            })
+            .next()
+    }
+
+    fn translate(&self, script_line: usize, script_column: usize) -> Option<SourceLocation> {
+        let line = script_line.checked_sub(self.first_line)? + 1;
+        let column = if line == 1 {
+            script_column.checked_sub(self.first_column)? + 1
+        } else {
+            script_column
+        };
+        let text = self.expression.lines().nth(line - 1)?;
+        //e.g. unexpected end of input is reported after the last character
+        let column = char_column(text, column).clamp(1, text.chars().count() + 1);
+        let indent: String = text
+            .chars()
+            .take(column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        Some(SourceLocation {
+            line,
+            column,
+            excerpt: format!("{}\n{}^", text, indent),
+        })
+    }
+}
+
+//the runtime counts columns in UTF-16 code units, so characters outside BMP (e.g. emoji) take two of them
+fn char_column(text: &str, utf16_column: usize) -> usize {
+    let mut units = 0;
+    text.chars()
+        .take_while(|c| {
+            units += c.len_utf16();
+            units < utf16_column
+        })
+        .count()
+        + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use js_sandbox::AnyError;
+
+    use super::{ExpressionLayout, SourceLocation};
+
+    #[test]
+    fn test_translates_positions() {
+        let layout = ExpressionLayout::new("function run () {\n  return (", "input +\n  foo.bar)");
+        let locate = |message: &str| layout.locate(&AnyError::msg(String::from(message)));
+
+        assert_eq!(
+            locate("TypeError: cannot read property\n    at run (sandboxed.js:2:17)"),
+            Some(SourceLocation {
+                line: 1,
+                column: 7,
+                excerpt: String::from("input +\n      ^")
+            })
+        );
+        assert_eq!(
+            locate("ReferenceError\n    at helper (helpers.js:1:1)\n    at run (sandboxed.js:3:6)"),
+            Some(SourceLocation {
+                line: 2,
+                column: 6,
+                excerpt: String::from("  foo.bar)\n     ^")
+            })
+        );
+        //the runtime counts each emoji as two columns
+        let emoji_layout =
+            ExpressionLayout::new("function run () {\n  return (", "'😀😀' + foo.bar");
+        assert_eq!(
+            emoji_layout.locate(&AnyError::msg(
+                "ReferenceError\n    at run (sandboxed.js:2:20)"
+            )),
+            Some(SourceLocation {
+                line: 1,
+                column: 8,
+                excerpt: String::from("'😀😀' + foo.bar\n       ^")
+            })
+        );
+
+        //positions in the wrapper
+        assert_eq!(locate("Error\n    at sandboxed.js:1:5"), None);
+        assert_eq!(locate("Error\n    at sandboxed.js:2:3"), None);
+        assert_eq!(locate("Error without position"), None);
+    }
+}
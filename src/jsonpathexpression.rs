@@ -0,0 +1,393 @@
+use serde_json::Value;
+
+use crate::{
+    expression::{CompiledExpression, ParseError, Parser, ResultType},
+    interpreter::data::{CompilationVarContext, ScenarioRuntimeError, VarContext, VarValue},
+    simpleexpression::{ast::Expr, check_variables, SimpleParseError},
+};
+
+mod parser;
+
+///[JSONPath](https://www.rfc-editor.org/rfc/rfc9535) queries, for extracting and reshaping data without JS runtime.
+///The root `$` is the context, so variables are addressed by name: `$.input.items[*].name`. Supported are:
+///- child segments: .name, ['name'], .*, [*], indices [0] and [-1], slices [1:5], [::-1] and unions ['a', 'b', 0]
+///- descendant segments: ..name, ..*, ..[0]
+///- filters: [?(@.amount > 100 && @.tag)] with comparisons, && || ! and existence tests,
+///  filter paths (@.field, $.variable.field) may contain only names and indices
+///
+///If the query can select at most one value (it contains only names and indices) the value is returned (or null,
+///if it does not exist), otherwise the list of selected values.
+pub struct JsonPathParser;
+
+impl Parser for JsonPathParser {
+    fn parse(
+        &self,
+        expression: &str,
+        var_context: &CompilationVarContext,
+    ) -> Result<Box<dyn CompiledExpression>, Box<dyn ParseError>> {
+        let query = parser::parse(expression).map_err(to_parse_error)?;
+        query.check_variables(var_context).map_err(to_parse_error)?;
+        Ok(Box::new(JsonPathExpression(query)))
+    }
+}
+
+fn to_parse_error(error: SimpleParseError) -> Box<dyn ParseError> {
+    Box::new(error)
+}
+
+#[derive(Debug, PartialEq)]
+struct Query {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Segment {
+    ///Selectors are applied to the node and all its descendants
+    descendants: bool,
+    selectors: Vec<Selector>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Selector {
+    Name(String),
+    Wildcard,
+    ///Negative index counts from the end
+    Index(i64),
+    Slice(Option<i64>, Option<i64>, i64),
+    Filter(Expr),
+}
+
+impl Query {
+    fn check_variables(&self, var_context: &CompilationVarContext) -> Result<(), SimpleParseError> {
+        if let Some(Segment {
+            descendants: false,
+            selectors,
+        }) = self.segments.first()
+        {
+            for selector in selectors {
+                if let Selector::Name(name) = selector {
+                    if !var_context.0.contains_key(name) {
+                        return Err(SimpleParseError::new(
+                            &format!("Unknown variable {}", name),
+                            0,
+                        ));
+                    }
+                }
+            }
+        }
+        self.segments
+            .iter()
+            .flat_map(|segment| segment.selectors.iter())
+            .try_for_each(|selector| match selector {
+                Selector::Filter(filter) => check_variables(filter, var_context),
+                _ => Ok(()),
+            })
+    }
+
+    //unknown if query reads all variables (e.g. with wildcard)
+    fn used_variables(&self) -> Option<Vec<String>> {
+        let mut variables = vec![];
+        match self.segments.first() {
+            Some(Segment {
+                descendants: false,
+                selectors,
+            }) => {
+                for selector in selectors {
+                    match selector {
+                        Selector::Name(name) => variables.push(name.as_str()),
+                        _ => return None,
+                    }
+                }
+            }
+            _ => return None,
+        }
+        for selector in self.segments.iter().flat_map(|s| s.selectors.iter()) {
+            if let Selector::Filter(filter) = selector {
+                filter.variables(&mut variables);
+            }
+        }
+        variables.sort_unstable();
+        variables.dedup();
+        Some(variables.into_iter().map(String::from).collect())
+    }
+
+    fn is_singular(&self) -> bool {
+        self.segments.iter().all(|segment| {
+            !segment.descendants
+                && matches!(
+                    segment.selectors.as_slice(),
+                    [Selector::Name(_) | Selector::Index(_)]
+                )
+        })
+    }
+
+    fn select<'a>(&'a self, data: &'a VarContext) -> Vec<&'a Value> {
+        let mut segments = self.segments.iter();
+        let mut nodes = match segments.next() {
+            Some(first) => first.apply_to_root(data),
+            None => return vec![],
+        };
+        for segment in segments {
+            let mut selected = vec![];
+            for node in nodes {
+                segment.apply(node, data, &mut selected);
+            }
+            nodes = selected;
+        }
+        nodes
+    }
+}
+
+impl Segment {
+    //the root is not a real value, its children are variables
+    fn apply_to_root<'a>(&'a self, data: &'a VarContext) -> Vec<&'a Value> {
+        let variables: Vec<&Value> = data.vars().into_iter().map(|(_, value)| value).collect();
+        let mut selected = vec![];
+        for selector in &self.selectors {
+            match selector {
+                Selector::Name(name) => selected.extend(data.get(name)),
+                Selector::Wildcard => selected.extend(variables.iter().copied()),
+                Selector::Filter(filter) => selected.extend(
+                    variables
+                        .iter()
+                        .copied()
+                        .filter(|value| matches_filter(filter, data, value)),
+                ),
+                Selector::Index(_) | Selector::Slice(..) => (),
+            }
+        }
+        if self.descendants {
+            for variable in variables {
+                self.apply(variable, data, &mut selected);
+            }
+        }
+        selected
+    }
+
+    fn apply<'a>(&'a self, node: &'a Value, data: &'a VarContext, selected: &mut Vec<&'a Value>) {
+        for selector in &self.selectors {
+            selector.apply(node, data, selected);
+        }
+        if self.descendants {
+            for child in children(node) {
+                self.apply(child, data, selected);
+            }
+        }
+    }
+}
+
+impl Selector {
+    fn apply<'a>(&'a self, node: &'a Value, data: &'a VarContext, selected: &mut Vec<&'a Value>) {
+        match (self, node) {
+            (Selector::Name(name), Value::Object(fields)) => selected.extend(fields.get(name)),
+            (Selector::Wildcard, _) => selected.extend(children(node)),
+            (Selector::Index(index), Value::Array(elements)) => {
+                let index = normalize(*index, elements.len());
+                if index >= 0 {
+                    selected.extend(elements.get(index as usize));
+                }
+            }
+            (Selector::Slice(start, end, step), Value::Array(elements)) => selected.extend(
+                slice(elements.len(), *start, *end, *step)
+                    .into_iter()
+                    .map(|index| &elements[index]),
+            ),
+            (Selector::Filter(filter), _) => {
+                selected.extend(children(node).filter(|child| matches_filter(filter, data, child)))
+            }
+            _ => (),
+        }
+    }
+}
+
+fn children(node: &Value) -> Box<dyn Iterator<Item = &Value> + '_> {
+    match node {
+        Value::Array(elements) => Box::new(elements.iter()),
+        Value::Object(fields) => Box::new(fields.values()),
+        _ => Box::new(std::iter::empty()),
+    }
+}
+
+//comparisons of incompatible values (e.g. missing field with number) are false, not errors
+fn matches_filter(filter: &Expr, data: &VarContext, value: &Value) -> bool {
+    matches!(filter.evaluate_with(data, Some(value)), Ok(result) if result.as_ref() == &Value::Bool(true))
+}
+
+fn normalize(index: i64, len: usize) -> i64 {
+    if index < 0 {
+        index + len as i64
+    } else {
+        index
+    }
+}
+
+//indices selected by slice, as defined by RFC 9535
+fn slice(len: usize, start: Option<i64>, end: Option<i64>, step: i64) -> Vec<usize> {
+    let length = len as i64;
+    let mut indices = vec![];
+    if step > 0 {
+        let mut index = start.map_or(0, |s| normalize(s, len)).clamp(0, length);
+        let end = end.map_or(length, |e| normalize(e, len)).clamp(0, length);
+        while index < end {
+            indices.push(index as usize);
+            index = match index.checked_add(step) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+    } else if step < 0 {
+        let mut index = start
+            .map_or(length - 1, |s| normalize(s, len))
+            .clamp(-1, length - 1);
+        let end = end.map_or(-1, |e| normalize(e, len)).clamp(-1, length - 1);
+        while index > end {
+            indices.push(index as usize);
+            index = match index.checked_add(step) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+    }
+    indices
+}
+
+struct JsonPathExpression(Query);
+
+impl CompiledExpression for JsonPathExpression {
+    fn execute(&self, data: &VarContext) -> Result<VarValue, ScenarioRuntimeError> {
+        let query = &self.0;
+        if query.segments.is_empty() {
+            return Ok(Value::Object(
+                data.vars()
+                    .into_iter()
+                    .map(|(name, value)| (String::from(name), value.clone()))
+                    .collect(),
+            ));
+        }
+        let selected = query.select(data);
+        Ok(if query.is_singular() {
+            selected
+                .first()
+                .map_or(Value::Null, |value| (*value).clone())
+        } else {
+            Value::Array(selected.into_iter().cloned().collect())
+        })
+    }
+
+    fn used_variables(&self) -> Option<Vec<String>> {
+        self.0.used_variables()
+    }
+
+    fn result_type(&self) -> ResultType {
+        if self.0.segments.is_empty() {
+            ResultType::Map
+        } else if self.0.is_singular() {
+            ResultType::Unknown
+        } else {
+            ResultType::List
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::{
+        expression::{Parser, ResultType},
+        interpreter::data::{CompilationVarContext, VarContext},
+        jsonpathexpression::JsonPathParser,
+        scenariomodel::NodeId,
+    };
+
+    use super::slice;
+
+    fn check(expression: &str, expected: Value) {
+        let input = json!({
+            "id": 7,
+            "items": [
+                {"name": "a", "amount": 50, "tags": ["x"]},
+                {"name": "b", "amount": 150},
+                {"name": "c", "amount": 250, "tags": ["y", "z"]}
+            ]
+        });
+        let data = VarContext::default_context_for_value(input).with_new_var("limit", json!(100));
+        let context = CompilationVarContext::default()
+            .with_var(&NodeId::new("test"), "limit")
+            .unwrap();
+        let expr = JsonPathParser
+            .parse(expression, &context)
+            .unwrap_or_else(|e| panic!("{}: {}", expression, e));
+        assert_eq!(expr.execute(&data).unwrap(), expected, "{}", expression);
+    }
+
+    #[test]
+    fn test_singular_queries() {
+        check("$.input.id", json!(7));
+        check("$['input'].items[-1].name", json!("c"));
+        check("$.input.items[1].tags", json!(null));
+        check("$.limit", json!(100));
+    }
+
+    #[test]
+    fn test_queries() {
+        check("$.input.items[*].name", json!(["a", "b", "c"]));
+        check("$.input.items[::-2].name", json!(["c", "a"]));
+        check("$.input.items[0:2].amount", json!([50, 150]));
+        check(
+            "$.input.items[0, 2]['name', 'amount']",
+            json!(["a", 50, "c", 250]),
+        );
+        check("$.input..tags[0]", json!(["x", "y"]));
+        check("$..name", json!(["a", "b", "c"]));
+        check(
+            "$.input.items[?(@.amount > $.limit)].name",
+            json!(["b", "c"]),
+        );
+        check(
+            "$.input.items[?@.tags && !(@.name == 'a')].name",
+            json!(["c"]),
+        );
+        check(
+            "$.input.items[?(@.tags[1] == 'z' || @.amount < 100)].name",
+            json!(["a", "c"]),
+        );
+        check("$.input.items[?(@.amount > 'text')]", json!([]));
+        check("$.*.items[*].amount", json!([50, 150, 250]));
+    }
+
+    #[test]
+    fn test_slices() {
+        assert_eq!(slice(5, Some(1), Some(3), 1), vec![1, 2]);
+        assert_eq!(slice(5, Some(-2), None, 1), vec![3, 4]);
+        assert_eq!(slice(5, None, None, 2), vec![0, 2, 4]);
+        assert_eq!(slice(5, None, None, -1), vec![4, 3, 2, 1, 0]);
+        assert_eq!(slice(5, Some(10), Some(-10), -2), vec![4, 2, 0]);
+        assert_eq!(slice(5, None, None, 0), Vec::<usize>::new());
+        assert_eq!(slice(5, Some(1), None, i64::MAX), vec![1]);
+        assert_eq!(slice(5, None, None, i64::MIN), vec![4]);
+    }
+
+    #[test]
+    fn test_introspection() {
+        let context = CompilationVarContext::default();
+        let parse = |expression: &str| JsonPathParser.parse(expression, &context).unwrap();
+        assert_eq!(
+            parse("$.input[?(@.a > $.input.limit)]").used_variables(),
+            Some(vec![String::from("input")])
+        );
+        assert_eq!(parse("$..name").used_variables(), None);
+        assert_eq!(parse("$.input.a").result_type(), ResultType::Unknown);
+        assert_eq!(parse("$.input.*").result_type(), ResultType::List);
+    }
+
+    #[test]
+    fn test_unknown_variables() {
+        let context = CompilationVarContext::default();
+        assert!(JsonPathParser.parse("$.unknown.field", &context).is_err());
+        assert!(JsonPathParser
+            .parse("$.input[?(@.a > $.unknown)]", &context)
+            .is_err());
+        assert!(JsonPathParser.parse("$..unknown", &context).is_ok());
+    }
+}
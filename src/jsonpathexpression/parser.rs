@@ -0,0 +1,300 @@
+use serde_json::Value;
+
+use crate::simpleexpression::{
+    ast::{BinaryOp, Expr},
+    parser::{tokenize, Token, TokenStream},
+    SimpleParseError,
+};
+
+use super::{Query, Segment, Selector};
+
+const SYMBOLS: &[&str] = &[
+    "..", "==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "(", ")", "[", "]", ",", ".", "*",
+    ":", "?", "@", "$", "-",
+];
+
+pub(super) fn parse(expression: &str) -> Result<Query, SimpleParseError> {
+    let tokens = tokenize(expression, SYMBOLS)?;
+    let mut tokens = TokenStream::new(&tokens, expression);
+    tokens.expect("$")?;
+    let mut segments = vec![];
+    while tokens.peek().is_some() {
+        segments.push(parse_segment(&mut tokens)?);
+    }
+    Ok(Query { segments })
+}
+
+fn parse_segment(tokens: &mut TokenStream) -> Result<Segment, SimpleParseError> {
+    let descendants = tokens.consume("..");
+    let selectors = if tokens.consume("[") {
+        let mut selectors = vec![parse_selector(tokens)?];
+        while tokens.consume(",") {
+            selectors.push(parse_selector(tokens)?);
+        }
+        tokens.expect("]")?;
+        selectors
+    } else if descendants || tokens.consume(".") {
+        let offset = tokens.offset();
+        match tokens.next() {
+            Some(Token::Symbol("*")) => vec![Selector::Wildcard],
+            Some(Token::Identifier(name)) => vec![Selector::Name(name.clone())],
+            _ => return Err(SimpleParseError::new("Expected name or *", offset)),
+        }
+    } else {
+        return Err(tokens.error("Expected '.', '..' or '['"));
+    };
+    Ok(Segment {
+        descendants,
+        selectors,
+    })
+}
+
+fn parse_selector(tokens: &mut TokenStream) -> Result<Selector, SimpleParseError> {
+    if tokens.consume("*") {
+        return Ok(Selector::Wildcard);
+    }
+    if tokens.consume("?") {
+        //parentheses around filter are optional
+        return Ok(Selector::Filter(parse_or(tokens)?));
+    }
+    if let Some(Token::Str(name)) = tokens.peek() {
+        tokens.next();
+        return Ok(Selector::Name(name.clone()));
+    }
+    let start = parse_integer(tokens)?;
+    if !tokens.consume(":") {
+        return start
+            .map(Selector::Index)
+            .ok_or_else(|| tokens.error("Expected selector"));
+    }
+    let end = parse_integer(tokens)?;
+    let step = if tokens.consume(":") {
+        parse_integer(tokens)?
+    } else {
+        None
+    };
+    Ok(Selector::Slice(start, end, step.unwrap_or(1)))
+}
+
+fn parse_integer(tokens: &mut TokenStream) -> Result<Option<i64>, SimpleParseError> {
+    let offset = tokens.offset();
+    let negative = tokens.consume("-");
+    match tokens.peek() {
+        Some(Token::Number(number)) if number.is_i64() => {
+            tokens.next();
+            let value = number.as_i64().unwrap_or_default();
+            Ok(Some(if negative { -value } else { value }))
+        }
+        _ if negative => Err(SimpleParseError::new("Expected integer", offset)),
+        _ => Ok(None),
+    }
+}
+
+//filters are translated to expressions of simple language, with @ being the current element
+
+fn parse_or(tokens: &mut TokenStream) -> Result<Expr, SimpleParseError> {
+    let mut left = parse_and(tokens)?;
+    while tokens.consume("||") {
+        left = Expr::Binary(BinaryOp::Or, Box::new(left), Box::new(parse_and(tokens)?));
+    }
+    Ok(left)
+}
+
+fn parse_and(tokens: &mut TokenStream) -> Result<Expr, SimpleParseError> {
+    let mut left = parse_comparison(tokens)?;
+    while tokens.consume("&&") {
+        left = Expr::Binary(
+            BinaryOp::And,
+            Box::new(left),
+            Box::new(parse_comparison(tokens)?),
+        );
+    }
+    Ok(left)
+}
+
+const COMPARISONS: &[(&str, BinaryOp)] = &[
+    ("==", BinaryOp::Eq),
+    ("!=", BinaryOp::NotEq),
+    ("<", BinaryOp::Lt),
+    ("<=", BinaryOp::LtEq),
+    (">", BinaryOp::Gt),
+    (">=", BinaryOp::GtEq),
+];
+
+fn parse_comparison(tokens: &mut TokenStream) -> Result<Expr, SimpleParseError> {
+    if tokens.consume("!") {
+        return Ok(Expr::Not(Box::new(parse_comparison(tokens)?)));
+    }
+    if tokens.consume("(") {
+        let result = parse_or(tokens)?;
+        tokens.expect(")")?;
+        return Ok(result);
+    }
+    let (left, is_path) = parse_operand(tokens)?;
+    match COMPARISONS
+        .iter()
+        .find(|(symbol, _)| tokens.consume(symbol))
+    {
+        Some((_, op)) => {
+            let (right, _) = parse_operand(tokens)?;
+            Ok(Expr::Binary(*op, Box::new(left), Box::new(right)))
+        }
+        //path alone tests existence of the value
+        None if is_path => Ok(Expr::Binary(
+            BinaryOp::NotEq,
+            Box::new(left),
+            Box::new(Expr::Literal(Value::Null)),
+        )),
+        None => Err(tokens.error("Expected comparison")),
+    }
+}
+
+//returns operand and whether it's a path
+fn parse_operand(tokens: &mut TokenStream) -> Result<(Expr, bool), SimpleParseError> {
+    let offset = tokens.offset();
+    let literal = |value: Value| Ok((Expr::Literal(value), false));
+    match tokens.next() {
+        Some(Token::Number(value)) => literal(value.clone()),
+        Some(Token::Symbol("-")) => match tokens.next() {
+            Some(Token::Number(value)) => {
+                Ok((Expr::Negate(Box::new(Expr::Literal(value.clone()))), false))
+            }
+            _ => Err(SimpleParseError::new("Expected number", offset)),
+        },
+        Some(Token::Str(value)) => literal(Value::from(value.as_str())),
+        Some(Token::Identifier(name)) if name == "true" => literal(Value::Bool(true)),
+        Some(Token::Identifier(name)) if name == "false" => literal(Value::Bool(false)),
+        Some(Token::Identifier(name)) if name == "null" => literal(Value::Null),
+        Some(Token::Symbol("@")) => Ok((parse_path(tokens, Expr::Current)?, true)),
+        Some(Token::Symbol("$")) => {
+            let variable = Expr::Variable(parse_variable_name(tokens)?);
+            Ok((parse_path(tokens, variable)?, true))
+        }
+        _ => Err(SimpleParseError::new("Expected value", offset)),
+    }
+}
+
+//only singular paths (names and indices) can be used in filters
+fn parse_path(tokens: &mut TokenStream, start: Expr) -> Result<Expr, SimpleParseError> {
+    let mut result = start;
+    loop {
+        if tokens.consume(".") {
+            let offset = tokens.offset();
+            match tokens.next() {
+                Some(Token::Identifier(name)) => {
+                    result = Expr::Field(Box::new(result), name.clone())
+                }
+                _ => return Err(SimpleParseError::new("Expected name", offset)),
+            }
+        } else if tokens.consume("[") {
+            let offset = tokens.offset();
+            result = match tokens.next() {
+                Some(Token::Str(name)) => Expr::Field(Box::new(result), name.clone()),
+                Some(Token::Number(index)) if index.is_u64() => {
+                    Expr::Index(Box::new(result), Box::new(Expr::Literal(index.clone())))
+                }
+                _ => return Err(SimpleParseError::new("Expected name or index", offset)),
+            };
+            tokens.expect("]")?;
+        } else {
+            return Ok(result);
+        }
+    }
+}
+
+//$.name or $['name']
+fn parse_variable_name(tokens: &mut TokenStream) -> Result<String, SimpleParseError> {
+    let bracket = tokens.consume("[");
+    if !bracket {
+        tokens.expect(".")?;
+    }
+    let offset = tokens.offset();
+    let name = match tokens.next() {
+        Some(Token::Identifier(name)) if !bracket => name.clone(),
+        Some(Token::Str(name)) if bracket => name.clone(),
+        _ => return Err(SimpleParseError::new("Expected variable name", offset)),
+    };
+    if bracket {
+        tokens.expect("]")?;
+    }
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::parse;
+    use crate::{
+        jsonpathexpression::{Segment, Selector},
+        simpleexpression::ast::{BinaryOp, Expr},
+    };
+
+    fn child(selectors: Vec<Selector>) -> Segment {
+        Segment {
+            descendants: false,
+            selectors,
+        }
+    }
+
+    fn name(name: &str) -> Selector {
+        Selector::Name(String::from(name))
+    }
+
+    #[test]
+    fn test_segments() {
+        assert_eq!(
+            parse("$.input['items'][*]..name[0, -1, 1:5:2, ::-1]")
+                .unwrap()
+                .segments,
+            vec![
+                child(vec![name("input")]),
+                child(vec![name("items")]),
+                child(vec![Selector::Wildcard]),
+                Segment {
+                    descendants: true,
+                    selectors: vec![name("name")]
+                },
+                child(vec![
+                    Selector::Index(0),
+                    Selector::Index(-1),
+                    Selector::Slice(Some(1), Some(5), 2),
+                    Selector::Slice(None, None, -1)
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filters() {
+        assert_eq!(
+            parse("$.input[?(@.amount > -1 && !@['tag'])]")
+                .unwrap()
+                .segments[1],
+            child(vec![Selector::Filter(Expr::Binary(
+                BinaryOp::And,
+                Box::new(Expr::Binary(
+                    BinaryOp::Gt,
+                    Box::new(Expr::Field(Box::new(Expr::Current), String::from("amount"))),
+                    Box::new(Expr::Negate(Box::new(Expr::Literal(json!(1)))))
+                )),
+                Box::new(Expr::Not(Box::new(Expr::Binary(
+                    BinaryOp::NotEq,
+                    Box::new(Expr::Field(Box::new(Expr::Current), String::from("tag"))),
+                    Box::new(Expr::Literal(json!(null)))
+                ))))
+            ))])
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let error_offset = |expression: &str| parse(expression).unwrap_err().offset;
+        assert_eq!(error_offset("input.field"), Some(0));
+        assert_eq!(error_offset("$.input["), Some(8));
+        assert_eq!(error_offset("$.input[?(@.a.* > 1)]"), Some(14));
+        assert_eq!(error_offset("$.input[?(@['a', 'b'])]"), Some(15));
+        assert_eq!(error_offset("$.input[?(@.a > )]"), Some(16));
+        assert_eq!(error_offset("$input"), Some(1));
+    }
+}
@@ -1,13 +1,18 @@
 pub mod customnodes;
 pub mod expression;
 pub mod interpreter;
-mod javascriptexpression;
+pub mod javascriptexpression;
+pub mod jsonpathexpression;
 pub mod scenariomodel;
+pub mod simpleexpression;
+pub mod spelexpression;
+pub mod templateexpression;
 
 use std::path::Path;
 
 use interpreter::{
     data::{
+        CompilationWarning, ScenarioCompilationError,
         ScenarioCompilationError::ScenarioReadFailure, ScenarioOutput, ScenarioRuntimeError,
         VarContext,
     },
@@ -17,9 +22,41 @@ use interpreter::{
 use crate::interpreter::compiler::Compiler;
 
 pub fn create_interpreter(scenario_path: &Path) -> CompilationResult {
+    create_interpreter_with_compiler(scenario_path, &Compiler::default())
+}
+
+pub fn create_interpreter_with_compiler(
+    scenario_path: &Path,
+    compiler: &Compiler,
+) -> CompilationResult {
+    create_interpreter_with_warnings(scenario_path, compiler).map(|(interpreter, _)| interpreter)
+}
+
+pub fn create_interpreter_with_warnings(
+    scenario_path: &Path,
+    compiler: &Compiler,
+) -> Result<(Box<dyn Interpreter>, Vec<CompilationWarning>), ScenarioCompilationError> {
     let scenario = scenariomodel::parse_file(scenario_path).map_err(ScenarioReadFailure)?;
-    let compiler = Compiler::default();
-    compiler.compile(&scenario)
+    compiler.compile_with_warnings(&scenario)
+}
+
+///Checks the scenario without running it. Errors make the scenario unusable, warnings are only hints for its author.
+pub fn validate_scenario(
+    scenario_path: &Path,
+    compiler: &Compiler,
+) -> Result<Vec<CompilationWarning>, ScenarioCompilationError> {
+    create_interpreter_with_warnings(scenario_path, compiler).map(|(_, warnings)| warnings)
+}
+
+///Same as `validate_scenario`, for the scenario JSON instead of the file.
+pub fn validate_scenario_json(
+    scenario: &str,
+    compiler: &Compiler,
+) -> Result<Vec<CompilationWarning>, ScenarioCompilationError> {
+    let scenario = scenariomodel::parse(scenario).map_err(ScenarioReadFailure)?;
+    compiler
+        .compile_with_warnings(&scenario)
+        .map(|(_, warnings)| warnings)
 }
 
 pub async fn invoke_interpreter(
@@ -1,8 +1,25 @@
-use rocket::State;
-use rusty_nussknacker::{create_interpreter, interpreter::Interpreter, invoke_interpreter};
+use rocket::{fairing::AdHoc, Build, Rocket, State};
+use rusty_nussknacker::{
+    create_interpreter_with_warnings,
+    expression::LanguageParser,
+    interpreter::{
+        compiler::Compiler,
+        data::CompilationWarning,
+        state::{FileStateStore, StateStore},
+        workers::WorkerPool,
+        Interpreter,
+    },
+    invoke_interpreter,
+    javascriptexpression::{Isolation, JavaScriptParser, Limits},
+    validate_scenario, validate_scenario_json,
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::env;
 use std::path::Path;
 use std::process::exit;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[macro_use]
 extern crate rocket;
@@ -15,6 +32,23 @@ async fn invoke(body: &str, interpreter: &State<Box<dyn Interpreter>>) -> String
     }
 }
 
+//checks the scenario in the body without running it, e.g. before deployment
+#[post("/validate", data = "<body>")]
+fn validate_endpoint(body: &str) -> String {
+    thread_local! {
+        //compiler is not Sync, workers and the state store don't matter for validation
+        static COMPILER: Compiler = plain_compiler();
+    }
+    let result = match COMPILER.with(|compiler| validate_scenario_json(body, compiler)) {
+        Ok(warnings) => json!({
+            "valid": true,
+            "warnings": warnings.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        }),
+        Err(err) => json!({ "valid": false, "error": err.to_string() }),
+    };
+    result.to_string()
+}
+
 #[get("/alive")]
 fn alive() -> String {
     String::from("OK")
@@ -25,16 +59,170 @@ fn ready() -> String {
     String::from("OK")
 }
 
-#[launch]
-fn rocket() -> _ {
+//JAVASCRIPT_ISOLATION=shared|single|frozen|fresh, defaults to shared
+fn javascript_isolation() -> Isolation {
+    match env::var("JAVASCRIPT_ISOLATION").as_deref() {
+        Ok("single") => Isolation::SingleRuntime,
+        Ok("frozen") => Isolation::Frozen,
+        Ok("fresh") => Isolation::FreshContext,
+        Ok("shared") | Err(_) => Isolation::Shared,
+        Ok(other) => {
+            eprintln!("Unknown JAVASCRIPT_ISOLATION: {other}");
+            exit(1);
+        }
+    }
+}
+
+fn javascript_limits() -> Limits {
+    fn read_env(name: &str) -> Option<u64> {
+        env::var(name).ok().map(|value| {
+            value.parse().unwrap_or_else(|err| {
+                eprintln!("Invalid {name}: {err}");
+                exit(1);
+            })
+        })
+    }
+    Limits {
+        max_execution_time: read_env("JAVASCRIPT_MAX_EXECUTION_MILLIS").map(Duration::from_millis),
+        max_heap_bytes: read_env("JAVASCRIPT_MAX_HEAP_BYTES").map(|bytes| bytes as usize),
+    }
+}
+
+//CUSTOM_NODES_CONFIG points to JSON file with configuration of custom nodes, keyed by node type
+fn with_custom_nodes_config(compiler: Compiler) -> Compiler {
+    let Ok(file) = env::var("CUSTOM_NODES_CONFIG") else {
+        return compiler;
+    };
+    let config = std::fs::read_to_string(&file)
+        .map_err(|err| err.to_string())
+        .and_then(|json| {
+            serde_json::from_str::<HashMap<String, Value>>(&json).map_err(|err| err.to_string())
+        })
+        .unwrap_or_else(|err| {
+            eprintln!("Invalid CUSTOM_NODES_CONFIG: {err}");
+            exit(1);
+        });
+    config
+        .into_iter()
+        .fold(compiler, |compiler, (node_type, config)| {
+            compiler.with_custom_node_config(&node_type, config)
+        })
+}
+
+//WORKER_THREADS=n runs split branches and for-each elements on n threads, by default they run on the invoking one
+fn with_worker_threads(compiler: Compiler) -> Compiler {
+    let Ok(threads) = env::var("WORKER_THREADS") else {
+        return compiler;
+    };
+    match threads.parse::<usize>() {
+        Ok(threads) if threads > 0 => compiler.with_worker_pool(Arc::new(WorkerPool::new(threads))),
+        _ => {
+            eprintln!("Invalid WORKER_THREADS: {threads}");
+            exit(1);
+        }
+    }
+}
+
+//STATE_FILE keeps state of stateful components (e.g. aggregates) between restarts, it's written on shutdown
+fn state_file() -> Option<Arc<FileStateStore>> {
+    let file = env::var("STATE_FILE").ok()?;
+    match FileStateStore::open(Path::new(&file)) {
+        Ok(store) => Some(Arc::new(store)),
+        Err(err) => {
+            eprintln!("Invalid STATE_FILE: {err}");
+            exit(1);
+        }
+    }
+}
+
+fn plain_compiler() -> Compiler {
+    let javascript = JavaScriptParser::new(javascript_isolation()).with_limits(javascript_limits());
+    with_custom_nodes_config(Compiler::default().with_language_parser(
+        LanguageParser::default().with_parser("javascript", Box::new(javascript)),
+    ))
+}
+
+fn compiler() -> Compiler {
+    with_worker_threads(plain_compiler())
+}
+
+fn print_warnings(warnings: &[CompilationWarning]) {
+    for warning in warnings {
+        eprintln!("Warning: {warning}");
+    }
+}
+
+//`rusty-nussknacker validate [scenario file]` only checks the scenario (SCENARIO_FILE by default), the service is not started
+fn validate(scenario_file: Option<String>) -> i32 {
+    let Some(name) = scenario_file.or_else(|| env::var("SCENARIO_FILE").ok()) else {
+        eprintln!("Usage: rusty-nussknacker validate <scenario file>");
+        return 2;
+    };
+    match validate_scenario(Path::new(&name), &compiler()) {
+        Ok(warnings) => {
+            print_warnings(&warnings);
+            0
+        }
+        Err(err) => {
+            eprintln!("Invalid scenario: {err}");
+            1
+        }
+    }
+}
+
+fn rocket() -> Rocket<Build> {
     let name = env::var("SCENARIO_FILE").unwrap();
     let scenario_file = Path::new(&name);
 
-    let interpreter = create_interpreter(scenario_file).unwrap_or_else(|err| {
-        eprintln!("Failed to parse scenario: {err}");
-        exit(1);
-    });
+    let mut compiler = compiler();
+    let state_store = state_file();
+    if let Some(store) = &state_store {
+        compiler = compiler.with_state_store(store.clone());
+    }
+    let (interpreter, warnings) = create_interpreter_with_warnings(scenario_file, &compiler)
+        .unwrap_or_else(|err| {
+            eprintln!("Failed to parse scenario: {err}");
+            exit(1);
+        });
+    print_warnings(&warnings);
     rocket::build()
         .manage(interpreter)
-        .mount("/", routes![invoke, alive, ready])
+        .attach(AdHoc::try_on_ignite("Open scenario", |rocket| {
+            Box::pin(async move {
+                let opened = match rocket.state::<Box<dyn Interpreter>>() {
+                    Some(interpreter) => interpreter.open().await,
+                    None => Ok(()),
+                };
+                match opened {
+                    Ok(()) => Ok(rocket),
+                    Err(err) => {
+                        eprintln!("Failed to open scenario: {err}");
+                        Err(rocket)
+                    }
+                }
+            })
+        }))
+        .attach(AdHoc::on_shutdown("Close scenario", |rocket| {
+            Box::pin(async move {
+                if let Some(interpreter) = rocket.state::<Box<dyn Interpreter>>() {
+                    interpreter.close().await;
+                }
+                if let Some(Err(err)) = state_store.map(|store| store.flush()) {
+                    eprintln!("Failed to save state: {err}");
+                }
+            })
+        }))
+        .mount("/", routes![invoke, validate_endpoint, alive, ready])
+}
+
+#[rocket::main]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("validate") {
+        exit(validate(args.get(1).cloned()));
+    }
+    if let Err(err) = rocket().launch().await {
+        eprintln!("Failed to launch: {err}");
+        exit(1);
+    }
 }
@@ -48,14 +48,28 @@ pub enum Node {
         var_name: String,
         value: Expression,
     },
+    ///Like mapVariable in Nussknacker - builds a record from separate field expressions
+    RecordVariable {
+        id: NodeId,
+        #[serde(rename = "varName")]
+        var_name: String,
+        fields: Vec<Field>,
+    },
+    ///Output variable can be omitted for nodes which don't produce any (e.g. with side effects only)
     CustomNode {
         id: NodeId,
-        #[serde(rename = "outputVar")]
-        output_var: String,
+        #[serde(rename = "outputVar", default)]
+        output_var: Option<String>,
         #[serde(rename = "nodeType")]
         node_type: String,
         parameters: Vec<Parameter>,
     },
+    ///Removes (e.g. large, temporary) variables, so that they are not passed to further nodes and sinks
+    RemoveVariables {
+        id: NodeId,
+        #[serde(rename = "varNames")]
+        var_names: Vec<String>,
+    },
 }
 
 impl Node {
@@ -70,12 +84,18 @@ impl Node {
                 var_name: _,
                 value: _,
             } => id,
+            Node::RecordVariable {
+                id,
+                var_name: _,
+                fields: _,
+            } => id,
             Node::CustomNode {
                 id,
                 output_var: _,
                 node_type: _,
                 parameters: _,
             } => id,
+            Node::RemoveVariables { id, var_names: _ } => id,
             Node::Source { id } => id,
             Node::Sink { id } => id,
         }
@@ -94,6 +114,12 @@ pub struct Parameter {
     pub expression: Expression,
 }
 
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub expression: Expression,
+}
+
 #[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
 ///Represents one branch of Switch, the expression should evaluate to Boolean value - it's a predicate if branch matches
 pub struct Case {
@@ -116,6 +142,12 @@ impl NodeId {
     }
 }
 
+impl std::fmt::Display for NodeId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Scenario {
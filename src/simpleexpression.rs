@@ -0,0 +1,255 @@
+use std::{error::Error, fmt::Display};
+
+use serde_json::Value;
+
+use crate::{
+    expression::{CompiledExpression, ParseError, Parser, ResultType},
+    interpreter::data::{CompilationVarContext, ScenarioRuntimeError, VarContext, VarValue},
+};
+
+use self::ast::Expr;
+
+pub(crate) mod ast;
+pub(crate) mod parser;
+
+///Small expression language, evaluated directly on JSON values, without JS runtime. It's meant for simple predicates
+///and computations, e.g. `input.amount * 2 > 100 && startsWith(lower(input.name), 'a')`. Supported are:
+///- literals: numbers, strings ('single' or "double" quoted), true, false, null and lists: [1, 'a']
+///- variables and field access: input.field, input['field'], input.list[0] (missing fields evaluate to null)
+///- operators: || && == != < <= > >= + - * / % ! (+ concatenates if one of operands is string)
+///- functions: length, upper, lower, trim, contains, startsWith, endsWith, substring, replace, toString
+pub struct SimpleParser;
+
+impl Parser for SimpleParser {
+    fn parse(
+        &self,
+        expression: &str,
+        var_context: &CompilationVarContext,
+    ) -> Result<Box<dyn CompiledExpression>, Box<dyn ParseError>> {
+        let expr = parser::parse(expression).map_err(to_parse_error)?;
+        check_variables(&expr, var_context).map_err(to_parse_error)?;
+        Ok(Box::new(SimpleExpression::new(expr, var_context)))
+    }
+}
+
+pub(crate) fn check_variables(
+    expr: &Expr,
+    var_context: &CompilationVarContext,
+) -> Result<(), SimpleParseError> {
+    let mut variables = vec![];
+    expr.variables(&mut variables);
+    match variables
+        .iter()
+        .find(|name| !var_context.0.contains_key(**name))
+    {
+        Some(unknown) => Err(SimpleParseError {
+            message: format!("Unknown variable {}", unknown),
+            offset: None,
+        }),
+        None => expr
+            .check_types(var_context)
+            .map_err(|message| SimpleParseError {
+                message,
+                offset: None,
+            }),
+    }
+}
+
+//looks clumsy, but type inference fails without it :/
+fn to_parse_error(error: SimpleParseError) -> Box<dyn ParseError> {
+    Box::new(error)
+}
+
+pub(crate) struct SimpleExpression {
+    expr: Expr,
+    ///Inferred using types of variables known during parsing
+    result_type: ResultType,
+}
+
+impl SimpleExpression {
+    pub(crate) fn new(expr: Expr, var_context: &CompilationVarContext) -> SimpleExpression {
+        let result_type = expr.result_type_in(var_context);
+        SimpleExpression { expr, result_type }
+    }
+}
+
+impl CompiledExpression for SimpleExpression {
+    fn execute(&self, data: &VarContext) -> Result<VarValue, ScenarioRuntimeError> {
+        Ok(self.expr.evaluate(data)?.into_owned())
+    }
+
+    fn used_variables(&self) -> Option<Vec<String>> {
+        let mut variables = vec![];
+        self.expr.variables(&mut variables);
+        Some(variables.into_iter().map(String::from).collect())
+    }
+
+    fn result_type(&self) -> ResultType {
+        self.result_type
+    }
+
+    //all functions are pure
+    fn is_constant(&self) -> bool {
+        matches!(self.used_variables(), Some(variables) if variables.is_empty())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SimpleParseError {
+    pub message: String,
+    ///Position (in characters) in the expression, not known for errors found after parsing (e.g. unknown variables)
+    pub offset: Option<usize>,
+}
+
+impl SimpleParseError {
+    pub(crate) fn new(message: &str, offset: usize) -> SimpleParseError {
+        SimpleParseError {
+            message: String::from(message),
+            offset: Some(offset),
+        }
+    }
+}
+
+impl Display for SimpleParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "{} at position {}", self.message, offset),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl Error for SimpleParseError {}
+
+impl ParseError for SimpleParseError {}
+
+#[derive(Debug, PartialEq)]
+pub enum SimpleExecutionError {
+    NotBoolean(Value),
+    NotNumber(Value),
+    NotComparable(Value, Value),
+    InvalidNumber(f64),
+    InvalidIndex(Value),
+    NotList(Value),
+    WrongArgument { function: String, value: Value },
+}
+
+impl From<SimpleExecutionError> for ScenarioRuntimeError {
+    fn from(error: SimpleExecutionError) -> Self {
+        ScenarioRuntimeError::ExpressionError(Box::new(error))
+    }
+}
+
+impl Display for SimpleExecutionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotBoolean(value) => write!(f, "Expected boolean, got {}", value),
+            Self::NotNumber(value) => write!(f, "Expected number, got {}", value),
+            Self::NotComparable(left, right) => write!(f, "Cannot compare {} and {}", left, right),
+            Self::InvalidNumber(value) => write!(f, "Result {} is not a valid number", value),
+            Self::InvalidIndex(value) => write!(f, "Invalid index {}", value),
+            Self::NotList(value) => write!(f, "Expected list, got {}", value),
+            Self::WrongArgument { function, value } => {
+                write!(f, "Wrong argument {} of function {}", value, function)
+            }
+        }
+    }
+}
+
+impl Error for SimpleExecutionError {}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        expression::{Parser, ResultType},
+        interpreter::data::{CompilationVarContext, VarContext},
+        scenariomodel::NodeId,
+        simpleexpression::SimpleParser,
+    };
+
+    #[test]
+    fn test_expressions() -> Result<(), Box<dyn std::error::Error>> {
+        let input = json!({"amount": 150, "name": " Alice ", "tags": ["a", "b"]});
+        let check = |expression: &str, expected: serde_json::Value| {
+            let expr = SimpleParser
+                .parse(expression, &CompilationVarContext::default())
+                .unwrap();
+            let result = expr
+                .execute(&VarContext::default_context_for_value(input.clone()))
+                .unwrap();
+            assert_eq!(result, expected, "{}", expression);
+        };
+        check("input.amount * 2 > 100", json!(true));
+        check("input.amount / 4", json!(37.5));
+        check("upper(trim(input.name)) + '!'", json!("ALICE!"));
+        check(
+            "contains(input.tags, 'b') && length(input.tags) == 2",
+            json!(true),
+        );
+        check("input.missing == null || input.missing.deeper", json!(true));
+        check(
+            "[input.tags[0], substring('abcdef', 1, 3)]",
+            json!(["a", "bc"]),
+        );
+        check("-(input.amount % 100)", json!(-50));
+        Ok(())
+    }
+
+    #[test]
+    fn test_introspection() {
+        let context = CompilationVarContext::default()
+            .with_var(&NodeId::new("node"), "other")
+            .unwrap();
+        let parse = |expression: &str| SimpleParser.parse(expression, &context).unwrap();
+
+        let expr = parse("input.amount > other.limit || input.force");
+        assert_eq!(
+            expr.used_variables(),
+            Some(vec![String::from("input"), String::from("other")])
+        );
+        assert_eq!(expr.result_type(), ResultType::Boolean);
+        assert!(!expr.is_constant());
+
+        let expr = parse("upper('a') + 1");
+        assert_eq!(expr.result_type(), ResultType::String);
+        assert!(expr.is_constant());
+        assert_eq!(parse("[1, 2]").result_type(), ResultType::List);
+        assert_eq!(parse("input.amount").result_type(), ResultType::Unknown);
+    }
+
+    #[test]
+    fn test_typed_variables() {
+        let context = CompilationVarContext::default()
+            .with_typed_var(&NodeId::new("node"), "count", ResultType::Number)
+            .unwrap();
+        let parse = |expression: &str| {
+            SimpleParser
+                .parse(expression, &context)
+                .map_err(|e| e.to_string())
+        };
+        assert_eq!(
+            parse("count + 1").map(|expr| expr.result_type()),
+            Ok(ResultType::Number)
+        );
+        assert_eq!(
+            parse("count.value").err(),
+            Some(String::from("Field value not available on Number"))
+        );
+        assert!(parse("count[0]").is_err());
+        assert!(parse("input.value[0]").is_ok());
+    }
+
+    #[test]
+    fn test_errors() {
+        let context = CompilationVarContext::default();
+        assert!(SimpleParser.parse("unknown > 5", &context).is_err());
+        assert!(SimpleParser.parse("input >", &context).is_err());
+
+        let expr = SimpleParser.parse("input.name > 5", &context).unwrap();
+        assert!(expr
+            .execute(&VarContext::default_context_for_value(json!({"name": "a"})))
+            .is_err());
+    }
+}
@@ -0,0 +1,639 @@
+use std::{borrow::Cow, cmp::Ordering};
+
+use regex::Regex;
+use serde_json::{Number, Value};
+
+use crate::{
+    expression::ResultType,
+    interpreter::data::{CompilationVarContext, FieldTypes, VarContext},
+};
+
+use super::SimpleExecutionError;
+
+///Expression tree shared by the built-in (non-JS) languages. Evaluation works directly on JSON values,
+///borrowing from the context wherever possible - e.g. `input.field` does not clone the whole input.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Expr {
+    Literal(Value),
+    Variable(String),
+    List(Vec<Expr>),
+    ///Property access, yields null for missing properties and null objects
+    Field(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+    ///Element currently processed by projection or selection (`#this` in SpEL)
+    Current,
+    Map(Vec<(String, Expr)>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    ///Left side, unless it's null or empty string
+    Elvis(Box<Expr>, Box<Expr>),
+    ///Evaluates the second expression for each element of the list
+    Projection(Box<Expr>, Box<Expr>),
+    Selection(Selection, Box<Expr>, Box<Expr>),
+    ///Whole string has to match the pattern
+    Matches(Box<Expr>, Pattern),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Selection {
+    All,
+    First,
+    Last,
+}
+
+///Regex is compiled once, during parsing
+#[derive(Debug, Clone)]
+pub(crate) struct Pattern(Regex);
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Pattern, regex::Error> {
+        Regex::new(&format!("^(?:{})$", pattern)).map(Pattern)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    ///Integers are divided with truncation (like in Java), other numbers as usual
+    TruncatingDiv,
+    Rem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Function {
+    Length,
+    Upper,
+    Lower,
+    Trim,
+    Contains,
+    StartsWith,
+    EndsWith,
+    Substring,
+    Replace,
+    ToString,
+    IsEmpty,
+}
+
+impl Function {
+    pub fn by_name(name: &str) -> Option<Function> {
+        use Function::*;
+        Some(match name {
+            "length" => Length,
+            "upper" => Upper,
+            "lower" => Lower,
+            "trim" => Trim,
+            "contains" => Contains,
+            "startsWith" => StartsWith,
+            "endsWith" => EndsWith,
+            "substring" => Substring,
+            "replace" => Replace,
+            "toString" => ToString,
+            "isEmpty" => IsEmpty,
+            _ => return None,
+        })
+    }
+
+    ///Minimal and maximal number of arguments
+    pub fn arity(&self) -> (usize, usize) {
+        use Function::*;
+        match self {
+            Length | Upper | Lower | Trim | ToString | IsEmpty => (1, 1),
+            Contains | StartsWith | EndsWith => (2, 2),
+            Substring => (2, 3),
+            Replace => (3, 3),
+        }
+    }
+
+    fn result_type(&self) -> ResultType {
+        use Function::*;
+        match self {
+            Length => ResultType::Number,
+            Upper | Lower | Trim | Substring | Replace | ToString => ResultType::String,
+            Contains | StartsWith | EndsWith | IsEmpty => ResultType::Boolean,
+        }
+    }
+
+    fn apply(&self, args: &[Cow<Value>]) -> Result<Value, SimpleExecutionError> {
+        use Function::*;
+        let string = |index: usize| -> Result<&str, SimpleExecutionError> {
+            args[index]
+                .as_str()
+                .ok_or_else(|| self.wrong_argument(&args[index]))
+        };
+        let index = |index: usize| -> Result<usize, SimpleExecutionError> {
+            args[index]
+                .as_u64()
+                .map(|i| i as usize)
+                .ok_or_else(|| self.wrong_argument(&args[index]))
+        };
+        Ok(match self {
+            Length => match args[0].as_ref() {
+                Value::String(value) => Value::from(value.chars().count()),
+                Value::Array(values) => Value::from(values.len()),
+                Value::Object(values) => Value::from(values.len()),
+                other => return Err(self.wrong_argument(other)),
+            },
+            Upper => Value::from(string(0)?.to_uppercase()),
+            Lower => Value::from(string(0)?.to_lowercase()),
+            Trim => Value::from(string(0)?.trim()),
+            Contains => match args[0].as_ref() {
+                Value::String(value) => Value::Bool(value.contains(string(1)?)),
+                Value::Array(values) => {
+                    Value::Bool(values.iter().any(|v| equal(v, args[1].as_ref())))
+                }
+                other => return Err(self.wrong_argument(other)),
+            },
+            StartsWith => Value::Bool(string(0)?.starts_with(string(1)?)),
+            EndsWith => Value::Bool(string(0)?.ends_with(string(1)?)),
+            Substring => {
+                let chars: Vec<char> = string(0)?.chars().collect();
+                let end = if args.len() > 2 {
+                    index(2)?
+                } else {
+                    chars.len()
+                };
+                let start = index(1)?.min(end);
+                Value::from(
+                    chars[start.min(chars.len())..end.min(chars.len())]
+                        .iter()
+                        .collect::<String>(),
+                )
+            }
+            Replace => Value::from(string(0)?.replace(string(1)?, string(2)?)),
+            ToString => Value::from(to_string(args[0].as_ref())),
+            IsEmpty => Value::Bool(match args[0].as_ref() {
+                Value::Null => true,
+                Value::String(value) => value.is_empty(),
+                Value::Array(values) => values.is_empty(),
+                Value::Object(values) => values.is_empty(),
+                other => return Err(self.wrong_argument(other)),
+            }),
+        })
+    }
+
+    fn wrong_argument(&self, value: &Value) -> SimpleExecutionError {
+        SimpleExecutionError::WrongArgument {
+            function: format!("{:?}", self),
+            value: value.clone(),
+        }
+    }
+}
+
+impl Expr {
+    pub fn evaluate<'a>(
+        &'a self,
+        data: &'a VarContext,
+    ) -> Result<Cow<'a, Value>, SimpleExecutionError> {
+        self.evaluate_with(data, None)
+    }
+
+    ///current is the element processed by enclosing projection/selection (or JSONPath filter)
+    pub fn evaluate_with<'a>(
+        &'a self,
+        data: &'a VarContext,
+        current: Option<&'a Value>,
+    ) -> Result<Cow<'a, Value>, SimpleExecutionError> {
+        let eval = |expr: &'a Expr| expr.evaluate_with(data, current);
+        Ok(match self {
+            Expr::Literal(value) => Cow::Borrowed(value),
+            Expr::Variable(name) => data
+                .get(name)
+                .map_or(Cow::Owned(Value::Null), Cow::Borrowed),
+            Expr::Current => current.map_or(Cow::Owned(Value::Null), Cow::Borrowed),
+            Expr::List(elements) => Cow::Owned(Value::Array(
+                elements
+                    .iter()
+                    .map(|e| eval(e).map(Cow::into_owned))
+                    .collect::<Result<Vec<Value>, SimpleExecutionError>>()?,
+            )),
+            Expr::Map(entries) => Cow::Owned(Value::Object(
+                entries
+                    .iter()
+                    .map(|(key, e)| Ok((key.clone(), eval(e)?.into_owned())))
+                    .collect::<Result<_, SimpleExecutionError>>()?,
+            )),
+            Expr::Field(object, name) => select(eval(object)?, |value| value.get(name.as_str())),
+            Expr::Index(object, index) => {
+                let index = eval(index)?;
+                let object = eval(object)?;
+                match index.as_ref() {
+                    Value::String(key) => select(object, |value| value.get(key.as_str())),
+                    Value::Number(number) if number.as_u64().is_some() => {
+                        let position = number.as_u64().unwrap_or_default() as usize;
+                        select(object, |value| value.get(position))
+                    }
+                    other => return Err(SimpleExecutionError::InvalidIndex(other.clone())),
+                }
+            }
+            Expr::Not(operand) => Cow::Owned(Value::Bool(!as_bool(eval(operand)?.as_ref())?)),
+            Expr::Negate(operand) => Cow::Owned(negate(eval(operand)?.as_ref())?),
+            //logical operators are short-circuited
+            Expr::Binary(BinaryOp::And, left, right) => Cow::Owned(Value::Bool(
+                as_bool(eval(left)?.as_ref())? && as_bool(eval(right)?.as_ref())?,
+            )),
+            Expr::Binary(BinaryOp::Or, left, right) => Cow::Owned(Value::Bool(
+                as_bool(eval(left)?.as_ref())? || as_bool(eval(right)?.as_ref())?,
+            )),
+            Expr::Binary(op, left, right) => {
+                Cow::Owned(binary(*op, eval(left)?.as_ref(), eval(right)?.as_ref())?)
+            }
+            Expr::Call(function, args) => {
+                let args = args
+                    .iter()
+                    .map(eval)
+                    .collect::<Result<Vec<Cow<Value>>, SimpleExecutionError>>()?;
+                Cow::Owned(function.apply(&args)?)
+            }
+            Expr::Conditional(condition, when_true, when_false) => {
+                if as_bool(eval(condition)?.as_ref())? {
+                    eval(when_true)?
+                } else {
+                    eval(when_false)?
+                }
+            }
+            Expr::Elvis(value, default) => match eval(value)? {
+                value if value.is_null() || value.as_str() == Some("") => eval(default)?,
+                value => value,
+            },
+            Expr::Projection(list, projection) => {
+                let list = eval(list)?;
+                Cow::Owned(Value::Array(
+                    as_list(&list)?
+                        .iter()
+                        .map(|e| projection.evaluate_with(data, Some(e)).map(Cow::into_owned))
+                        .collect::<Result<Vec<Value>, SimpleExecutionError>>()?,
+                ))
+            }
+            Expr::Selection(selection, list, predicate) => {
+                let list = eval(list)?;
+                let matching = |e: &&Value| -> Result<bool, SimpleExecutionError> {
+                    as_bool(predicate.evaluate_with(data, Some(e))?.as_ref())
+                };
+                let elements = as_list(&list)?;
+                Cow::Owned(match selection {
+                    Selection::All => {
+                        let mut selected = vec![];
+                        for element in elements {
+                            if matching(&element)? {
+                                selected.push(element.clone());
+                            }
+                        }
+                        Value::Array(selected)
+                    }
+                    Selection::First => first_matching(elements.iter(), matching)?,
+                    Selection::Last => first_matching(elements.iter().rev(), matching)?,
+                })
+            }
+            Expr::Matches(value, pattern) => match eval(value)?.as_ref() {
+                Value::String(value) => Cow::Owned(Value::Bool(pattern.0.is_match(value))),
+                other => {
+                    return Err(SimpleExecutionError::WrongArgument {
+                        function: String::from("matches"),
+                        value: other.clone(),
+                    })
+                }
+            },
+        })
+    }
+
+    ///Result type, given types of variables
+    pub fn result_type_in(&self, variables: &CompilationVarContext) -> ResultType {
+        use BinaryOp::*;
+        let type_of = |expr: &Expr| expr.result_type_in(variables);
+        match self {
+            Expr::Literal(value) => ResultType::of(value),
+            Expr::Variable(name) => variables
+                .0
+                .get(name)
+                .copied()
+                .unwrap_or(ResultType::Unknown),
+            Expr::Field(object, name) => match object.record_fields(variables) {
+                Some(fields) => fields.get(name).copied().unwrap_or(ResultType::Unknown),
+                None => ResultType::Unknown,
+            },
+            Expr::List(_) | Expr::Projection(..) | Expr::Selection(Selection::All, ..) => {
+                ResultType::List
+            }
+            Expr::Map(_) => ResultType::Map,
+            Expr::Not(_) | Expr::Matches(..) => ResultType::Boolean,
+            Expr::Negate(_) => ResultType::Number,
+            Expr::Binary(Or | And | Eq | NotEq | Lt | LtEq | Gt | GtEq, ..) => ResultType::Boolean,
+            Expr::Binary(Add, left, right) => match (type_of(left), type_of(right)) {
+                (ResultType::String, _) | (_, ResultType::String) => ResultType::String,
+                (ResultType::Number, ResultType::Number) => ResultType::Number,
+                _ => ResultType::Unknown,
+            },
+            Expr::Binary(..) => ResultType::Number,
+            Expr::Call(function, _) => function.result_type(),
+            Expr::Conditional(_, left, right) | Expr::Elvis(left, right) => {
+                match (type_of(left), type_of(right)) {
+                    (left, right) if left == right => left,
+                    _ => ResultType::Unknown,
+                }
+            }
+            Expr::Current | Expr::Index(..) | Expr::Selection(..) => ResultType::Unknown,
+        }
+    }
+
+    ///Checks that fields and elements are not taken from values which cannot have them, e.g. numbers
+    pub fn check_types(&self, variables: &CompilationVarContext) -> Result<(), String> {
+        let scalar = |expr: &Expr| match expr.result_type_in(variables) {
+            result @ (ResultType::Boolean | ResultType::Number | ResultType::String) => {
+                Some(result)
+            }
+            _ => None,
+        };
+        match self {
+            Expr::Field(object, name) => {
+                if let Some(result) = scalar(object) {
+                    return Err(format!("Field {} not available on {:?}", name, result));
+                }
+                if let Some(fields) = object.record_fields(variables) {
+                    if !fields.contains_key(name) {
+                        return Err(format!("Unknown field {} of record", name));
+                    }
+                }
+            }
+            Expr::Index(object, _) => match scalar(object) {
+                Some(ResultType::String) | None => (),
+                Some(result) => return Err(format!("Cannot index {:?}", result)),
+            },
+            Expr::Projection(list, _) | Expr::Selection(_, list, _) => {
+                if let Some(result) = scalar(list) {
+                    return Err(format!("{:?} is not a list", result));
+                }
+            }
+            _ => (),
+        }
+        self.children()
+            .into_iter()
+            .try_for_each(|child| child.check_types(variables))
+    }
+
+    //only variables are known to be records, fields of records are not typed further
+    fn record_fields<'a>(&self, variables: &'a CompilationVarContext) -> Option<&'a FieldTypes> {
+        match self {
+            Expr::Variable(name) => variables.record_fields(name),
+            _ => None,
+        }
+    }
+
+    fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Literal(_) | Expr::Current | Expr::Variable(_) => vec![],
+            Expr::List(elements) | Expr::Call(_, elements) => elements.iter().collect(),
+            Expr::Map(entries) => entries.iter().map(|(_, e)| e).collect(),
+            Expr::Field(object, _)
+            | Expr::Not(object)
+            | Expr::Negate(object)
+            | Expr::Matches(object, _) => vec![object],
+            Expr::Index(left, right)
+            | Expr::Binary(_, left, right)
+            | Expr::Elvis(left, right)
+            | Expr::Projection(left, right)
+            | Expr::Selection(_, left, right) => vec![left, right],
+            Expr::Conditional(condition, left, right) => vec![condition, left, right],
+        }
+    }
+
+    ///Names of variables read by the expression
+    pub fn variables<'a>(&'a self, result: &mut Vec<&'a str>) {
+        match self {
+            Expr::Literal(_) | Expr::Current => (),
+            Expr::Variable(name) => {
+                if !result.contains(&name.as_str()) {
+                    result.push(name)
+                }
+            }
+            Expr::List(elements) | Expr::Call(_, elements) => {
+                elements.iter().for_each(|e| e.variables(result))
+            }
+            Expr::Map(entries) => entries.iter().for_each(|(_, e)| e.variables(result)),
+            Expr::Field(object, _)
+            | Expr::Not(object)
+            | Expr::Negate(object)
+            | Expr::Matches(object, _) => object.variables(result),
+            Expr::Index(left, right)
+            | Expr::Binary(_, left, right)
+            | Expr::Elvis(left, right)
+            | Expr::Projection(left, right)
+            | Expr::Selection(_, left, right) => {
+                left.variables(result);
+                right.variables(result);
+            }
+            Expr::Conditional(condition, left, right) => {
+                condition.variables(result);
+                left.variables(result);
+                right.variables(result);
+            }
+        }
+    }
+}
+
+//takes part of value without cloning the rest of it
+fn select<'a>(
+    value: Cow<'a, Value>,
+    selector: impl Fn(&Value) -> Option<&Value>,
+) -> Cow<'a, Value> {
+    match value {
+        Cow::Borrowed(value) => selector(value).map_or(Cow::Owned(Value::Null), Cow::Borrowed),
+        Cow::Owned(value) => Cow::Owned(selector(&value).cloned().unwrap_or(Value::Null)),
+    }
+}
+
+fn as_list(value: &Value) -> Result<&Vec<Value>, SimpleExecutionError> {
+    value
+        .as_array()
+        .ok_or_else(|| SimpleExecutionError::NotList(value.clone()))
+}
+
+fn first_matching<'a>(
+    elements: impl Iterator<Item = &'a Value>,
+    matching: impl Fn(&&'a Value) -> Result<bool, SimpleExecutionError>,
+) -> Result<Value, SimpleExecutionError> {
+    for element in elements {
+        if matching(&element)? {
+            return Ok(element.clone());
+        }
+    }
+    Ok(Value::Null)
+}
+
+pub(crate) fn as_bool(value: &Value) -> Result<bool, SimpleExecutionError> {
+    value
+        .as_bool()
+        .ok_or_else(|| SimpleExecutionError::NotBoolean(value.clone()))
+}
+
+fn negate(value: &Value) -> Result<Value, SimpleExecutionError> {
+    match value {
+        Value::Number(number) => Ok(match number.as_i64().and_then(i64::checked_neg) {
+            Some(negated) => Value::from(negated),
+            None => float(-number.as_f64().unwrap_or_default())?,
+        }),
+        other => Err(SimpleExecutionError::NotNumber(other.clone())),
+    }
+}
+
+///Numbers are compared by value (1 == 1.0), other values structurally
+pub(crate) fn equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => l.as_f64() == r.as_f64(),
+        (l, r) => l == r,
+    }
+}
+
+pub(crate) fn compare(left: &Value, right: &Value) -> Result<Ordering, SimpleExecutionError> {
+    let incomparable = || SimpleExecutionError::NotComparable(left.clone(), right.clone());
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => {
+            l.as_f64().partial_cmp(&r.as_f64()).ok_or_else(incomparable)
+        }
+        (Value::String(l), Value::String(r)) => Ok(l.cmp(r)),
+        (Value::Bool(l), Value::Bool(r)) => Ok(l.cmp(r)),
+        _ => Err(incomparable()),
+    }
+}
+
+pub(crate) fn to_string(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn float(value: f64) -> Result<Value, SimpleExecutionError> {
+    Number::from_f64(value)
+        .map(Value::Number)
+        .ok_or(SimpleExecutionError::InvalidNumber(value))
+}
+
+fn binary(op: BinaryOp, left: &Value, right: &Value) -> Result<Value, SimpleExecutionError> {
+    use BinaryOp::*;
+    Ok(match op {
+        Eq => Value::Bool(equal(left, right)),
+        NotEq => Value::Bool(!equal(left, right)),
+        Lt => Value::Bool(compare(left, right)? == Ordering::Less),
+        LtEq => Value::Bool(compare(left, right)? != Ordering::Greater),
+        Gt => Value::Bool(compare(left, right)? == Ordering::Greater),
+        GtEq => Value::Bool(compare(left, right)? != Ordering::Less),
+        //strings are concatenated, like in JS
+        Add if left.is_string() || right.is_string() => {
+            Value::from(to_string(left) + &to_string(right))
+        }
+        Add | Sub | Mul | Div | TruncatingDiv | Rem => arithmetic(op, left, right)?,
+        And | Or => Value::Bool(as_bool(left)? && as_bool(right)?),
+    })
+}
+
+fn arithmetic(op: BinaryOp, left: &Value, right: &Value) -> Result<Value, SimpleExecutionError> {
+    use BinaryOp::*;
+    let (l, r) = match (left, right) {
+        (Value::Number(l), Value::Number(r)) => (l, r),
+        (Value::Number(_), other) | (other, _) => {
+            return Err(SimpleExecutionError::NotNumber(other.clone()))
+        }
+    };
+    //we stay with integers as long as it's possible, so that 2 * 3 is 6 and not 6.0
+    if let (Some(l), Some(r)) = (l.as_i64(), r.as_i64()) {
+        let result = match op {
+            Add => l.checked_add(r),
+            Sub => l.checked_sub(r),
+            Mul => l.checked_mul(r),
+            Div if l.checked_rem(r) == Some(0) => l.checked_div(r),
+            TruncatingDiv if r != 0 => l.checked_div(r),
+            Rem if r != 0 => l.checked_rem(r),
+            _ => None,
+        };
+        if let Some(result) = result {
+            return Ok(Value::from(result));
+        }
+    }
+    let (l, r) = (
+        l.as_f64().unwrap_or_default(),
+        r.as_f64().unwrap_or_default(),
+    );
+    float(match op {
+        Add => l + r,
+        Sub => l - r,
+        Mul => l * r,
+        Div | TruncatingDiv => l / r,
+        _ => l % r,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::interpreter::data::VarContext;
+
+    use super::{BinaryOp, Expr, Function};
+
+    fn literal(value: Value) -> Box<Expr> {
+        Box::new(Expr::Literal(value))
+    }
+
+    fn evaluate(expr: Expr) -> Value {
+        let data = VarContext::default_context_for_value(json!({"a": [1, {"b": "text"}]}));
+        expr.evaluate(&data).unwrap().into_owned()
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let binary = |op, l: Value, r: Value| evaluate(Expr::Binary(op, literal(l), literal(r)));
+        assert_eq!(binary(BinaryOp::Add, json!(2), json!(3)), json!(5));
+        assert_eq!(binary(BinaryOp::Div, json!(6), json!(3)), json!(2));
+        assert_eq!(binary(BinaryOp::Div, json!(3), json!(2)), json!(1.5));
+        assert_eq!(
+            binary(BinaryOp::Mul, json!(i64::MAX), json!(2)),
+            json!(i64::MAX as f64 * 2.0)
+        );
+        assert_eq!(
+            binary(BinaryOp::Div, json!(i64::MIN), json!(-1)),
+            json!(-(i64::MIN as f64))
+        );
+        assert_eq!(binary(BinaryOp::Add, json!("a"), json!(1)), json!("a1"));
+        assert_eq!(binary(BinaryOp::Eq, json!(1), json!(1.0)), json!(true));
+        assert_eq!(binary(BinaryOp::Lt, json!("a"), json!("b")), json!(true));
+    }
+
+    #[test]
+    fn test_access() {
+        let input = Box::new(Expr::Variable(String::from("input")));
+        let element = Expr::Index(
+            Box::new(Expr::Field(input, String::from("a"))),
+            literal(json!(1)),
+        );
+        assert_eq!(evaluate(element.clone()), json!({"b": "text"}));
+        let field = Expr::Field(Box::new(element), String::from("b"));
+        assert_eq!(
+            evaluate(Expr::Call(Function::Upper, vec![field.clone()])),
+            json!("TEXT")
+        );
+        assert_eq!(
+            evaluate(Expr::Field(Box::new(field), String::from("missing"))),
+            Value::Null
+        );
+    }
+}
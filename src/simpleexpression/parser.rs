@@ -0,0 +1,381 @@
+use serde_json::Value;
+
+use super::{
+    ast::{BinaryOp, Expr, Function},
+    SimpleParseError,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token {
+    Number(Value),
+    Str(String),
+    Identifier(String),
+    Symbol(&'static str),
+}
+
+///Tokens with their position (in characters) in the expression
+pub(crate) type Tokens = Vec<(Token, usize)>;
+
+pub(crate) const SYMBOLS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "+", "-", "*", "/", "%", "!", "(", ")", "[", "]",
+    ",", ".",
+];
+
+///Splits expression into tokens, symbols should be sorted from the longest
+pub(crate) fn tokenize(
+    expression: &str,
+    symbols: &[&'static str],
+) -> Result<Tokens, SimpleParseError> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let token = if c == '\'' || c == '"' {
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(SimpleParseError::new("Unterminated string", start)),
+                    //quote is escaped by doubling it, like in SpEL/SQL
+                    Some(q) if *q == c && chars.get(i + 1) == Some(&c) => {
+                        value.push(c);
+                        i += 2;
+                    }
+                    Some(q) if *q == c => break,
+                    Some(other) => {
+                        value.push(*other);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+            Token::Str(value)
+        } else if c.is_ascii_digit() {
+            while i < chars.len()
+                && (chars[i].is_ascii_digit()
+                    || (chars[i] == '.'
+                        && matches!(chars.get(i + 1), Some(d) if d.is_ascii_digit())))
+            {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number = serde_json::from_str::<Value>(&text)
+                .map_err(|_| SimpleParseError::new("Invalid number", start))?;
+            Token::Number(number)
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            Token::Identifier(chars[start..i].iter().collect())
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 3)].iter().collect();
+            let symbol = symbols
+                .iter()
+                .find(|s| rest.starts_with(**s))
+                .ok_or_else(|| {
+                    SimpleParseError::new(&format!("Unexpected character '{}'", c), start)
+                })?;
+            i += symbol.chars().count();
+            Token::Symbol(symbol)
+        };
+        tokens.push((token, start));
+    }
+    Ok(tokens)
+}
+
+pub(crate) fn parse(expression: &str) -> Result<Expr, SimpleParseError> {
+    let tokens = tokenize(expression, SYMBOLS)?;
+    let mut parser = TokenStream::new(&tokens, expression);
+    let result = parse_expression(&mut parser)?;
+    parser.expect_end()?;
+    Ok(result)
+}
+
+///Parses expression at the beginning of the stream, stops at first token which cannot continue it
+pub(crate) fn parse_expression(tokens: &mut TokenStream) -> Result<Expr, SimpleParseError> {
+    parse_binary(tokens, 0)
+}
+
+///Cursor over tokens, shared by parsers of built-in languages
+pub(crate) struct TokenStream<'a> {
+    tokens: &'a [(Token, usize)],
+    position: usize,
+    end: usize,
+}
+
+impl<'a> TokenStream<'a> {
+    pub fn new(tokens: &'a [(Token, usize)], expression: &str) -> TokenStream<'a> {
+        TokenStream {
+            tokens,
+            position: 0,
+            end: expression.chars().count(),
+        }
+    }
+
+    pub fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position).map(|t| &t.0)
+    }
+
+    ///Token after the current one, at given distance
+    pub fn peek_at(&self, distance: usize) -> Option<&'a Token> {
+        self.tokens.get(self.position + distance).map(|t| &t.0)
+    }
+
+    pub fn next(&mut self) -> Option<&'a Token> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    ///Position of the current token, for error reporting
+    pub fn offset(&self) -> usize {
+        self.tokens.get(self.position).map_or(self.end, |t| t.1)
+    }
+
+    pub fn consume(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn expect(&mut self, symbol: &str) -> Result<(), SimpleParseError> {
+        if self.consume(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", symbol)))
+        }
+    }
+
+    pub fn expect_end(&self) -> Result<(), SimpleParseError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.error("Unexpected token")),
+        }
+    }
+
+    pub fn error(&self, message: &str) -> SimpleParseError {
+        SimpleParseError::new(message, self.offset())
+    }
+
+    ///Comma separated list of elements, until closing symbol (which is consumed)
+    pub fn list(
+        &mut self,
+        closing: &str,
+        mut element: impl FnMut(&mut TokenStream<'a>) -> Result<Expr, SimpleParseError>,
+    ) -> Result<Vec<Expr>, SimpleParseError> {
+        let mut elements = vec![];
+        if self.consume(closing) {
+            return Ok(elements);
+        }
+        loop {
+            elements.push(element(self)?);
+            if self.consume(closing) {
+                return Ok(elements);
+            }
+            self.expect(",")?;
+        }
+    }
+}
+
+//operators with their precedence, the lowest first
+const BINARY_OPERATORS: &[(&str, BinaryOp, u8)] = &[
+    ("||", BinaryOp::Or, 1),
+    ("&&", BinaryOp::And, 2),
+    ("==", BinaryOp::Eq, 3),
+    ("!=", BinaryOp::NotEq, 3),
+    ("<", BinaryOp::Lt, 4),
+    ("<=", BinaryOp::LtEq, 4),
+    (">", BinaryOp::Gt, 4),
+    (">=", BinaryOp::GtEq, 4),
+    ("+", BinaryOp::Add, 5),
+    ("-", BinaryOp::Sub, 5),
+    ("*", BinaryOp::Mul, 6),
+    ("/", BinaryOp::Div, 6),
+    ("%", BinaryOp::Rem, 6),
+];
+
+fn binary_operator(token: Option<&Token>) -> Option<(BinaryOp, u8)> {
+    match token {
+        Some(Token::Symbol(symbol)) => BINARY_OPERATORS
+            .iter()
+            .find(|(s, _, _)| s == symbol)
+            .map(|(_, op, precedence)| (*op, *precedence)),
+        _ => None,
+    }
+}
+
+//precedence climbing, all operators are left-associative
+fn parse_binary(tokens: &mut TokenStream, min_precedence: u8) -> Result<Expr, SimpleParseError> {
+    let mut left = parse_unary(tokens)?;
+    while let Some((op, precedence)) = binary_operator(tokens.peek()) {
+        if precedence <= min_precedence {
+            break;
+        }
+        tokens.next();
+        let right = parse_binary(tokens, precedence)?;
+        left = Expr::Binary(op, Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_unary(tokens: &mut TokenStream) -> Result<Expr, SimpleParseError> {
+    if tokens.consume("!") {
+        Ok(Expr::Not(Box::new(parse_unary(tokens)?)))
+    } else if tokens.consume("-") {
+        Ok(Expr::Negate(Box::new(parse_unary(tokens)?)))
+    } else {
+        parse_postfix(tokens)
+    }
+}
+
+fn parse_postfix(tokens: &mut TokenStream) -> Result<Expr, SimpleParseError> {
+    let mut result = parse_primary(tokens)?;
+    loop {
+        if tokens.consume(".") {
+            match tokens.next() {
+                Some(Token::Identifier(name)) => {
+                    result = Expr::Field(Box::new(result), name.clone())
+                }
+                _ => return Err(tokens.error("Expected field name")),
+            }
+        } else if tokens.consume("[") {
+            let index = parse_binary(tokens, 0)?;
+            tokens.expect("]")?;
+            result = Expr::Index(Box::new(result), Box::new(index));
+        } else {
+            return Ok(result);
+        }
+    }
+}
+
+fn parse_primary(tokens: &mut TokenStream) -> Result<Expr, SimpleParseError> {
+    let offset = tokens.offset();
+    match tokens.next() {
+        Some(Token::Number(value)) => Ok(Expr::Literal(value.clone())),
+        Some(Token::Str(value)) => Ok(Expr::Literal(Value::from(value.as_str()))),
+        Some(Token::Identifier(name)) => match name.as_str() {
+            "true" => Ok(Expr::Literal(Value::Bool(true))),
+            "false" => Ok(Expr::Literal(Value::Bool(false))),
+            "null" => Ok(Expr::Literal(Value::Null)),
+            _ if tokens.consume("(") => {
+                let function = Function::by_name(name).ok_or_else(|| {
+                    SimpleParseError::new(&format!("Unknown function {}", name), offset)
+                })?;
+                let args = tokens.list(")", |t| parse_binary(t, 0))?;
+                check_arity(function, name, args.len(), offset)?;
+                Ok(Expr::Call(function, args))
+            }
+            _ => Ok(Expr::Variable(name.clone())),
+        },
+        Some(Token::Symbol("(")) => {
+            let result = parse_binary(tokens, 0)?;
+            tokens.expect(")")?;
+            Ok(result)
+        }
+        Some(Token::Symbol("[")) => Ok(Expr::List(tokens.list("]", |t| parse_binary(t, 0))?)),
+        _ => Err(SimpleParseError::new("Expected value", offset)),
+    }
+}
+
+pub(crate) fn check_arity(
+    function: Function,
+    name: &str,
+    count: usize,
+    offset: usize,
+) -> Result<(), SimpleParseError> {
+    let (min, max) = function.arity();
+    if count < min || count > max {
+        return Err(SimpleParseError::new(
+            &format!(
+                "Function {} expects {}..{} arguments, got {}",
+                name, min, max, count
+            ),
+            offset,
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::parse;
+    use crate::simpleexpression::ast::{BinaryOp, Expr, Function};
+
+    fn var(name: &str) -> Box<Expr> {
+        Box::new(Expr::Variable(String::from(name)))
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(
+            parse("a + b * 2 > 3 && !c").unwrap(),
+            Expr::Binary(
+                BinaryOp::And,
+                Box::new(Expr::Binary(
+                    BinaryOp::Gt,
+                    Box::new(Expr::Binary(
+                        BinaryOp::Add,
+                        var("a"),
+                        Box::new(Expr::Binary(
+                            BinaryOp::Mul,
+                            var("b"),
+                            Box::new(Expr::Literal(json!(2)))
+                        ))
+                    )),
+                    Box::new(Expr::Literal(json!(3)))
+                )),
+                Box::new(Expr::Not(var("c")))
+            )
+        );
+        assert_eq!(
+            parse("a - b - c").unwrap(),
+            Expr::Binary(
+                BinaryOp::Sub,
+                Box::new(Expr::Binary(BinaryOp::Sub, var("a"), var("b"))),
+                var("c")
+            )
+        );
+    }
+
+    #[test]
+    fn test_literals_and_calls() {
+        assert_eq!(
+            parse("[1.5, 'it''s', \"x\", null, upper(a.b[0])]").unwrap(),
+            Expr::List(vec![
+                Expr::Literal(json!(1.5)),
+                Expr::Literal(json!("it's")),
+                Expr::Literal(json!("x")),
+                Expr::Literal(json!(null)),
+                Expr::Call(
+                    Function::Upper,
+                    vec![Expr::Index(
+                        Box::new(Expr::Field(var("a"), String::from("b"))),
+                        Box::new(Expr::Literal(json!(0)))
+                    )]
+                )
+            ])
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let error_offset = |expression: &str| parse(expression).unwrap_err().offset;
+        assert_eq!(error_offset("a +"), Some(3));
+        assert_eq!(error_offset("a b"), Some(2));
+        assert_eq!(error_offset("unknown(a)"), Some(0));
+        assert_eq!(error_offset("upper(a, b)"), Some(0));
+        assert_eq!(error_offset("'abc"), Some(0));
+        assert_eq!(error_offset("a # b"), Some(2));
+    }
+}
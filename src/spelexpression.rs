@@ -0,0 +1,157 @@
+use crate::{
+    expression::{CompiledExpression, ParseError, Parser},
+    interpreter::data::CompilationVarContext,
+    simpleexpression::{check_variables, SimpleExpression, SimpleParseError},
+};
+
+mod parser;
+
+///Subset of [SpEL](https://docs.spring.io/spring-framework/docs/current/reference/html/core.html#expressions), used by
+///JVM Nussknacker, so that exported scenarios can be run without rewriting expressions. It's evaluated with the same engine
+///as `simple` language. Supported are:
+///- literals: numbers, strings, true, false, null, inline lists {1, 2} and maps {key: 1, 'other': 2} ({:} is empty map)
+///- variables (#input), properties and indexing: #input.field, #input?.field, #input['field'], #input.list[0]
+///- operators: and or not && || ! == != < <= > >= (also eq ne lt le gt ge), + - * / % (div, mod), matches,
+///  ternary (a ? b : c) and elvis (a ?: b)
+///- projections #list.![#this * 2] and selections #list.?[#this > 1] (.^[...] first, .$[...] last matching element),
+///  inside them properties of current element can be referenced directly: #list.?[amount > 100].![name]
+///- methods: length, size, isEmpty, toUpperCase, toLowerCase, trim, contains, startsWith, endsWith, substring, replace, get, toString
+///
+///Differences to SpEL: missing properties (and properties of null) evaluate to null instead of failing,
+///type references T(...), constructors, assignments and bean references are not supported.
+pub struct SpelParser;
+
+impl Parser for SpelParser {
+    fn parse(
+        &self,
+        expression: &str,
+        var_context: &CompilationVarContext,
+    ) -> Result<Box<dyn CompiledExpression>, Box<dyn ParseError>> {
+        let expr = parser::parse(expression).map_err(to_parse_error)?;
+        check_variables(&expr, var_context).map_err(to_parse_error)?;
+        Ok(Box::new(SimpleExpression::new(expr, var_context)))
+    }
+}
+
+fn to_parse_error(error: SimpleParseError) -> Box<dyn ParseError> {
+    Box::new(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::{
+        expression::Parser,
+        interpreter::data::{CompilationVarContext, VarContext},
+        spelexpression::SpelParser,
+    };
+
+    fn evaluate(expression: &str, input: Value) -> Value {
+        let expr = SpelParser
+            .parse(expression, &CompilationVarContext::default())
+            .unwrap_or_else(|e| panic!("{}: {}", expression, e));
+        expr.execute(&VarContext::default_context_for_value(input))
+            .unwrap_or_else(|e| panic!("{}: {}", expression, e))
+    }
+
+    #[test]
+    fn test_operators() {
+        let input = json!({"amount": 150, "name": "Alice", "nested": null});
+        let check = |expression: &str, expected: Value| {
+            assert_eq!(
+                evaluate(expression, input.clone()),
+                expected,
+                "{}",
+                expression
+            )
+        };
+        check("#input.amount > 100", json!(true));
+        check(
+            "#input.amount gt 100 and not (#input.name eq 'Bob')",
+            json!(true),
+        );
+        check("#input.amount / 100", json!(1));
+        check("#input.amount / 100.0 + 7 % 4", json!(4.5));
+        check("#input.amount > 100 ? 'big' : 'small'", json!("big"));
+        check("#input.missing ?: 'default'", json!("default"));
+        check("#input.nested?.field", json!(null));
+        check("#input.?name", json!("Alice"));
+        check("#input.name matches 'A[a-z]+'", json!(true));
+        check("#input.name matches 'l'", json!(false));
+        check(
+            "'Message ' + #input.name.toUpperCase()",
+            json!("Message ALICE"),
+        );
+        check(
+            "#input.name.length() == 5 && #input.name.startsWith('Al')",
+            json!(true),
+        );
+    }
+
+    #[test]
+    fn test_collections() {
+        let input = json!({"items": [
+            {"name": "a", "amount": 50},
+            {"name": "b", "amount": 150},
+            {"name": "c", "amount": 250}
+        ]});
+        let check = |expression: &str, expected: Value| {
+            assert_eq!(
+                evaluate(expression, input.clone()),
+                expected,
+                "{}",
+                expression
+            )
+        };
+        check("{1,2,3}", json!([1, 2, 3]));
+        check("{}.isEmpty()", json!(true));
+        check(
+            "{key: 1, 'other key': {:}}",
+            json!({"key": 1, "other key": {}}),
+        );
+        check("{1,2,3}.![#this * 2]", json!([2, 4, 6]));
+        check("{1,2,3}.contains(2)", json!(true));
+        check("#input.items.?[amount > 100].![name]", json!(["b", "c"]));
+        check("#input.items.^[amount > 100].name", json!("b"));
+        check("#input.items.$[amount > 100].name", json!("c"));
+        check("#input.items.?[amount > 1000].size()", json!(0));
+        check("#input.items.![name.toUpperCase()]", json!(["A", "B", "C"]));
+        check("#input.items[0]['name']", json!("a"));
+        check(
+            "#input.items.![{#this.name, #input.items.size()}].get(2)",
+            json!(["c", 3]),
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let parse_error = |expression: &str| {
+            SpelParser
+                .parse(expression, &CompilationVarContext::default())
+                .err()
+                .map(|e| e.to_string())
+        };
+        assert_eq!(
+            parse_error("input.amount"),
+            Some(String::from(
+                "Unknown property input, variables should be referenced as #input at position 0"
+            ))
+        );
+        assert_eq!(
+            parse_error("#unknown > 1"),
+            Some(String::from("Unknown variable unknown"))
+        );
+        assert!(parse_error("#this > 1").is_some());
+        assert!(parse_error("#input.name matches #input.pattern").is_some());
+        assert!(parse_error("#input.name.unknownMethod()").is_some());
+        assert!(parse_error("{1, 2").is_some());
+
+        let expr = SpelParser
+            .parse("#input.?[#this > 1]", &CompilationVarContext::default())
+            .unwrap();
+        assert!(expr
+            .execute(&VarContext::default_context_for_value(json!("text")))
+            .is_err());
+    }
+}
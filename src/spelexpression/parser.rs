@@ -0,0 +1,312 @@
+use serde_json::Value;
+
+use crate::simpleexpression::{
+    ast::{BinaryOp, Expr, Function, Pattern, Selection},
+    parser::{check_arity, tokenize, Token, TokenStream},
+    SimpleParseError,
+};
+
+const SYMBOLS: &[&str] = &[
+    ".?[", ".![", ".^[", ".$[", "?.", ".?", "?:", "==", "!=", "<=", ">=", "&&", "||", "<", ">",
+    "+", "-", "*", "/", "%", "!", "(", ")", "[", "]", "{", "}", ",", ".", "#", "?", ":",
+];
+
+pub(crate) fn parse(expression: &str) -> Result<Expr, SimpleParseError> {
+    let tokens = tokenize(expression, SYMBOLS)?;
+    let mut parser = ParserState {
+        tokens: TokenStream::new(&tokens, expression),
+        element_depth: 0,
+    };
+    let result = parser.parse_expression()?;
+    parser.tokens.expect_end()?;
+    Ok(result)
+}
+
+#[derive(Clone, Copy)]
+enum Operator {
+    Binary(BinaryOp),
+    Matches,
+}
+
+//operators with their precedence, the lowest first. Word operators are case-insensitive
+const BINARY_OPERATORS: &[(&str, Operator, u8)] = &[
+    ("||", Operator::Binary(BinaryOp::Or), 1),
+    ("or", Operator::Binary(BinaryOp::Or), 1),
+    ("&&", Operator::Binary(BinaryOp::And), 2),
+    ("and", Operator::Binary(BinaryOp::And), 2),
+    ("==", Operator::Binary(BinaryOp::Eq), 3),
+    ("eq", Operator::Binary(BinaryOp::Eq), 3),
+    ("!=", Operator::Binary(BinaryOp::NotEq), 3),
+    ("ne", Operator::Binary(BinaryOp::NotEq), 3),
+    ("<", Operator::Binary(BinaryOp::Lt), 3),
+    ("lt", Operator::Binary(BinaryOp::Lt), 3),
+    ("<=", Operator::Binary(BinaryOp::LtEq), 3),
+    ("le", Operator::Binary(BinaryOp::LtEq), 3),
+    (">", Operator::Binary(BinaryOp::Gt), 3),
+    ("gt", Operator::Binary(BinaryOp::Gt), 3),
+    (">=", Operator::Binary(BinaryOp::GtEq), 3),
+    ("ge", Operator::Binary(BinaryOp::GtEq), 3),
+    ("matches", Operator::Matches, 3),
+    ("+", Operator::Binary(BinaryOp::Add), 4),
+    ("-", Operator::Binary(BinaryOp::Sub), 4),
+    ("*", Operator::Binary(BinaryOp::Mul), 5),
+    ("/", Operator::Binary(BinaryOp::TruncatingDiv), 5),
+    ("div", Operator::Binary(BinaryOp::TruncatingDiv), 5),
+    ("%", Operator::Binary(BinaryOp::Rem), 5),
+    ("mod", Operator::Binary(BinaryOp::Rem), 5),
+];
+
+fn binary_operator(token: Option<&Token>) -> Option<(Operator, u8)> {
+    let matching = |operator: &str| match token {
+        Some(Token::Symbol(symbol)) => operator == *symbol,
+        Some(Token::Identifier(word)) => operator.eq_ignore_ascii_case(word),
+        _ => false,
+    };
+    BINARY_OPERATORS
+        .iter()
+        .find(|(s, _, _)| matching(s))
+        .map(|(_, op, precedence)| (*op, *precedence))
+}
+
+///Methods which can be invoked on values, e.g. `#input.name.toUpperCase()`, with the receiver as the first argument
+fn method(name: &str) -> Option<Function> {
+    use Function::*;
+    Some(match name {
+        "toUpperCase" => Upper,
+        "toLowerCase" => Lower,
+        "length" | "size" => Length,
+        "trim" | "contains" | "startsWith" | "endsWith" | "substring" | "replace" | "toString"
+        | "isEmpty" => Function::by_name(name)?,
+        _ => return None,
+    })
+}
+
+struct ParserState<'a> {
+    tokens: TokenStream<'a>,
+    //greater than 0 inside projection or selection, where #this and bare properties are allowed
+    element_depth: usize,
+}
+
+impl<'a> ParserState<'a> {
+    //ternary and elvis operators have the lowest precedence and are right-associative
+    fn parse_expression(&mut self) -> Result<Expr, SimpleParseError> {
+        let condition = self.parse_binary(0)?;
+        if self.tokens.consume("?:") {
+            let default = self.parse_expression()?;
+            Ok(Expr::Elvis(Box::new(condition), Box::new(default)))
+        } else if self.tokens.consume("?") {
+            let when_true = self.parse_expression()?;
+            self.tokens.expect(":")?;
+            let when_false = self.parse_expression()?;
+            Ok(Expr::Conditional(
+                Box::new(condition),
+                Box::new(when_true),
+                Box::new(when_false),
+            ))
+        } else {
+            Ok(condition)
+        }
+    }
+
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, SimpleParseError> {
+        let mut left = self.parse_unary()?;
+        while let Some((op, precedence)) = binary_operator(self.tokens.peek()) {
+            if precedence <= min_precedence {
+                break;
+            }
+            self.tokens.next();
+            let offset = self.tokens.offset();
+            let right = self.parse_binary(precedence)?;
+            left = match (op, right) {
+                (Operator::Binary(op), right) => Expr::Binary(op, Box::new(left), Box::new(right)),
+                (Operator::Matches, Expr::Literal(Value::String(pattern))) => {
+                    let pattern = Pattern::new(&pattern).map_err(|error| {
+                        SimpleParseError::new(&format!("Invalid pattern: {}", error), offset)
+                    })?;
+                    Expr::Matches(Box::new(left), pattern)
+                }
+                (Operator::Matches, _) => {
+                    return Err(SimpleParseError::new(
+                        "Pattern has to be a string literal",
+                        offset,
+                    ))
+                }
+            };
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, SimpleParseError> {
+        if self.tokens.consume("!") || self.consume_word("not") {
+            Ok(Expr::Not(Box::new(self.parse_unary()?)))
+        } else if self.tokens.consume("-") {
+            Ok(Expr::Negate(Box::new(self.parse_unary()?)))
+        } else {
+            self.parse_postfix()
+        }
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, SimpleParseError> {
+        let mut result = self.parse_primary()?;
+        loop {
+            //missing properties evaluate to null anyway, so safe navigation is the same as ordinary one
+            if self.tokens.consume(".") || self.tokens.consume("?.") || self.tokens.consume(".?") {
+                let offset = self.tokens.offset();
+                match self.tokens.next() {
+                    Some(Token::Identifier(name)) => {
+                        result = self.parse_property(result, name, offset)?;
+                    }
+                    _ => return Err(SimpleParseError::new("Expected property name", offset)),
+                }
+            } else if self.tokens.consume("[") {
+                let index = self.parse_expression()?;
+                self.tokens.expect("]")?;
+                result = Expr::Index(Box::new(result), Box::new(index));
+            } else if self.tokens.consume(".![") {
+                let projection = self.parse_element_expression()?;
+                result = Expr::Projection(Box::new(result), Box::new(projection));
+            } else if let Some(selection) = self.consume_selection() {
+                let predicate = self.parse_element_expression()?;
+                result = Expr::Selection(selection, Box::new(result), Box::new(predicate));
+            } else {
+                return Ok(result);
+            }
+        }
+    }
+
+    //property or method invoked on the object
+    fn parse_property(
+        &mut self,
+        object: Expr,
+        name: &str,
+        offset: usize,
+    ) -> Result<Expr, SimpleParseError> {
+        if !self.tokens.consume("(") {
+            return Ok(Expr::Field(Box::new(object), String::from(name)));
+        }
+        let mut args = self.parse_list(")")?;
+        if name == "get" && args.len() == 1 {
+            return Ok(Expr::Index(Box::new(object), Box::new(args.remove(0))));
+        }
+        let function = method(name)
+            .ok_or_else(|| SimpleParseError::new(&format!("Unknown method {}", name), offset))?;
+        args.insert(0, object);
+        check_arity(function, name, args.len(), offset)?;
+        Ok(Expr::Call(function, args))
+    }
+
+    //expression evaluated for each element, inside .![...] or .?[...]
+    fn parse_element_expression(&mut self) -> Result<Expr, SimpleParseError> {
+        self.element_depth += 1;
+        let result = self.parse_expression();
+        self.element_depth -= 1;
+        let result = result?;
+        self.tokens.expect("]")?;
+        Ok(result)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, SimpleParseError> {
+        let offset = self.tokens.offset();
+        match self.tokens.next() {
+            Some(Token::Number(value)) => Ok(Expr::Literal(value.clone())),
+            Some(Token::Str(value)) => Ok(Expr::Literal(Value::from(value.as_str()))),
+            Some(Token::Identifier(name)) => match name.to_lowercase().as_str() {
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                "null" => Ok(Expr::Literal(Value::Null)),
+                //property (or method) of the current element
+                _ if self.element_depth > 0 => self.parse_property(Expr::Current, name, offset),
+                _ => Err(SimpleParseError::new(
+                    &format!(
+                        "Unknown property {}, variables should be referenced as #{}",
+                        name, name
+                    ),
+                    offset,
+                )),
+            },
+            Some(Token::Symbol("#")) => match self.tokens.next() {
+                Some(Token::Identifier(name)) if name == "this" => {
+                    if self.element_depth > 0 {
+                        Ok(Expr::Current)
+                    } else {
+                        Err(SimpleParseError::new(
+                            "#this can be used only in projection or selection",
+                            offset,
+                        ))
+                    }
+                }
+                Some(Token::Identifier(name)) => Ok(Expr::Variable(name.clone())),
+                _ => Err(SimpleParseError::new("Expected variable name", offset)),
+            },
+            Some(Token::Symbol("(")) => {
+                let result = self.parse_expression()?;
+                self.tokens.expect(")")?;
+                Ok(result)
+            }
+            Some(Token::Symbol("{")) => self.parse_inline(),
+            _ => Err(SimpleParseError::new("Expected value", offset)),
+        }
+    }
+
+    //inline list {1, 2} or map {key: 1, 'other key': 2}, empty map is {:}
+    fn parse_inline(&mut self) -> Result<Expr, SimpleParseError> {
+        if self.tokens.consume(":") {
+            self.tokens.expect("}")?;
+            return Ok(Expr::Map(vec![]));
+        }
+        let is_map = matches!(
+            self.tokens.peek(),
+            Some(Token::Identifier(_) | Token::Str(_))
+        ) && matches!(self.tokens.peek_at(1), Some(Token::Symbol(":")));
+        if !is_map {
+            return Ok(Expr::List(self.parse_list("}")?));
+        }
+        let mut entries = vec![];
+        loop {
+            let key = match self.tokens.next() {
+                Some(Token::Identifier(key) | Token::Str(key)) => key.clone(),
+                _ => return Err(self.tokens.error("Expected key")),
+            };
+            self.tokens.expect(":")?;
+            entries.push((key, self.parse_expression()?));
+            if self.tokens.consume("}") {
+                return Ok(Expr::Map(entries));
+            }
+            self.tokens.expect(",")?;
+        }
+    }
+
+    //comma separated expressions, until closing symbol (which is consumed)
+    fn parse_list(&mut self, closing: &str) -> Result<Vec<Expr>, SimpleParseError> {
+        let mut elements = vec![];
+        if self.tokens.consume(closing) {
+            return Ok(elements);
+        }
+        loop {
+            elements.push(self.parse_expression()?);
+            if self.tokens.consume(closing) {
+                return Ok(elements);
+            }
+            self.tokens.expect(",")?;
+        }
+    }
+
+    fn consume_selection(&mut self) -> Option<Selection> {
+        [
+            (".?[", Selection::All),
+            (".^[", Selection::First),
+            (".$[", Selection::Last),
+        ]
+        .into_iter()
+        .find(|(symbol, _)| self.tokens.consume(symbol))
+        .map(|(_, selection)| selection)
+    }
+
+    fn consume_word(&mut self, word: &str) -> bool {
+        let matching = matches!(self.tokens.peek(), Some(Token::Identifier(name)) if name.eq_ignore_ascii_case(word));
+        if matching {
+            self.tokens.next();
+        }
+        matching
+    }
+}
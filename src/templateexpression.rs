@@ -0,0 +1,365 @@
+use std::borrow::Cow;
+
+use serde_json::Value;
+
+use crate::{
+    expression::{CompiledExpression, ParseError, Parser, ResultType},
+    interpreter::data::{CompilationVarContext, ScenarioRuntimeError, VarContext, VarValue},
+    simpleexpression::{
+        ast::{to_string, Expr},
+        check_variables,
+        parser::{parse_expression, tokenize, Token, TokenStream, SYMBOLS},
+        SimpleExecutionError, SimpleParseError,
+    },
+};
+
+use self::format::{format_date, parse_date, NumberFormat};
+
+mod format;
+
+///Text with placeholders, e.g. `Dear ${input.name | upper}, you owe ${input.amount | number('#,##0.00')}`.
+///Placeholders contain expressions of `simple` language (so also nested paths, like `input.client.address[0].city`),
+///optionally followed by filters:
+///- upper, lower, trim
+///- default('text') - used if value is null or empty
+///- number('#,##0.00') - format with subset of Java DecimalFormat patterns (#, 0, grouping and decimal separator)
+///- date('dd.MM.yyyy HH:mm') - epoch millis or ISO-8601 string formatted in UTC (yyyy, MM, dd, HH, mm, ss, SSS)
+///
+///Null values are rendered as empty text, `$$` is literal `$`. The result is always a string.
+pub struct TemplateParser;
+
+impl Parser for TemplateParser {
+    fn parse(
+        &self,
+        expression: &str,
+        var_context: &CompilationVarContext,
+    ) -> Result<Box<dyn CompiledExpression>, Box<dyn ParseError>> {
+        let template = parse_template(expression).map_err(to_parse_error)?;
+        for part in &template.0 {
+            if let Part::Placeholder(placeholder) = part {
+                check_variables(&placeholder.expr, var_context)
+                    .map_err(|error| to_parse_error(shift(error, placeholder.offset)))?;
+            }
+        }
+        Ok(Box::new(template))
+    }
+}
+
+fn to_parse_error(error: SimpleParseError) -> Box<dyn ParseError> {
+    Box::new(error)
+}
+
+//errors in placeholders report offset in the placeholder, or position of the placeholder if it's not known
+fn shift(error: SimpleParseError, offset: usize) -> SimpleParseError {
+    SimpleParseError {
+        offset: Some(error.offset.unwrap_or_default() + offset),
+        ..error
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Template(Vec<Part>);
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+#[derive(Debug, PartialEq)]
+struct Placeholder {
+    expr: Expr,
+    filters: Vec<Filter>,
+    ///Position of the placeholder content in the template
+    offset: usize,
+}
+
+#[derive(Debug, PartialEq)]
+enum Filter {
+    Upper,
+    Lower,
+    Trim,
+    Default(Value),
+    Number(NumberFormat),
+    Date(String),
+}
+
+fn parse_template(template: &str) -> Result<Template, SimpleParseError> {
+    let chars: Vec<char> = template.chars().collect();
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match (chars[i], chars.get(i + 1)) {
+            ('$', Some('$')) => {
+                text.push('$');
+                i += 2;
+            }
+            ('$', Some('{')) => {
+                let start = i + 2;
+                let end = placeholder_end(&chars, start)
+                    .ok_or_else(|| SimpleParseError::new("Unclosed placeholder", i))?;
+                if !text.is_empty() {
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                }
+                let content: String = chars[start..end].iter().collect();
+                let placeholder =
+                    parse_placeholder(&content, start).map_err(|error| shift(error, start))?;
+                parts.push(Part::Placeholder(placeholder));
+                i = end + 1;
+            }
+            (c, _) => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+    Ok(Template(parts))
+}
+
+//position of closing brace, braces inside string literals are skipped
+fn placeholder_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in chars.iter().enumerate().skip(start) {
+        match (quote, c) {
+            (None, '}') => return Some(i),
+            (None, '\'' | '"') => quote = Some(*c),
+            (Some(q), c) if q == *c => quote = None,
+            _ => (),
+        }
+    }
+    None
+}
+
+fn parse_placeholder(content: &str, offset: usize) -> Result<Placeholder, SimpleParseError> {
+    let symbols = [SYMBOLS, &["|"]].concat();
+    let tokens = tokenize(content, &symbols)?;
+    let mut tokens = TokenStream::new(&tokens, content);
+    let expr = parse_expression(&mut tokens)?;
+    let mut filters = vec![];
+    while tokens.consume("|") {
+        filters.push(parse_filter(&mut tokens)?);
+    }
+    tokens.expect_end()?;
+    Ok(Placeholder {
+        expr,
+        filters,
+        offset,
+    })
+}
+
+fn parse_filter(tokens: &mut TokenStream) -> Result<Filter, SimpleParseError> {
+    let offset = tokens.offset();
+    let name = match tokens.next() {
+        Some(Token::Identifier(name)) => name,
+        _ => return Err(SimpleParseError::new("Expected filter name", offset)),
+    };
+    let args = if tokens.consume("(") {
+        tokens.list(")", parse_expression)?
+    } else {
+        vec![]
+    };
+    let error =
+        |message: &str| SimpleParseError::new(&format!("{} in filter {}", message, name), offset);
+    let args = args
+        .into_iter()
+        .map(|arg| match arg {
+            Expr::Literal(value) => Ok(value),
+            _ => Err(error("Only literal arguments are allowed")),
+        })
+        .collect::<Result<Vec<Value>, SimpleParseError>>()?;
+    let text_arg = || match args.as_slice() {
+        [Value::String(text)] => Ok(text.as_str()),
+        _ => Err(error("Expected single text argument")),
+    };
+    Ok(match name.as_str() {
+        "upper" | "lower" | "trim" if !args.is_empty() => {
+            return Err(error("Unexpected arguments"))
+        }
+        "upper" => Filter::Upper,
+        "lower" => Filter::Lower,
+        "trim" => Filter::Trim,
+        "default" => match args.as_slice() {
+            [value] => Filter::Default(value.clone()),
+            _ => return Err(error("Expected single argument")),
+        },
+        "number" => Filter::Number(NumberFormat::parse(text_arg()?).map_err(|e| error(&e))?),
+        "date" => Filter::Date(String::from(text_arg()?)),
+        _ => {
+            return Err(SimpleParseError::new(
+                &format!("Unknown filter {}", name),
+                offset,
+            ))
+        }
+    })
+}
+
+impl Filter {
+    fn apply<'a>(&'a self, value: Cow<'a, Value>) -> Result<Cow<'a, Value>, SimpleExecutionError> {
+        let wrong_argument = |function: &str, value: &Value| SimpleExecutionError::WrongArgument {
+            function: String::from(function),
+            value: value.clone(),
+        };
+        Ok(match self {
+            Filter::Upper => Cow::Owned(Value::from(render(&value).to_uppercase())),
+            Filter::Lower => Cow::Owned(Value::from(render(&value).to_lowercase())),
+            Filter::Trim => Cow::Owned(Value::from(render(&value).trim())),
+            Filter::Default(default) if render(&value).is_empty() => Cow::Borrowed(default),
+            Filter::Default(_) => value,
+            Filter::Number(_) | Filter::Date(_) if value.is_null() => value,
+            Filter::Number(format) => match value.as_f64() {
+                Some(number) => Cow::Owned(Value::from(format.format(number))),
+                None => return Err(wrong_argument("number", &value)),
+            },
+            Filter::Date(pattern) => {
+                let millis = match value.as_ref() {
+                    Value::Number(millis) => millis.as_i64(),
+                    Value::String(text) => parse_date(text),
+                    _ => None,
+                };
+                match millis {
+                    Some(millis) => Cow::Owned(Value::from(format_date(millis, pattern))),
+                    None => return Err(wrong_argument("date", &value)),
+                }
+            }
+        })
+    }
+}
+
+fn render(value: &Value) -> Cow<'_, str> {
+    match value {
+        Value::Null => Cow::Borrowed(""),
+        Value::String(text) => Cow::Borrowed(text),
+        other => Cow::Owned(to_string(other)),
+    }
+}
+
+impl CompiledExpression for Template {
+    fn execute(&self, data: &VarContext) -> Result<VarValue, ScenarioRuntimeError> {
+        let mut result = String::new();
+        for part in &self.0 {
+            match part {
+                Part::Text(text) => result.push_str(text),
+                Part::Placeholder(placeholder) => {
+                    let mut value = placeholder.expr.evaluate(data)?;
+                    for filter in &placeholder.filters {
+                        value = filter.apply(value)?;
+                    }
+                    result.push_str(&render(&value));
+                }
+            }
+        }
+        Ok(Value::String(result))
+    }
+
+    fn used_variables(&self) -> Option<Vec<String>> {
+        let mut variables = vec![];
+        for part in &self.0 {
+            if let Part::Placeholder(placeholder) = part {
+                placeholder.expr.variables(&mut variables);
+            }
+        }
+        variables.sort_unstable();
+        variables.dedup();
+        Some(variables.into_iter().map(String::from).collect())
+    }
+
+    fn result_type(&self) -> ResultType {
+        ResultType::String
+    }
+
+    fn is_constant(&self) -> bool {
+        matches!(self.used_variables(), Some(variables) if variables.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::{
+        expression::Parser,
+        interpreter::data::{CompilationVarContext, VarContext},
+        templateexpression::TemplateParser,
+    };
+
+    fn render(template: &str) -> Value {
+        let input = json!({
+            "name": " alice ",
+            "amount": 1234.5,
+            "created": 1669889700000i64,
+            "client": {"addresses": [{"city": "Warsaw"}]},
+            "empty": ""
+        });
+        TemplateParser
+            .parse(template, &CompilationVarContext::default())
+            .unwrap_or_else(|e| panic!("{}: {}", template, e))
+            .execute(&VarContext::default_context_for_value(input))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_templates() {
+        assert_eq!(
+            render(
+                "Dear ${input.name | trim | upper}, you owe ${input.amount | number('#,##0.00')}"
+            ),
+            json!("Dear ALICE, you owe 1,234.50")
+        );
+        assert_eq!(
+            render("${input.client.addresses[0].city | lower} on ${input.created | date('dd.MM.yyyy')}"),
+            json!("warsaw on 01.12.2022")
+        );
+        assert_eq!(
+            render("${input.missing}|${input.empty | default('none')}|${ length(input.client.addresses) + 1 }"),
+            json!("|none|2")
+        );
+        assert_eq!(
+            render("$${not placeholder} costs $$5, ${'}' | upper}"),
+            json!("${not placeholder} costs $5, }")
+        );
+        assert_eq!(
+            render("${'2022-12-01T10:15:00Z' | date('HH:mm')}"),
+            json!("10:15")
+        );
+        assert_eq!(render("no placeholders"), json!("no placeholders"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let parse_error = |template: &str| {
+            TemplateParser
+                .parse(template, &CompilationVarContext::default())
+                .err()
+                .map(|e| e.to_string())
+        };
+        assert_eq!(
+            parse_error("Hello ${unknown.name}"),
+            Some(String::from("Unknown variable unknown at position 8"))
+        );
+        assert_eq!(
+            parse_error("Hello ${input.name | unknown}"),
+            Some(String::from("Unknown filter unknown at position 21"))
+        );
+        assert_eq!(
+            parse_error("Hello ${input.name"),
+            Some(String::from("Unclosed placeholder at position 6"))
+        );
+        assert!(parse_error("${input | number('#.#x')}").is_some());
+        assert!(parse_error("${input | date}").is_some());
+        assert!(parse_error("${input | default(input)}").is_some());
+
+        let expr = TemplateParser
+            .parse(
+                "${input | number('#.##')}",
+                &CompilationVarContext::default(),
+            )
+            .unwrap();
+        assert!(expr
+            .execute(&VarContext::default_context_for_value(json!("text")))
+            .is_err());
+    }
+}
@@ -0,0 +1,220 @@
+//Formatting of numbers and dates, without pulling whole locale/timezone machinery - numbers use '.' and ',' separators,
+//dates are epoch millis (as in JS helpers) or ISO-8601 strings, formatted in UTC.
+
+///Subset of Java DecimalFormat patterns, e.g. `#,##0.00`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct NumberFormat {
+    grouping: bool,
+    min_integer_digits: usize,
+    min_decimals: usize,
+    max_decimals: usize,
+}
+
+impl NumberFormat {
+    pub fn parse(pattern: &str) -> Result<NumberFormat, String> {
+        if let Some(invalid) = pattern.chars().find(|c| !"#0,.".contains(*c)) {
+            return Err(format!(
+                "Unsupported character '{}' in number pattern",
+                invalid
+            ));
+        }
+        let (integer, fraction) = pattern.split_once('.').unwrap_or((pattern, ""));
+        if fraction.contains(['.', ',']) {
+            return Err(format!("Invalid number pattern {}", pattern));
+        }
+        Ok(NumberFormat {
+            grouping: integer.contains(','),
+            //like in Java, at least one digit of integer part is always printed
+            min_integer_digits: integer.matches('0').count().max(1),
+            min_decimals: fraction.matches('0').count(),
+            max_decimals: fraction.len(),
+        })
+    }
+
+    pub fn format(&self, value: f64) -> String {
+        //rounding half away from zero, avoiding binary representation problems, like helpers.roundMoney
+        let factor = 10f64.powi(self.max_decimals as i32);
+        let rounded = (value.abs() * factor + f64::EPSILON * factor).round() / factor;
+        let formatted = format!("{:.*}", self.max_decimals, rounded);
+        let (integer, fraction) = formatted.split_once('.').unwrap_or((&formatted, ""));
+
+        //optional decimal places ('#') are skipped if they are zeros
+        let decimals = fraction.trim_end_matches('0').len().max(self.min_decimals);
+        let fraction = &fraction[..decimals];
+
+        let integer = format!("{:0>width$}", integer, width = self.min_integer_digits);
+        let mut result = String::new();
+        if value < 0.0 && rounded != 0.0 {
+            result.push('-');
+        }
+        for (i, digit) in integer.chars().enumerate() {
+            if self.grouping && i > 0 && (integer.len() - i) % 3 == 0 {
+                result.push(',');
+            }
+            result.push(digit);
+        }
+        if !fraction.is_empty() {
+            result.push('.');
+            result.push_str(fraction);
+        }
+        result
+    }
+}
+
+const MILLIS_IN_DAY: i64 = 24 * 60 * 60 * 1000;
+
+///Formats epoch millis (in UTC) using yyyy, MM, dd, HH, mm, ss, SSS patterns
+pub(super) fn format_date(millis: i64, pattern: &str) -> String {
+    let (year, month, day) = civil_from_days(millis.div_euclid(MILLIS_IN_DAY));
+    let time = millis.rem_euclid(MILLIS_IN_DAY);
+    let parts = [
+        ("yyyy", format!("{:04}", year)),
+        ("MM", format!("{:02}", month)),
+        ("dd", format!("{:02}", day)),
+        ("HH", format!("{:02}", time / 3_600_000)),
+        ("mm", format!("{:02}", time / 60_000 % 60)),
+        ("ss", format!("{:02}", time / 1000 % 60)),
+        ("SSS", format!("{:03}", time % 1000)),
+    ];
+    let mut result = String::new();
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        match parts.iter().find(|(part, _)| rest.starts_with(part)) {
+            Some((part, value)) => {
+                result.push_str(value);
+                rest = &rest[part.len()..];
+            }
+            None => {
+                result.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    result
+}
+
+///Parses ISO-8601 date (e.g. '2022-12-01', '2022-12-01T10:15:00Z' or '2022-12-01T10:15:00.250+01:00'), returns epoch millis
+pub(super) fn parse_date(text: &str) -> Option<i64> {
+    //so that we can index by bytes
+    if !text.is_ascii() {
+        return None;
+    }
+    let number = |from: usize, to: usize| -> Option<i64> {
+        let part = text.get(from..to)?;
+        part.chars()
+            .all(|c| c.is_ascii_digit())
+            .then(|| part.parse().ok())?
+    };
+    let separator = |at: usize, expected: &[char]| -> Option<()> {
+        expected
+            .contains(&text.get(at..)?.chars().next()?)
+            .then_some(())
+    };
+    separator(4, &['-'])?;
+    separator(7, &['-'])?;
+    let (year, month, day) = (number(0, 4)?, number(5, 7)?, number(8, 10)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let date = days_from_civil(year, month, day) * MILLIS_IN_DAY;
+    if text.len() == 10 {
+        return Some(date);
+    }
+    separator(10, &['T', ' '])?;
+    separator(13, &[':'])?;
+    let (hours, minutes) = (number(11, 13)?, number(14, 16)?);
+    let mut position = 16;
+    let mut seconds = 0;
+    let mut millis = 0;
+    if text[position..].starts_with(':') {
+        seconds = number(17, 19)?;
+        position = 19;
+        if text[position..].starts_with('.') {
+            let digits = text[20..]
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .count();
+            millis = format!("{:0<3}", &text[20..20 + digits.min(3)])
+                .parse()
+                .ok()?;
+            position = 20 + digits;
+        }
+    }
+    if hours > 23 || minutes > 59 || seconds > 59 {
+        return None;
+    }
+    let offset_minutes = match &text[position..] {
+        "" | "Z" => 0,
+        offset if offset.len() == 6 => {
+            let sign = match offset.chars().next()? {
+                '+' => 1,
+                '-' => -1,
+                _ => return None,
+            };
+            separator(position + 3, &[':'])?;
+            sign * (number(position + 1, position + 3)? * 60 + number(position + 4, position + 6)?)
+        }
+        _ => return None,
+    };
+    Some(date + ((hours * 60 + minutes - offset_minutes) * 60 + seconds) * 1000 + millis)
+}
+
+//algorithms from http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_date, parse_date, NumberFormat};
+
+    #[test]
+    fn test_number_format() {
+        let format =
+            |pattern: &str, value: f64| NumberFormat::parse(pattern).unwrap().format(value);
+        assert_eq!(format("#,##0.00", 1234567.0), "1,234,567.00");
+        assert_eq!(format("0.00", 1.005), "1.01");
+        assert_eq!(format("0.0#", 2.0), "2.0");
+        assert_eq!(format("0.0#", 2.125), "2.13");
+        assert_eq!(format("000", 7.6), "008");
+        assert_eq!(format("#.##", -0.001), "0");
+        assert_eq!(format("#,###", -1000.0), "-1,000");
+        assert!(NumberFormat::parse("#.##%").is_err());
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2022-12-01T10:15:00Z"), Some(1669889700000));
+        assert_eq!(
+            parse_date("2022-12-01T11:15:00.25+01:00"),
+            Some(1669889700250)
+        );
+        assert_eq!(parse_date("2022-13-01"), None);
+        assert_eq!(parse_date("01.12.2022"), None);
+        assert_eq!(
+            format_date(1669889700250, "dd.MM.yyyy HH:mm:ss.SSS"),
+            "01.12.2022 10:15:00.250"
+        );
+        assert_eq!(format_date(-1, "yyyy-MM-dd HH"), "1969-12-31 23");
+    }
+}
@@ -2,8 +2,11 @@ use std::error;
 use std::path::PathBuf;
 
 use rusty_nussknacker::create_interpreter;
+use rusty_nussknacker::interpreter::compiler::Compiler;
+use rusty_nussknacker::interpreter::data::CompilationWarning;
 use rusty_nussknacker::interpreter::data::VarContext;
 use rusty_nussknacker::scenariomodel::NodeId;
+use rusty_nussknacker::validate_scenario_json;
 use serde_json::json;
 use tokio_test::block_on;
 
@@ -22,6 +25,23 @@ fn test_scenario_with_custom_node() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_scenario_with_spel() -> Result<()> {
+    let interpreter = create_interpreter(scenario("with_spel.json").as_path())?;
+
+    let input = VarContext::default_context_for_value(json!({"field2": "x", "amount": 150}));
+    let output = block_on(interpreter.run(&input))?;
+    assert_eq!(
+        output.var_in_sink(&NodeId::new("sink"), "each"),
+        vec![Some(&json!("A")), Some(&json!("B")), Some(&json!("C"))]
+    );
+    assert_eq!(
+        output.var_in_sink(&NodeId::new("sink"), "newone"),
+        vec![Some(&json!("Message x")); 3]
+    );
+    Ok(())
+}
+
 #[test]
 fn test_scenario_with_split() -> Result<()> {
     let interpreter = create_interpreter(scenario("with_split.json").as_path())?;
@@ -39,6 +59,29 @@ fn test_scenario_with_split() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_validate_scenario_json() -> Result<()> {
+    let scenario = json!({
+        "metaData": {"id": "validated"},
+        "nodes": [
+            {"type": "Source", "id": "source"},
+            {"type": "Variable", "id": "unused", "varName": "unused",
+                "value": {"language": "javascript", "expression": "input"}},
+            {"type": "Sink", "id": "sink"}
+        ]
+    });
+    let warnings = validate_scenario_json(&scenario.to_string(), &Compiler::default())?;
+    assert_eq!(
+        warnings,
+        vec![CompilationWarning::UnusedVariable {
+            node_id: NodeId::new("unused"),
+            var_name: String::from("unused")
+        }]
+    );
+    assert!(validate_scenario_json("{}", &Compiler::default()).is_err());
+    Ok(())
+}
+
 fn scenario(name: &str) -> PathBuf {
     let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     d.push("tests/scenarios");